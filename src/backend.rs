@@ -0,0 +1,137 @@
+//! The tools which can install packages.
+
+use clap::ValueEnum;
+use prebuilt::Release;
+use semver::Version;
+
+/// How a package is installed.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Backend {
+    /// Build from source with `cargo install`.
+    Source,
+    /// Download a prebuilt binary with `cargo binstall`, which builds from source if there isn't
+    /// one.
+    Binstall,
+    /// Download a prebuilt binary published by cargo-quickinstall, failing if there isn't one.
+    Quickinstall,
+    /// Download a binary the package publishes on GitHub releases, as configured in its manifest
+    /// entry.
+    GithubRelease,
+}
+
+impl Backend {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Backend::Source => "source",
+            Backend::Binstall => "binstall",
+            Backend::Quickinstall => "quickinstall",
+            Backend::GithubRelease => "github-release",
+        }
+    }
+
+    /// Parses a backend name as given in a manifest.
+    pub fn parse(name: &str) -> Result<Backend, String> {
+        Backend::from_str(name, false).map_err(|_| {
+            let names: Vec<_> = Backend::value_variants()
+                .iter()
+                .map(Backend::name)
+                .collect();
+            format!(
+                "unknown backend '{}' (expected one of {})",
+                name,
+                names.join(", ")
+            )
+        })
+    }
+}
+
+/// Where a package publishes its binaries on GitHub releases.
+///
+/// Each template may refer to `{name}` (the package), `{version}` and `{target}`, and `checksum`
+/// may also refer to `{asset}`, the expanded asset name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GithubRelease {
+    /// `owner/name` of the repository.
+    pub repo: String,
+    /// The release tag, `v{version}` by default.
+    pub tag: String,
+    /// The file name of the archive to download.
+    pub asset: String,
+    /// The file name of a file containing the archive's SHA-256, if one is published.
+    pub checksum: Option<String>,
+}
+
+impl GithubRelease {
+    /// The release of `version` of `package` built for `target`.
+    pub fn release(&self, package: &str, version: &Version, target: &str) -> Release {
+        let expand = |template: &str| {
+            template
+                .replace("{name}", package)
+                .replace("{version}", &version.to_string())
+                .replace("{target}", target)
+        };
+        let base = format!(
+            "https://github.com/{}/releases/download/{}",
+            self.repo,
+            expand(&self.tag)
+        );
+        let asset = expand(&self.asset);
+        Release {
+            version: version.clone(),
+            url: format!("{}/{}", base, asset),
+            checksum_url: self.checksum.as_ref().map(|checksum| {
+                format!("{}/{}", base, expand(checksum).replace("{asset}", &asset))
+            }),
+            source: format!("github+https://github.com/{}", self.repo),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backend, GithubRelease};
+    use semver::Version;
+
+    #[test]
+    pub fn parse() {
+        assert_eq!(Backend::parse("binstall"), Ok(Backend::Binstall));
+        assert_eq!(
+            Backend::parse("apt"),
+            Err(
+                "unknown backend 'apt' (expected one of source, binstall, quickinstall, github-release)".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    pub fn github_release() {
+        let github = GithubRelease {
+            repo: "BurntSushi/ripgrep".to_owned(),
+            tag: "{version}".to_owned(),
+            asset: "ripgrep-{version}-{target}.tar.gz".to_owned(),
+            checksum: Some("{asset}.sha256".to_owned()),
+        };
+        let release = github.release(
+            "ripgrep",
+            &Version::parse("14.1.0").unwrap(),
+            "x86_64-unknown-linux-musl",
+        );
+        assert_eq!(
+            release.url,
+            "https://github.com/BurntSushi/ripgrep/releases/download/14.1.0/\
+             ripgrep-14.1.0-x86_64-unknown-linux-musl.tar.gz"
+        );
+        assert_eq!(
+            release.checksum_url,
+            Some(
+                "https://github.com/BurntSushi/ripgrep/releases/download/14.1.0/\
+                 ripgrep-14.1.0-x86_64-unknown-linux-musl.tar.gz.sha256"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            release.source,
+            "github+https://github.com/BurntSushi/ripgrep"
+        );
+    }
+}
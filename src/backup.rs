@@ -0,0 +1,124 @@
+//! Keeping copies of a package's binaries while it is reinstalled, so that a failed install leaves
+//! the old version working rather than no version at all.
+//!
+//! The copies are kept in `.cargo-ensure-installed-backups` in the install root, next to `bin`,
+//! so that restoring them is a rename within one filesystem, which is safe even if the old binary
+//! is running.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Copies of some binaries, and where each was copied from.
+pub struct Backup {
+    dir: PathBuf,
+    copies: Vec<(PathBuf, PathBuf)>,
+}
+
+/// Copies `package`'s `bins` out of `bin_dir`. Binaries which are already missing are skipped.
+pub fn back_up(bin_dir: &Path, package: &str, bins: &[String]) -> Result<Backup, String> {
+    let dir = bin_dir
+        .with_file_name(".cargo-ensure-installed-backups")
+        .join(package);
+    // Left behind by a run which was killed; whatever it held has been replaced since.
+    if dir.exists() {
+        if let Err(err) = fs::remove_dir_all(&dir) {
+            return Err(format!("Error removing {:?}: {:?}", dir, err));
+        }
+    }
+    if let Err(err) = fs::create_dir_all(&dir) {
+        return Err(format!("Error creating {:?}: {:?}", dir, err));
+    }
+    let mut backup = Backup {
+        dir,
+        copies: vec![],
+    };
+    for bin in bins {
+        let mut original = bin_dir.join(bin);
+        if !original.is_file() {
+            original = bin_dir.join(format!("{}{}", bin, std::env::consts::EXE_SUFFIX));
+            if !original.is_file() {
+                continue;
+            }
+        }
+        let copy = backup.dir.join(original.file_name().unwrap());
+        if let Err(err) = fs::copy(&original, &copy) {
+            backup.discard();
+            return Err(format!(
+                "Error copying {:?} to {:?}: {:?}",
+                original, copy, err
+            ));
+        }
+        backup.copies.push((original, copy));
+    }
+    Ok(backup)
+}
+
+impl Backup {
+    /// Puts every binary back as it was when it was backed up.
+    pub fn restore(self) -> Result<(), String> {
+        for (original, copy) in &self.copies {
+            if let Err(err) = fs::rename(copy, original) {
+                return Err(format!(
+                    "Error restoring {:?} from {:?}: {:?}",
+                    original, copy, err
+                ));
+            }
+        }
+        self.discard();
+        Ok(())
+    }
+
+    /// Removes the copies, once they're no longer needed.
+    pub fn discard(self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::back_up;
+    use std::fs;
+
+    #[test]
+    pub fn restores_binaries() {
+        let root = std::env::temp_dir().join(format!(
+            "cargo-ensure-installed-backup-{}",
+            std::process::id()
+        ));
+        let bin_dir = root.join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        fs::write(bin_dir.join("cargo-nm"), "old nm").unwrap();
+        fs::write(bin_dir.join("cargo-size"), "old size").unwrap();
+        let bins = vec![
+            "cargo-nm".to_owned(),
+            "cargo-size".to_owned(),
+            "cargo-objdump".to_owned(),
+        ];
+
+        let backup = back_up(&bin_dir, "cargo-binutils", &bins).unwrap();
+        fs::write(bin_dir.join("cargo-nm"), "broken").unwrap();
+        fs::remove_file(bin_dir.join("cargo-size")).unwrap();
+        backup.restore().unwrap();
+        assert_eq!(
+            fs::read_to_string(bin_dir.join("cargo-nm")).unwrap(),
+            "old nm"
+        );
+        assert_eq!(
+            fs::read_to_string(bin_dir.join("cargo-size")).unwrap(),
+            "old size"
+        );
+        assert!(!bin_dir.join("cargo-objdump").exists());
+        let backups = root.join(".cargo-ensure-installed-backups");
+        assert!(!backups.join("cargo-binutils").exists());
+
+        let backup = back_up(&bin_dir, "cargo-binutils", &bins).unwrap();
+        fs::write(bin_dir.join("cargo-nm"), "new nm").unwrap();
+        backup.discard();
+        assert_eq!(
+            fs::read_to_string(bin_dir.join("cargo-nm")).unwrap(),
+            "new nm"
+        );
+        assert!(!backups.join("cargo-binutils").exists());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,156 @@
+//! Reading cargo's own configuration.
+
+use std::path::{Path, PathBuf};
+use toml::Value;
+
+/// The config files cargo reads, most specific first: `.cargo/config.toml` (or `.cargo/config`)
+/// in `cwd` and each of its ancestors, then `config.toml` (or `config`) in `cargo_home`.
+fn config_files(cargo_home: &Path, cwd: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<_> = cwd.ancestors().map(|dir| dir.join(".cargo")).collect();
+    dirs.push(cargo_home.to_owned());
+    let mut files: Vec<PathBuf> = vec![];
+    for dir in dirs {
+        let file = ["config.toml", "config"]
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file());
+        if let Some(file) = file {
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+    }
+    files
+}
+
+/// Looks up `key` (a dotted path such as `registries.internal.index`) in cargo's config files,
+/// returning the most specific value.
+fn lookup(cargo_home: &Path, cwd: &Path, key: &[&str]) -> Result<Option<Value>, String> {
+    for path in config_files(cargo_home, cwd) {
+        let contents = match ::read_file_to_string(&path) {
+            Ok(s) => s,
+            Err(err) => return Err(format!("Error reading {:?}: {:?}", path, err)),
+        };
+        let value = match contents.parse::<Value>() {
+            Ok(v) => v,
+            Err(err) => return Err(format!("Error parsing {:?}: {:?}", path, err)),
+        };
+        let found = key
+            .iter()
+            .try_fold(&value, |value, segment| value.get(*segment));
+        if let Some(found) = found {
+            return Ok(Some(found.clone()));
+        }
+    }
+    Ok(None)
+}
+
+/// The index URL of the registry called `name`, from `CARGO_REGISTRIES_<NAME>_INDEX` or the
+/// `[registries]` table of cargo's config.
+pub fn registry_index(cargo_home: &Path, cwd: &Path, name: &str) -> Result<Option<String>, String> {
+    let var = format!(
+        "CARGO_REGISTRIES_{}_INDEX",
+        name.to_uppercase().replace('-', "_")
+    );
+    if let Ok(index) = std::env::var(var) {
+        return Ok(Some(index));
+    }
+    match lookup(cargo_home, cwd, &["registries", name, "index"])? {
+        Some(Value::String(index)) => Ok(Some(index)),
+        _ => Ok(None),
+    }
+}
+
+/// `--config` arguments which make cargo take crates.io packages from `dir` instead of the network.
+/// `dir` is a local registry (an `index` directory alongside `.crate` files, as made by
+/// `cargo local-registry`) if it has an index, and otherwise the output of `cargo vendor`.
+pub fn vendored_source_args(dir: &Path) -> Vec<String> {
+    let kind = if dir.join("index").is_dir() {
+        "local-registry"
+    } else {
+        "directory"
+    };
+    let path = Value::String(dir.display().to_string());
+    vec![
+        "--config".to_owned(),
+        "source.crates-io.replace-with=\"vendored\"".to_owned(),
+        "--config".to_owned(),
+        format!("source.vendored.{}={}", kind, path),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{registry_index, vendored_source_args};
+    use std::path::PathBuf;
+
+    fn write_config(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "cargo-ensure-installed-config-{}-{}",
+            name,
+            std::process::id()
+        ));
+        for &(file, contents) in files {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    pub fn registry_indexes() {
+        let dir = write_config(
+            "registries",
+            &[
+                (
+                    "home/config.toml",
+                    "[registries.internal]\nindex = \"sparse+https://home.example.com/\"\n\
+                     [registries.other]\nindex = \"sparse+https://other.example.com/\"\n",
+                ),
+                (
+                    "project/.cargo/config.toml",
+                    "[registries.internal]\nindex = \"sparse+https://project.example.com/\"\n",
+                ),
+            ],
+        );
+        let home = dir.join("home");
+        let cwd = dir.join("project").join("sub");
+        let index = |name| registry_index(&home, &cwd, name).unwrap();
+        assert_eq!(
+            index("internal"),
+            Some("sparse+https://project.example.com/".to_owned())
+        );
+        assert_eq!(
+            index("other"),
+            Some("sparse+https://other.example.com/".to_owned())
+        );
+        assert_eq!(index("missing"), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    pub fn vendored_sources() {
+        let dir = write_config("vendored", &[("registry/index/3/a/abc", "")]);
+        assert_eq!(
+            vendored_source_args(&dir.join("registry"))[3],
+            format!(
+                "source.vendored.local-registry=\"{}\"",
+                dir.join("registry").display()
+            )
+        );
+        assert_eq!(
+            vendored_source_args(&dir.join("vendor")),
+            [
+                "--config",
+                "source.crates-io.replace-with=\"vendored\"",
+                "--config",
+                &format!(
+                    "source.vendored.directory=\"{}\"",
+                    dir.join("vendor").display()
+                ),
+            ]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,349 @@
+//! A small evaluator for `cfg`-style platform conditions, such as
+//! `all(unix, target_arch = "aarch64")`, evaluated against the host platform.
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Cfg {
+    Name(String),
+    KeyValue(String, String),
+    All(Vec<Cfg>),
+    Any(Vec<Cfg>),
+    Not(Box<Cfg>),
+}
+
+impl Cfg {
+    /// Parses a condition, optionally wrapped in `cfg(...)`.
+    pub fn parse(s: &str) -> Result<Cfg, String> {
+        let tokens = tokenize(s)?;
+        let tokens = match (tokens.first(), tokens.get(1), tokens.last()) {
+            (Some(Token::Ident(cfg)), Some(Token::LeftParen), Some(Token::RightParen))
+                if cfg == "cfg" =>
+            {
+                &tokens[2..tokens.len() - 1]
+            }
+            _ => &tokens[..],
+        };
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+        };
+        let cfg = match parser.expr() {
+            Ok(cfg) => cfg,
+            Err(err) => return Err(format!("Invalid cfg {:?}: {}", s, err)),
+        };
+        match parser.peek() {
+            Some(token) => Err(format!("Invalid cfg {:?}: unexpected {}", s, token)),
+            None => Ok(cfg),
+        }
+    }
+
+    pub fn matches(&self, platform: &Platform) -> bool {
+        match *self {
+            Cfg::Name(ref name) => platform.names.iter().any(|n| n == name),
+            Cfg::KeyValue(ref key, ref value) => {
+                platform.values.iter().any(|(k, v)| k == key && v == value)
+            }
+            Cfg::All(ref exprs) => exprs.iter().all(|e| e.matches(platform)),
+            Cfg::Any(ref exprs) => exprs.iter().any(|e| e.matches(platform)),
+            Cfg::Not(ref expr) => !expr.matches(platform),
+        }
+    }
+}
+
+impl fmt::Display for Cfg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn list(f: &mut fmt::Formatter, name: &str, exprs: &[Cfg]) -> fmt::Result {
+            write!(f, "{}(", name)?;
+            for (index, expr) in exprs.iter().enumerate() {
+                if index > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", expr)?;
+            }
+            write!(f, ")")
+        }
+        match *self {
+            Cfg::Name(ref name) => write!(f, "{}", name),
+            Cfg::KeyValue(ref key, ref value) => write!(f, "{} = {:?}", key, value),
+            Cfg::All(ref exprs) => list(f, "all", exprs),
+            Cfg::Any(ref exprs) => list(f, "any", exprs),
+            Cfg::Not(ref expr) => write!(f, "not({})", expr),
+        }
+    }
+}
+
+/// The set of cfg names and key/value pairs which hold for a platform.
+pub struct Platform {
+    names: Vec<String>,
+    values: Vec<(String, String)>,
+}
+
+impl Platform {
+    pub fn new(names: &[&str], values: &[(&str, &str)]) -> Platform {
+        Platform {
+            names: names.iter().map(|n| n.to_string()).collect(),
+            values: values
+                .iter()
+                .map(|&(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    /// The platform this binary was built for, which is the platform tools will be installed for.
+    pub fn host() -> Platform {
+        let mut names = vec![];
+        if cfg!(unix) {
+            names.push("unix");
+        }
+        if cfg!(windows) {
+            names.push("windows");
+        }
+        let pointer_width = if cfg!(target_pointer_width = "64") {
+            "64"
+        } else if cfg!(target_pointer_width = "32") {
+            "32"
+        } else {
+            "16"
+        };
+        let endian = if cfg!(target_endian = "big") {
+            "big"
+        } else {
+            "little"
+        };
+        let env = if cfg!(target_env = "gnu") {
+            "gnu"
+        } else if cfg!(target_env = "musl") {
+            "musl"
+        } else if cfg!(target_env = "msvc") {
+            "msvc"
+        } else {
+            ""
+        };
+        Platform::new(
+            &names,
+            &[
+                ("target_os", std::env::consts::OS),
+                ("target_arch", std::env::consts::ARCH),
+                ("target_family", std::env::consts::FAMILY),
+                ("target_env", env),
+                ("target_pointer_width", pointer_width),
+                ("target_endian", endian),
+            ],
+        )
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    LeftParen,
+    RightParen,
+    Comma,
+    Equals,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::Ident(ref s) => write!(f, "`{}`", s),
+            Token::Str(ref s) => write!(f, "{:?}", s),
+            Token::LeftParen => write!(f, "`(`"),
+            Token::RightParen => write!(f, "`)`"),
+            Token::Comma => write!(f, "`,`"),
+            Token::Equals => write!(f, "`=`"),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            ' ' | '\t' | '\n' => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::LeftParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::RightParen);
+            }
+            ',' => {
+                chars.next();
+                tokens.push(Token::Comma);
+            }
+            '=' => {
+                chars.next();
+                tokens.push(Token::Equals);
+            }
+            '"' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => value.push(c),
+                        None => return Err(format!("Invalid cfg {:?}: unterminated string", s)),
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut ident = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_alphanumeric() || c == '_' {
+                        ident.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(Token::Ident(ident));
+            }
+            c => return Err(format!("Invalid cfg {:?}: unexpected character {:?}", s, c)),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn expr(&mut self) -> Result<Cfg, String> {
+        let name = match self.next() {
+            Some(Token::Ident(name)) => name,
+            Some(token) => return Err(format!("unexpected {}", token)),
+            None => return Err("unexpected end of input".to_owned()),
+        };
+        match self.peek() {
+            Some(Token::Equals) => {
+                self.next();
+                match self.next() {
+                    Some(Token::Str(value)) => Ok(Cfg::KeyValue(name.clone(), value.clone())),
+                    Some(token) => Err(format!(
+                        "expected a string after `{} =` but got {}",
+                        name, token
+                    )),
+                    None => Err(format!("expected a string after `{} =`", name)),
+                }
+            }
+            Some(Token::LeftParen) => {
+                self.next();
+                let mut exprs = vec![];
+                loop {
+                    if let Some(Token::RightParen) = self.peek() {
+                        self.next();
+                        break;
+                    }
+                    exprs.push(self.expr()?);
+                    match self.next() {
+                        Some(Token::Comma) => {}
+                        Some(Token::RightParen) => break,
+                        Some(token) => {
+                            return Err(format!("expected `,` or `)` but got {}", token))
+                        }
+                        None => return Err("unexpected end of input".to_owned()),
+                    }
+                }
+                match name.as_str() {
+                    "all" => Ok(Cfg::All(exprs)),
+                    "any" => Ok(Cfg::Any(exprs)),
+                    "not" if exprs.len() == 1 => Ok(Cfg::Not(Box::new(exprs.remove(0)))),
+                    "not" => Err("not() takes exactly one argument".to_owned()),
+                    _ => Err(format!("unknown operator `{}`", name)),
+                }
+            }
+            _ => Ok(Cfg::Name(name.clone())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cfg, Platform};
+
+    fn linux_x86_64() -> Platform {
+        Platform::new(
+            &["unix"],
+            &[
+                ("target_os", "linux"),
+                ("target_arch", "x86_64"),
+                ("target_family", "unix"),
+            ],
+        )
+    }
+
+    fn matches(s: &str) -> bool {
+        Cfg::parse(s).unwrap().matches(&linux_x86_64())
+    }
+
+    #[test]
+    pub fn name() {
+        assert!(matches("unix"));
+        assert!(!matches("windows"));
+    }
+
+    #[test]
+    pub fn key_value() {
+        assert!(matches(r#"target_os = "linux""#));
+        assert!(!matches(r#"target_arch = "aarch64""#));
+    }
+
+    #[test]
+    pub fn operators() {
+        assert!(matches(r#"all(unix, target_arch = "x86_64")"#));
+        assert!(!matches(r#"all(unix, target_arch = "aarch64")"#));
+        assert!(matches(r#"any(windows, target_os = "linux")"#));
+        assert!(!matches(r#"any(windows, target_os = "macos")"#));
+        assert!(matches("not(windows)"));
+        assert!(matches(
+            r#"all(unix, not(any(target_os = "macos", windows)))"#
+        ));
+        assert!(matches("all()"));
+        assert!(!matches("any()"));
+    }
+
+    #[test]
+    pub fn cfg_wrapper() {
+        assert_eq!(
+            Cfg::parse(r#"cfg(target_os = "linux")"#).unwrap(),
+            Cfg::KeyValue("target_os".to_owned(), "linux".to_owned())
+        );
+    }
+
+    #[test]
+    pub fn display_round_trips() {
+        let s = r#"all(unix, not(target_os = "macos"), any(target_arch = "x86_64"))"#;
+        assert_eq!(Cfg::parse(s).unwrap().to_string(), s);
+    }
+
+    #[test]
+    pub fn invalid() {
+        assert!(Cfg::parse("").is_err());
+        assert!(Cfg::parse("target_os =").is_err());
+        assert!(Cfg::parse("target_os = linux").is_err());
+        assert!(Cfg::parse("nope(unix)").is_err());
+        assert!(Cfg::parse("not(unix, windows)").is_err());
+        assert!(Cfg::parse("all(unix").is_err());
+        assert!(Cfg::parse("unix windows").is_err());
+        assert!(Cfg::parse(r#"target_os = "linux"#).is_err());
+    }
+}
@@ -0,0 +1,680 @@
+//! Command line arguments.
+
+use backend::Backend;
+use clap;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use semver::VersionReq;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+/// Like cargo install but if you already have a suitable version, simply leaves it as-is.
+#[derive(Debug, Parser)]
+#[command(name = "cargo-ensure-installed", bin_name = "cargo ensure-installed")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Explain what is being done and why; repeat for more detail. (`-v` is `--version`.)
+    #[arg(long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Install into (and check) DIR instead of $CARGO_HOME.
+    #[arg(long, value_name = "DIR", global = true)]
+    pub root: Option<PathBuf>,
+
+    /// Run the cargo at PATH, rather than $CARGO or the first cargo on $PATH.
+    #[arg(long, value_name = "PATH", global = true)]
+    pub cargo_path: Option<PathBuf>,
+
+    /// Build packages with RUSTC_WRAPPER set to PROGRAM, such as sccache to share a compile
+    /// cache between builds.
+    #[arg(long, value_name = "PROGRAM", global = true)]
+    pub rustc_wrapper: Option<PathBuf>,
+
+    /// Run cargo without network access, so only packages it has already downloaded can be
+    /// installed.
+    #[arg(long, global = true)]
+    pub offline: bool,
+
+    /// Install crates.io packages from DIR, a local registry or `cargo vendor` directory, rather
+    /// than downloading them.
+    #[arg(long, value_name = "DIR", global = true)]
+    pub vendored: Option<PathBuf>,
+
+    /// Retry cargo up to N times, waiting longer each time, when it fails to reach the network.
+    #[arg(long, value_name = "N", default_value_t = 2, global = true)]
+    pub retries: u32,
+
+    /// Give up after SECS seconds when cargo is waiting for another cargo process to release its
+    /// package cache or build directory, rather than waiting as long as it takes.
+    #[arg(long, value_name = "SECS", global = true)]
+    pub lock_timeout: Option<u64>,
+
+    /// Kill cargo, and everything it started, if installing a package takes longer than SECS
+    /// seconds, rather than waiting as long as it takes.
+    #[arg(long, value_name = "SECS", global = true)]
+    pub timeout: Option<u64>,
+
+    /// How to report what was done: `json` prints a JSON document on stdout once every package has
+    /// been ensured, and `json-events` a line of JSON for each event as it happens. Either way,
+    /// everything else is printed on stderr.
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        value_enum,
+        default_value_t = MessageFormat::Human,
+        global = true
+    )]
+    pub message_format: MessageFormat,
+
+    /// Also write service messages for a CI server: `teamcity` opens a block for each package, and
+    /// reports each failure as a build problem.
+    #[arg(long, value_name = "FORMAT", value_enum, global = true)]
+    pub output: Option<Output>,
+
+    /// Log what is done, and why, to stderr as tracing events: `json` prints a JSON object for each,
+    /// with the spans it happened in, for correlating with other build telemetry.
+    #[arg(long, value_name = "FORMAT", value_enum, global = true)]
+    pub log_format: Option<LogFormat>,
+
+    /// Append everything done, and all of cargo's output, to PATH, rather than to
+    /// .cargo-ensure-installed.log in the install root.
+    #[arg(long, value_name = "PATH", global = true)]
+    pub log_file: Option<PathBuf>,
+
+    /// Only print errors.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Carry on past parts of .crates.toml which can't be understood, such as a newer format or
+    /// a malformed entry, treating them as recording nothing.
+    #[arg(long, global = true)]
+    pub lenient: bool,
+
+    /// Arguments for `ensure`, which runs when no subcommand is given.
+    #[command(flatten)]
+    pub ensure: EnsureArgs,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Install anything which is missing or doesn't satisfy its requirement (the default).
+    Ensure(EnsureArgs),
+    /// Report unsatisfied requirements without installing anything.
+    Check(RequirementArgs),
+    /// Print each requirement alongside its installed version and status.
+    List(RequirementArgs),
+    /// Print the newest versions available on crates.io for each requirement.
+    Outdated(RequirementArgs),
+    /// Uninstall required packages whose installed version no longer satisfies the requirement.
+    Prune(PruneArgs),
+    /// Ensure, then uninstall any installed package which wasn't requested.
+    Sync(SyncArgs),
+    /// Re-resolve locked versions against crates.io, then ensure.
+    Update(UpdateArgs),
+}
+
+/// Where to read requirements from, other than the command line.
+#[derive(Debug, Args)]
+pub struct SourceArgs {
+    /// TOML, JSON or YAML file listing tools to ensure are installed (may be repeated).
+    #[arg(long = "manifest", value_name = "PATH")]
+    pub manifests: Vec<PathBuf>,
+
+    /// Read packages from stdin, one `name version-req` or `name@req` per line.
+    #[arg(long)]
+    pub stdin: bool,
+
+    /// Only ensure manifest entries in this group (may be repeated).
+    #[arg(long = "group", value_name = "GROUP")]
+    pub groups: Vec<String>,
+
+    /// Lockfile pinning resolved versions (defaults to ensure-installed.lock next to the
+    /// manifest).
+    #[arg(long, value_name = "PATH")]
+    pub lockfile: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct RequirementArgs {
+    /// Name of package to install (may be repeated, paired in order with --version).
+    #[arg(short = 'p', long = "package", value_name = "NAME")]
+    pub packages: Vec<String>,
+
+    /// Version requirement to ensure is installed (accepts any valid semver).
+    #[arg(
+        short = 'v',
+        long = "version",
+        value_name = "REQ",
+        value_parser = parse_version_req
+    )]
+    pub versions: Vec<String>,
+
+    /// Packages to ensure, as `name@req` (or just `name` for any version).
+    #[arg(value_name = "NAME@REQ")]
+    pub specs: Vec<String>,
+
+    /// Features to install packages named on the command line with, or `package/feature` to
+    /// select a feature for one package (may be repeated, or comma separated).
+    #[arg(short = 'F', long, value_name = "FEATURES")]
+    pub features: Vec<String>,
+
+    /// Install packages named on the command line with all of their features.
+    #[arg(long)]
+    pub all_features: bool,
+
+    /// Install packages named on the command line without their default features.
+    #[arg(long)]
+    pub no_default_features: bool,
+
+    /// Only install this binary of the packages named on the command line (may be repeated).
+    #[arg(long = "bin", value_name = "NAME")]
+    pub bins: Vec<String>,
+
+    /// Accept an installed package whatever it was installed from, rather than only from the
+    /// git repository, path or registry it is required from.
+    #[arg(long)]
+    pub any_source: bool,
+
+    /// Also run each installed binary with `--version`, and treat it as broken unless it runs and
+    /// reports the installed version.
+    #[arg(long)]
+    pub verify: bool,
+
+    /// Treat a tool cargo didn't install, in the install root or on $PATH, as installed if its
+    /// `--version` output reports a satisfying version.
+    #[arg(long)]
+    pub detect_version: bool,
+
+    /// Don't install a tool if any copy of it on $PATH, however it was installed, reports a
+    /// satisfying version with `--version`.
+    #[arg(long)]
+    pub accept_path: bool,
+
+    /// Reinstall packages when the rustc they would be built with differs from the one they were
+    /// built with, for tools which break across toolchain updates.
+    #[arg(long)]
+    pub rebuild_on_rustc_change: bool,
+
+    /// Fail, rather than reinstalling, if an installed binary's SHA-256 differs from when it was
+    /// installed, in case it was tampered with.
+    #[arg(long)]
+    pub strict_checksums: bool,
+
+    /// Always pass --force to cargo install, even for packages which aren't installed, replacing
+    /// any binaries of the same name which other packages installed.
+    #[arg(long)]
+    pub force_always: bool,
+
+    #[command(flatten)]
+    pub sources: SourceArgs,
+}
+
+/// Options controlling how packages are installed.
+#[derive(Debug, Args)]
+pub struct InstallArgs {
+    /// Git URL to install the specified crate from.
+    #[arg(long, visible_alias = "git", value_name = "URL")]
+    pub git_url: Option<String>,
+
+    /// Local directory to install the specified crate from.
+    #[arg(long, value_name = "DIR", conflicts_with = "git_url")]
+    pub path: Option<PathBuf>,
+
+    /// Registry (from cargo's `[registries]` config) to install packages from.
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["git_url", "path"])]
+    pub registry: Option<String>,
+
+    /// Registry index URL to install packages from.
+    #[arg(long, value_name = "URL", conflicts_with_all = ["git_url", "path", "registry"])]
+    pub index: Option<String>,
+
+    /// Git branch to install the specified crate from.
+    #[arg(long, requires = "git_url", conflicts_with_all = ["tag", "git_rev"])]
+    pub branch: Option<String>,
+
+    /// Git tag to install the specified crate from.
+    #[arg(long, requires = "git_url", conflicts_with = "git_rev")]
+    pub tag: Option<String>,
+
+    /// Git sha to install the specified crate from.
+    #[arg(long, visible_alias = "rev", value_name = "SHA", requires = "git_url")]
+    pub git_rev: Option<String>,
+
+    /// Build every package with this cargo profile instead of `release`.
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Build every package for this target triple instead of the host.
+    #[arg(long, value_name = "TRIPLE")]
+    pub target: Option<String>,
+
+    /// Build every package with this rustup toolchain (`cargo +TOOLCHAIN install`).
+    #[arg(long, value_name = "TOOLCHAIN")]
+    pub toolchain: Option<String>,
+
+    /// Install every package with these backends instead of building it from source, trying each
+    /// in turn until one succeeds (comma separated).
+    #[arg(long = "backend", value_name = "BACKEND", value_delimiter = ',')]
+    pub backends: Vec<Backend>,
+
+    /// Build with at most N parallel jobs, rather than one per CPU.
+    #[arg(short, long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub jobs: Option<u32>,
+
+    /// Build every package with its own Cargo.lock.
+    #[arg(long)]
+    pub locked: bool,
+
+    /// Fail if anything is unsatisfied, rather than installing it (or uninstalling anything, for
+    /// `sync`).
+    #[arg(long)]
+    pub frozen: bool,
+
+    /// Install without asking for confirmation.
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// Print the cargo commands which would be run, without running them.
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Exit with code 10, rather than 0, if anything was (or with --dry-run, would be) installed,
+    /// upgraded or uninstalled.
+    #[arg(long)]
+    pub detailed_exit_codes: bool,
+
+    /// Write a report of what was done to each package to PATH: `junit` writes JUnit XML, with a
+    /// test case for each package. May be given more than once.
+    #[arg(long, value_name = "FORMAT=PATH", value_parser = parse_report)]
+    pub report: Vec<Report>,
+
+    /// Write how long checking and installing each package took to
+    /// cargo-ensure-installed-timings.html, or with `--timings=json`, to .json (comma separated).
+    #[arg(
+        long,
+        value_name = "FORMATS",
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "html",
+        value_delimiter = ','
+    )]
+    pub timings: Vec<TimingsFormat>,
+
+    /// Print what would change for each package, without changing anything.
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "human",
+        conflicts_with = "dry_run"
+    )]
+    pub plan: Option<PlanFormat>,
+
+    /// Extra arguments to pass to every `cargo install`, after `--`.
+    #[arg(last = true, value_name = "CARGO_INSTALL_ARGS")]
+    pub extra_args: Vec<String>,
+}
+
+/// How `--message-format` reports what was done.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum MessageFormat {
+    /// Status messages for people to read.
+    Human,
+    /// A JSON document describing each package.
+    Json,
+    /// A line of JSON for each event, as it happens, ending with the summary `json` prints.
+    JsonEvents,
+}
+
+/// Which CI server `--output` writes service messages for.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Output {
+    Teamcity,
+}
+
+/// How `--log-format` prints log events.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum LogFormat {
+    /// A line for people to read.
+    Human,
+    /// A JSON object per line.
+    Json,
+}
+
+/// How `--plan` is printed.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum PlanFormat {
+    /// An aligned table.
+    Human,
+    /// Tab-separated columns with no header, which won't change between releases.
+    Porcelain,
+}
+
+#[derive(Debug, Args)]
+pub struct EnsureArgs {
+    #[command(flatten)]
+    pub requirements: RequirementArgs,
+
+    #[command(flatten)]
+    pub install: InstallArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct PruneArgs {
+    #[command(flatten)]
+    pub requirements: RequirementArgs,
+
+    /// Print what would be uninstalled without running cargo.
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct SyncArgs {
+    #[command(flatten)]
+    pub ensure: EnsureArgs,
+
+    /// Never uninstall this package even if it isn't requested (may be repeated).
+    #[arg(long, value_name = "NAME")]
+    pub keep: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct UpdateArgs {
+    /// Packages to update (defaults to every package).
+    #[arg(value_name = "NAME")]
+    pub packages: Vec<String>,
+
+    #[command(flatten)]
+    pub sources: SourceArgs,
+
+    #[command(flatten)]
+    pub install: InstallArgs,
+}
+
+/// What `--timings` writes.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum TimingsFormat {
+    /// A page with a bar for each package.
+    Html,
+    /// A JSON document, for tools to read.
+    Json,
+}
+
+/// A report for `--report` to write.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    pub format: ReportFormat,
+    pub path: PathBuf,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReportFormat {
+    Junit,
+}
+
+fn parse_report(s: &str) -> Result<Report, String> {
+    let (format, path) = match s.split_once('=') {
+        Some((format, path)) if !path.is_empty() => (format, path),
+        _ => return Err("expected FORMAT=PATH, like junit=report.xml".to_owned()),
+    };
+    let format = match format {
+        "junit" => ReportFormat::Junit,
+        _ => {
+            return Err(format!(
+                "unknown report format '{}' (expected junit)",
+                format
+            ))
+        }
+    };
+    Ok(Report {
+        format,
+        path: PathBuf::from(path),
+    })
+}
+
+fn parse_version_req(s: &str) -> Result<String, String> {
+    match VersionReq::parse(s) {
+        Ok(_) => Ok(s.to_owned()),
+        Err(err) => Err(format!("{:?}", err)),
+    }
+}
+
+/// Parses the process arguments, dropping the subcommand name cargo passes when invoked as
+/// `cargo ensure-installed`.
+pub fn parse() -> Cli {
+    let mut args: Vec<_> = std::env::args_os().collect();
+    if args.get(1).and_then(|arg| arg.to_str()) == Some("ensure-installed") {
+        args.remove(1);
+    }
+    try_parse_from(args).unwrap_or_else(|err| err.exit())
+}
+
+/// Parses `args`, rejecting arguments for the default `ensure` given before a subcommand, which
+/// would otherwise be ignored.
+fn try_parse_from(args: Vec<OsString>) -> Result<Cli, clap::Error> {
+    let mut command = Cli::command();
+    let matches = command.try_get_matches_from_mut(args)?;
+    if let Some((subcommand, _)) = matches.subcommand() {
+        let misplaced = command.get_arguments().find(|arg| {
+            !arg.is_global_set()
+                && matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+        });
+        if let Some(arg) = misplaced {
+            let name = match arg.get_long() {
+                Some(long) => format!("--{}", long),
+                None => arg.get_id().to_string(),
+            };
+            return Err(command.error(
+                ErrorKind::ArgumentConflict,
+                format!("'{}' must come after the subcommand '{}'", name, subcommand),
+            ));
+        }
+    }
+    Cli::from_arg_matches(&matches).map_err(|err| err.format(&mut command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{try_parse_from, Cli, Command, PlanFormat, Report, ReportFormat};
+    use backend::Backend;
+    use clap::{CommandFactory, Parser};
+
+    fn try_parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        try_parse_from(
+            Some("cargo-ensure-installed")
+                .iter()
+                .chain(args)
+                .map(|arg| arg.into())
+                .collect(),
+        )
+    }
+
+    fn parse(args: &[&str]) -> Cli {
+        try_parse(args).unwrap()
+    }
+
+    #[test]
+    pub fn verify() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    pub fn backwards_compatible_flags() {
+        let cli = parse(&[
+            "-p",
+            "rustfmt",
+            "-v",
+            "0.9",
+            "-p",
+            "cargo-deny",
+            "-v",
+            "0.14",
+        ]);
+        assert!(cli.command.is_none());
+        assert_eq!(
+            cli.ensure.requirements.packages,
+            vec!["rustfmt", "cargo-deny"]
+        );
+        assert_eq!(cli.ensure.requirements.versions, vec!["0.9", "0.14"]);
+    }
+
+    #[test]
+    pub fn default_specs() {
+        let cli = parse(&["rustfmt@^1.4", "cargo-audit"]);
+        assert!(cli.command.is_none());
+        assert_eq!(
+            cli.ensure.requirements.specs,
+            vec!["rustfmt@^1.4", "cargo-audit"]
+        );
+    }
+
+    #[test]
+    pub fn subcommand() {
+        match parse(&["check", "--manifest", "tools.toml"]).command {
+            Some(Command::Check(args)) => {
+                assert_eq!(args.sources.manifests.len(), 1);
+            }
+            other => panic!("Unexpected command {:?}", other),
+        }
+        match parse(&["update", "rustfmt"]).command {
+            Some(Command::Update(args)) => assert_eq!(args.packages, vec!["rustfmt"]),
+            other => panic!("Unexpected command {:?}", other),
+        }
+    }
+
+    #[test]
+    pub fn global_flags_before_subcommand() {
+        let cli = parse(&["--quiet", "--root", "/opt/tools", "check", "rustfmt"]);
+        assert!(cli.quiet);
+        match cli.command {
+            Some(Command::Check(args)) => assert_eq!(args.specs, vec!["rustfmt"]),
+            other => panic!("Unexpected command {:?}", other),
+        }
+        let err = try_parse(&["-p", "rustfmt", "check"]).err().unwrap();
+        assert!(
+            err.to_string()
+                .contains("'--package' must come after the subcommand 'check'"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    pub fn invalid_version() {
+        assert!(
+            Cli::try_parse_from(["cargo-ensure-installed", "-p", "rustfmt", "-v", "nope"]).is_err()
+        );
+    }
+
+    #[test]
+    pub fn reports() {
+        assert_eq!(
+            parse(&["--report", "junit=target/tools.xml", "rustfmt"])
+                .ensure
+                .install
+                .report,
+            [Report {
+                format: ReportFormat::Junit,
+                path: "target/tools.xml".into()
+            }]
+        );
+        assert!(try_parse(&["--report", "junit", "rustfmt"]).is_err());
+        assert!(try_parse(&["--report", "xunit=tools.xml", "rustfmt"]).is_err());
+    }
+
+    #[test]
+    pub fn verbosity() {
+        assert_eq!(parse(&["rustfmt"]).verbose, 0);
+        assert_eq!(parse(&["--verbose", "--verbose", "rustfmt"]).verbose, 2);
+        assert_eq!(parse(&["check", "rustfmt", "--verbose"]).verbose, 1);
+        // `-v` is still short for `--version`.
+        let cli = parse(&["--verbose", "-p", "rustfmt", "-v", "1.4"]);
+        assert_eq!(cli.verbose, 1);
+        assert_eq!(cli.ensure.requirements.versions, vec!["1.4"]);
+        assert!(parse(&["sync", "-q", "rustfmt"]).quiet);
+        assert!(parse(&["-y", "rustfmt"]).ensure.install.yes);
+        assert!(Cli::try_parse_from(["cargo-ensure-installed", "--quiet", "--verbose"]).is_err());
+    }
+
+    #[test]
+    pub fn extra_args() {
+        let cli = parse(&[
+            "-p",
+            "foo",
+            "-v",
+            "1",
+            "--",
+            "--features",
+            "bar",
+            "--locked",
+        ]);
+        assert_eq!(cli.ensure.requirements.packages, vec!["foo"]);
+        assert_eq!(
+            cli.ensure.install.extra_args,
+            vec!["--features", "bar", "--locked"]
+        );
+        match parse(&["update", "rustfmt", "--", "--locked"]).command {
+            Some(Command::Update(args)) => {
+                assert_eq!(args.packages, vec!["rustfmt"]);
+                assert_eq!(args.install.extra_args, vec!["--locked"]);
+            }
+            other => panic!("Unexpected command {:?}", other),
+        }
+    }
+
+    #[test]
+    pub fn backends() {
+        assert!(parse(&["rustfmt"]).ensure.install.backends.is_empty());
+        assert_eq!(
+            parse(&["--backend", "binstall,source", "rustfmt"])
+                .ensure
+                .install
+                .backends,
+            vec![Backend::Binstall, Backend::Source]
+        );
+    }
+
+    #[test]
+    pub fn plan_format() {
+        assert_eq!(parse(&["rustfmt"]).ensure.install.plan, None);
+        assert_eq!(
+            parse(&["--plan", "rustfmt"]).ensure.install.plan,
+            Some(PlanFormat::Human)
+        );
+        assert_eq!(
+            parse(&["--plan=porcelain", "rustfmt"]).ensure.install.plan,
+            Some(PlanFormat::Porcelain)
+        );
+    }
+
+    #[test]
+    pub fn git_flags() {
+        let install = parse(&["--git", "https://example.com/rustfmt.git", "rustfmt"])
+            .ensure
+            .install;
+        assert_eq!(
+            install.git_url,
+            Some("https://example.com/rustfmt.git".to_owned())
+        );
+        assert_eq!(install.git_rev, None);
+        let install = parse(&["--git-url", "u", "--rev", "ccc979", "rustfmt"])
+            .ensure
+            .install;
+        assert_eq!(install.git_rev, Some("ccc979".to_owned()));
+
+        let invalid = |args: &[&str]| {
+            Cli::try_parse_from(Some("cargo-ensure-installed").iter().chain(args)).is_err()
+        };
+        assert!(invalid(&["--git-rev", "ccc979", "rustfmt"]));
+        assert!(invalid(&["--tag", "v1", "rustfmt"]));
+        assert!(invalid(&[
+            "--git", "u", "--tag", "v1", "--branch", "main", "rustfmt"
+        ]));
+    }
+}
@@ -0,0 +1,818 @@
+//! The implementation of each subcommand.
+
+use cfg::Platform;
+use cli::{
+    EnsureArgs, InstallArgs, MessageFormat, PlanFormat, PruneArgs, ReportFormat, RequirementArgs,
+    SourceArgs, SyncArgs, TimingsFormat, UpdateArgs,
+};
+use detect;
+use github;
+use junit::format_junit;
+use local;
+use lockfile::{Lockfile, LOCKFILE_NAME};
+use output::{self, Color};
+use plan::{format_human, format_porcelain, Step};
+use progress;
+use prompt::Confirm;
+use registry;
+use requirement::{parse_features, parse_lines, parse_requirements, Requirement};
+use semver::Version;
+use signals;
+use source::Source;
+use state::State;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use summary::{self, format_json, format_json_event, Decision, PackageSummary};
+use table::format_table;
+use teamcity;
+use timings;
+use workspace;
+use {
+    ensure_package, error_message, exit_code, installed_keys, installed_packages,
+    installed_version, package_status, prunable_version, read_crates_toml, uninstall,
+    unsatisfied_reason, Cargo, Error, Failure, Outcome, PackageStatus, EXIT_CHANGED, EXIT_FAILURE,
+    EXIT_OFFLINE, EXIT_UNSATISFIED,
+};
+
+/// The requirements to act on, and where their resolved versions are locked.
+struct Loaded {
+    requirements: Vec<Requirement>,
+    lockfile_path: Option<PathBuf>,
+    /// Every package which was asked for, including those `--group` or a `cfg` condition left
+    /// out, so that `sync` doesn't uninstall them.
+    declared: Vec<String>,
+}
+
+impl Loaded {
+    fn load_lockfile(&self) -> Result<Lockfile, String> {
+        match self.lockfile_path {
+            Some(ref path) => Lockfile::load(path),
+            None => Ok(Lockfile::default()),
+        }
+    }
+}
+
+fn load_requirements(args: &RequirementArgs) -> Result<Loaded, String> {
+    let mut requirements = parse_requirements(&args.packages, &args.versions, &args.specs)?;
+    let (features, package_features) = parse_features(&args.features);
+    for requirement in &mut requirements {
+        for feature in &features {
+            requirement.add_feature(feature);
+        }
+        requirement.all_features |= args.all_features;
+        requirement.no_default_features |= args.no_default_features;
+        requirement.bins.extend(args.bins.iter().cloned());
+    }
+    let mut loaded = load_sources(requirements, &args.sources)?;
+    for requirement in &mut loaded.requirements {
+        requirement.any_source |= args.any_source;
+        requirement.verify |= args.verify;
+        requirement.accept_path |= args.accept_path;
+        requirement.rebuild_on_rustc_change |= args.rebuild_on_rustc_change;
+        requirement.strict_checksums |= args.strict_checksums;
+        requirement.force |= args.force_always;
+        if args.detect_version && requirement.version_regex.is_none() {
+            requirement.version_regex = Some(detect::DEFAULT_VERSION_REGEX.to_owned());
+        }
+    }
+    for (package, feature) in package_features {
+        let mut found = false;
+        for requirement in &mut loaded.requirements {
+            if requirement.package == package {
+                requirement.add_feature(&feature);
+                found = true;
+            }
+        }
+        if !found {
+            return Err(format!(
+                "--features {}/{}: {} is not being ensured",
+                package, feature, package
+            ));
+        }
+    }
+    Ok(loaded)
+}
+
+/// Adds requirements from manifests, stdin, or (if nothing else was requested) Cargo metadata to
+/// `requirements`, dropping any which don't apply to this platform.
+fn load_sources(
+    mut requirements: Vec<Requirement>,
+    sources: &SourceArgs,
+) -> Result<Loaded, String> {
+    let groups = &sources.groups;
+    let mut lockfile_path = sources.lockfile.clone();
+    let mut declared: Vec<String> = requirements
+        .iter()
+        .map(|requirement| requirement.package.clone())
+        .collect();
+    for manifest in &sources.manifests {
+        if lockfile_path.is_none() {
+            let dir = manifest.parent().unwrap_or_else(|| Path::new(""));
+            lockfile_path = Some(dir.join(LOCKFILE_NAME));
+        }
+        let manifest_requirements = ::manifest::load_manifest(manifest)?;
+        declared.extend(
+            manifest_requirements
+                .iter()
+                .map(|requirement| requirement.package.clone()),
+        );
+        requirements.extend(
+            manifest_requirements
+                .into_iter()
+                .filter(|requirement| requirement.in_groups(groups)),
+        );
+    }
+    if sources.stdin {
+        let mut input = String::new();
+        if let Err(err) = std::io::stdin().read_to_string(&mut input) {
+            return Err(format!("Error reading stdin: {:?}", err));
+        }
+        let stdin_requirements = parse_lines(&input)?;
+        declared.extend(
+            stdin_requirements
+                .iter()
+                .map(|requirement| requirement.package.clone()),
+        );
+        requirements.extend(stdin_requirements);
+    }
+    let explicit_sources = !sources.manifests.is_empty() || sources.stdin;
+    if requirements.is_empty() && !explicit_sources {
+        let cwd = match std::env::current_dir() {
+            Ok(cwd) => cwd,
+            Err(err) => return Err(format!("Error finding current directory: {:?}", err)),
+        };
+        if let Some((root_dir, cargo_requirements)) = workspace::load_cargo_requirements(&cwd)? {
+            requirements = cargo_requirements;
+            if lockfile_path.is_none() {
+                lockfile_path = Some(root_dir.join(LOCKFILE_NAME));
+            }
+        }
+        if requirements.is_empty() {
+            return Err("At least one package must be specified".to_owned());
+        }
+        declared.extend(
+            requirements
+                .iter()
+                .map(|requirement| requirement.package.clone()),
+        );
+        requirements.retain(|requirement| requirement.in_groups(groups));
+    }
+
+    let platform = Platform::host();
+    requirements.retain(|requirement| match requirement.platform {
+        Some(ref condition) => condition.matches(&platform),
+        None => true,
+    });
+    Ok(Loaded {
+        requirements,
+        lockfile_path,
+        declared,
+    })
+}
+
+/// The source given on the command line. `--registry` and `--index` apply to every package, while
+/// `--git` and friends and `--path` may only be used with a single package.
+fn cli_source(
+    install: &InstallArgs,
+    requirements: &[Requirement],
+) -> Result<Option<Source>, String> {
+    if let Some(ref name) = install.registry {
+        return Ok(Some(Source::NamedRegistry(name.clone())));
+    }
+    if let Some(ref url) = install.index {
+        return Ok(Some(Source::Index(url.clone())));
+    }
+    let source = match (&install.git_url, &install.path) {
+        (Some(url), _) => Source::git(
+            url,
+            install.branch.as_deref(),
+            install.tag.as_deref(),
+            install.git_rev.as_deref(),
+        )?,
+        (None, Some(path)) => Source::Path(path.clone()),
+        (None, None) => return Ok(None),
+    };
+    if requirements.len() > 1 {
+        return Err("Cannot specify --git or --path with more than one package".to_owned());
+    }
+    Ok(Some(source))
+}
+
+/// `requirement` as it should be installed: pinned to its locked version, and with any options
+/// from the command line.
+fn to_install(
+    requirement: &Requirement,
+    lockfile: &Lockfile,
+    install: &InstallArgs,
+    source: &Option<Source>,
+) -> Requirement {
+    let mut pinned = lockfile.pin(requirement);
+    pinned.locked |= install.locked;
+    pinned.jobs = install.jobs;
+    if install.profile.is_some() {
+        pinned.profile = install.profile.clone();
+    }
+    if install.target.is_some() {
+        pinned.target = install.target.clone();
+    }
+    if install.toolchain.is_some() {
+        pinned.toolchain = install.toolchain.clone();
+    }
+    if !install.backends.is_empty() {
+        pinned.backends = install.backends.clone();
+    }
+    pinned.extra_args.extend(install.extra_args.iter().cloned());
+    if let Some(ref source) = *source {
+        pinned.source = source.clone();
+    }
+    pinned
+}
+
+/// Ensures every requirement is installed, recording what was installed in the lockfile, and
+/// returns what was done to each package.
+fn ensure_all(
+    cargo: &Cargo,
+    loaded: &Loaded,
+    mut lockfile: Lockfile,
+    install: &InstallArgs,
+) -> Result<Ensured, String> {
+    let loaded_lockfile = loaded.load_lockfile()?;
+    let source = cli_source(install, &loaded.requirements)?;
+    let crates_toml = &cargo.crates_toml;
+    if output::verbosity() >= output::VERY_VERBOSE {
+        let contents = read_crates_toml(crates_toml)?;
+        eprintln!("{:?} records:", crates_toml);
+        for key in installed_keys(crates_toml, &contents)? {
+            eprintln!("  {}", key);
+        }
+    }
+    let mut confirm = if install.yes {
+        Confirm::always()
+    } else {
+        Confirm::for_terminal()
+    };
+    let mut ensured = Ensured {
+        failures: vec![],
+        changed: false,
+        packages: vec![],
+    };
+    let packages: Vec<_> = loaded
+        .requirements
+        .iter()
+        .map(|requirement| requirement.package.clone())
+        .collect();
+    let _progress = progress::start(&packages);
+    for requirement in &loaded.requirements {
+        if signals::interrupted().is_some() {
+            break;
+        }
+        let started = Instant::now();
+        let pinned = to_install(requirement, &lockfile, install, &source);
+        let contents = read_crates_toml(crates_toml);
+        let before = contents
+            .as_ref()
+            .ok()
+            .and_then(|contents| {
+                installed_version(crates_toml, contents, &requirement.package).ok()
+            })
+            .flatten();
+        let mut summary = PackageSummary {
+            package: requirement.package.clone(),
+            requirement: Some(pinned.raw_version.clone()),
+            decision: Decision::Satisfied,
+            reason: None,
+            before: before.clone(),
+            after: before,
+            duration: Duration::default(),
+            install_duration: None,
+            backend: None,
+            error: None,
+        };
+        let failed = |summary: &mut PackageSummary, err: &Error| {
+            summary.decision = Decision::Failed;
+            summary.error = Some(error_message(err));
+            package_failure(&requirement.package, err)
+        };
+        let failure = if install.frozen {
+            match unsatisfied_reason(crates_toml, &contents?, &pinned) {
+                Ok(None) => None,
+                Ok(Some(problem)) => {
+                    summary.decision = Decision::Unsatisfied;
+                    summary.error = Some(problem.clone());
+                    Some(unsatisfied(&requirement.package, &problem))
+                }
+                Err(err) => Some(failed(&mut summary, &err)),
+            }
+        } else {
+            match ensure_package(cargo, &pinned, install.dry_run, &mut confirm) {
+                Ok(Some(outcome)) => {
+                    let color = if outcome.changed() {
+                        Color::Yellow
+                    } else {
+                        Color::Green
+                    };
+                    report(&requirement.package, color, &outcome.to_string());
+                    if outcome.changed() {
+                        ensured.changed = true;
+                        summary.decision = match outcome {
+                            Outcome::Upgraded { ref from, ref to } => {
+                                Decision::between(Some(from), to)
+                            }
+                            _ => Decision::Installed,
+                        };
+                        summary.backend = State::load(crates_toml)
+                            .ok()
+                            .and_then(|state| state.backend(&requirement.package));
+                    }
+                    summary.after = Some(outcome.version().clone());
+                    lockfile.lock(requirement, outcome.version().clone());
+                    None
+                }
+                // What would have been installed, for a dry run.
+                Ok(None) => {
+                    ensured.changed = true;
+                    summary.decision = Decision::WouldInstall;
+                    progress::finish(&requirement.package, Color::Yellow, summary.decision.name());
+                    None
+                }
+                Err(Error::Declined { .. }) => {
+                    summary.decision = Decision::Declined;
+                    summary.error = Some("install declined".to_owned());
+                    Some(unsatisfied(&requirement.package, "install declined"))
+                }
+                Err(err) => Some(failed(&mut summary, &err)),
+            }
+        };
+        if failure.is_some() {
+            match summary.decision {
+                Decision::Declined => report(&requirement.package, Color::Yellow, "declined"),
+                Decision::Unsatisfied => report(&requirement.package, Color::Red, "not satisfied"),
+                _ => report(&requirement.package, Color::Red, "failed"),
+            }
+        }
+        ensured.failures.extend(failure);
+        summary.duration = started.elapsed();
+        summary.install_duration = timings::take_install_duration(&requirement.package);
+        summary.reason = summary::take_reason(&requirement.package);
+        ensured.packages.push(summary);
+    }
+    if let Some(ref path) = loaded.lockfile_path {
+        if !install.dry_run && !install.frozen {
+            lockfile.save_if_changed(path, &loaded_lockfile)?;
+        }
+    }
+    Ok(ensured)
+}
+
+/// Says what happened to `package`, with `message` in `color`, on its progress bar if it has one,
+/// or as a status line.
+fn report(package: &str, color: Color, message: &str) {
+    if !progress::finish(package, color, message) {
+        status!("{}: {}", package, output::human_color(color, message));
+    }
+}
+
+/// What `ensure_all` did.
+struct Ensured {
+    failures: Vec<Failure>,
+    /// Whether anything was installed, upgraded or uninstalled, or would have been by a dry run.
+    changed: bool,
+    /// What was done to each package, in order.
+    packages: Vec<PackageSummary>,
+}
+
+/// `package` failing because of `error`.
+fn package_failure(package: &str, error: &Error) -> Failure {
+    Failure {
+        message: format!("{}: {}", package, error_message(error)),
+        code: exit_code(error),
+    }
+}
+
+/// `package` not being satisfied, because of `problem`, when it wasn't to be installed.
+fn unsatisfied(package: &str, problem: &str) -> Failure {
+    Failure {
+        message: format!("{}: {}", package, problem),
+        code: EXIT_UNSATISFIED,
+    }
+}
+
+/// Works out what `ensure_all` would do to each requirement, without installing anything.
+fn plan(
+    cargo: &Cargo,
+    loaded: &Loaded,
+    lockfile: &Lockfile,
+    install: &InstallArgs,
+) -> Result<Vec<Step>, String> {
+    let crates_toml = &cargo.crates_toml;
+    let source = cli_source(install, &loaded.requirements)?;
+    let contents = read_crates_toml(crates_toml)?;
+    let mut steps = vec![];
+    for requirement in &loaded.requirements {
+        let pinned = to_install(requirement, lockfile, install, &source);
+        let status = package_status(crates_toml, &contents, &pinned)?;
+        let target = match (
+            &status,
+            lockfile.locked_version(requirement),
+            &pinned.source,
+        ) {
+            (PackageStatus::Satisfied(version), _, _) | (_, Some(version), _) => {
+                Some(version.clone())
+            }
+            (_, None, Source::Registry) if !cargo.offline => {
+                let versions =
+                    registry::fetch_versions(registry::CRATES_IO_INDEX, &requirement.package)?;
+                registry::newest_matching(&versions, &requirement.version).cloned()
+            }
+            (_, None, Source::Path(dir)) => local::package_version(dir)?,
+            (_, None, _) => None,
+        };
+        steps.push(Step::ensure(
+            &requirement.package,
+            status.installed_version().cloned(),
+            &requirement.raw_version,
+            target,
+            matches!(status, PackageStatus::Satisfied(_)),
+        ));
+    }
+    Ok(steps)
+}
+
+fn print_plan(format: PlanFormat, steps: &[Step]) {
+    match format {
+        PlanFormat::Human => print!("{}", format_human(steps)),
+        PlanFormat::Porcelain => print!("{}", format_porcelain(steps)),
+    }
+}
+
+/// Fails with each of `failures`, exiting with the code they have in common, if they all have the
+/// same one.
+fn failed(what: &str, failures: Vec<Failure>) -> Result<(), Failure> {
+    if failures.is_empty() {
+        return Ok(());
+    }
+    Err(Failure {
+        message: format!(
+            "{}:\n{}",
+            output::stderr_color(
+                Color::Red,
+                &format!("Failed to {} {} packages", what, failures.len())
+            ),
+            messages(&failures)
+        ),
+        code: common_code(&failures),
+    })
+}
+
+fn messages(failures: &[Failure]) -> String {
+    let messages: Vec<_> = failures.iter().map(|f| f.message.as_str()).collect();
+    messages.join("\n")
+}
+
+fn common_code(failures: &[Failure]) -> i32 {
+    match failures.split_first() {
+        Some((first, rest)) if rest.iter().all(|f| f.code == first.code) => first.code,
+        _ => EXIT_FAILURE,
+    }
+}
+
+/// Like `failed`, but explaining that `--frozen` forbade any changes, or with its own exit code
+/// when offline, since the packages may only need downloading. With `--detailed-exit-codes`, also
+/// fails if anything changed, so that scripts can tell. First prints what was done to each package,
+/// as a table or, with `--message-format json`, as JSON, writes any `--report`s and `--timings`,
+/// and reports it to GitHub Actions or TeamCity.
+fn ensure_failed(cargo: &Cargo, install: &InstallArgs, ensured: Ensured) -> Result<(), Failure> {
+    for report in &install.report {
+        let contents = match report.format {
+            ReportFormat::Junit => format_junit(&ensured.packages),
+        };
+        if let Err(err) = fs::write(&report.path, contents) {
+            return Err(format!("Error writing report {:?}: {:?}", report.path, err).into());
+        }
+    }
+    for format in &install.timings {
+        let (path, contents) = match *format {
+            TimingsFormat::Html => (
+                "cargo-ensure-installed-timings.html",
+                timings::format_html(&ensured.packages),
+            ),
+            TimingsFormat::Json => (
+                "cargo-ensure-installed-timings.json",
+                timings::format_json(&ensured.packages),
+            ),
+        };
+        match fs::write(path, contents) {
+            Ok(()) => status!("Timings written to {}", path),
+            Err(err) => return Err(format!("Error writing timings {:?}: {:?}", path, err).into()),
+        }
+    }
+    github::report(&ensured.packages);
+    teamcity::report(&ensured.packages);
+    match output::message_format() {
+        // A table is only worth it for several packages, each of which has a status line anyway.
+        MessageFormat::Human if ensured.packages.len() > 1 => {
+            status!("\n{}", summary::format_human(&ensured.packages).trim_end());
+        }
+        MessageFormat::Human => {}
+        MessageFormat::Json => println!("{}", format_json(&ensured.packages)),
+        MessageFormat::JsonEvents => println!("{}", format_json_event(&ensured.packages)),
+    }
+    let failures = ensured.failures;
+    if failures.is_empty() {
+        if ensured.changed && install.detailed_exit_codes {
+            return Err(Failure {
+                message: String::new(),
+                code: EXIT_CHANGED,
+            });
+        }
+        return Ok(());
+    }
+    if install.frozen {
+        return Err(Failure {
+            message: format!(
+                "{}:\n{}",
+                output::stderr_color(
+                    Color::Red,
+                    &format!(
+                        "{} packages are not satisfied, and --frozen forbids changing them",
+                        failures.len()
+                    )
+                ),
+                messages(&failures)
+            ),
+            code: common_code(&failures),
+        });
+    }
+    if !cargo.offline {
+        return failed("ensure", failures);
+    }
+    Err(Failure {
+        message: format!(
+            "{}:\n{}",
+            output::stderr_color(
+                Color::Red,
+                &format!("Failed to ensure {} packages offline", failures.len())
+            ),
+            messages(&failures)
+        ),
+        code: EXIT_OFFLINE,
+    })
+}
+
+pub fn ensure(cargo: &Cargo, args: &EnsureArgs) -> Result<(), Failure> {
+    let loaded = load_requirements(&args.requirements)?;
+    let lockfile = loaded.load_lockfile()?;
+    if let Some(format) = args.install.plan {
+        print_plan(format, &plan(cargo, &loaded, &lockfile, &args.install)?);
+        return Ok(());
+    }
+    let ensured = ensure_all(cargo, &loaded, lockfile, &args.install)?;
+    ensure_failed(cargo, &args.install, ensured)
+}
+
+/// Installed packages which `sync` would uninstall.
+fn undeclared(crates_toml: &Path, loaded: &Loaded, keep: &[String]) -> Result<Vec<String>, String> {
+    let contents = read_crates_toml(crates_toml)?;
+    let mut packages = installed_packages(crates_toml, &contents)?;
+    packages.retain(|package| {
+        let declared = loaded.declared.contains(package);
+        !(declared || keep.contains(package) || package == env!("CARGO_PKG_NAME"))
+    });
+    Ok(packages)
+}
+
+pub fn sync(cargo: &Cargo, args: &SyncArgs) -> Result<(), Failure> {
+    let crates_toml = &cargo.crates_toml;
+    let loaded = load_requirements(&args.ensure.requirements)?;
+    let lockfile = loaded.load_lockfile()?;
+    if let Some(format) = args.ensure.install.plan {
+        let mut steps = plan(cargo, &loaded, &lockfile, &args.ensure.install)?;
+        let contents = read_crates_toml(crates_toml)?;
+        for package in undeclared(crates_toml, &loaded, &args.keep)? {
+            let installed = installed_version(crates_toml, &contents, &package)?;
+            steps.push(Step::uninstall(&package, installed));
+        }
+        print_plan(format, &steps);
+        return Ok(());
+    }
+    let mut ensured = ensure_all(cargo, &loaded, lockfile, &args.ensure.install)?;
+    if args.ensure.install.frozen {
+        for package in undeclared(crates_toml, &loaded, &args.keep)? {
+            ensured
+                .failures
+                .push(unsatisfied(&package, "installed, but not requested"));
+        }
+    } else if ensured.failures.is_empty() {
+        for package in undeclared(crates_toml, &loaded, &args.keep)? {
+            let started = Instant::now();
+            ensured.changed = true;
+            let contents = read_crates_toml(crates_toml)?;
+            let mut summary = PackageSummary {
+                package: package.clone(),
+                requirement: None,
+                decision: Decision::Uninstalled,
+                reason: None,
+                before: installed_version(crates_toml, &contents, &package)?,
+                after: None,
+                duration: Duration::default(),
+                install_duration: None,
+                backend: None,
+                error: None,
+            };
+            if args.ensure.install.dry_run {
+                human!("cargo uninstall {}", package);
+                summary.decision = Decision::WouldUninstall;
+            } else if let Err(err) = uninstall(cargo, &package) {
+                summary.decision = Decision::Failed;
+                summary.after = summary.before.clone();
+                summary.error = Some(error_message(&err));
+                ensured.failures.push(package_failure(&package, &err));
+            }
+            summary.duration = started.elapsed();
+            ensured.packages.push(summary);
+        }
+    }
+    ensure_failed(cargo, &args.ensure.install, ensured)
+}
+
+pub fn update(cargo: &Cargo, args: &UpdateArgs) -> Result<(), Failure> {
+    online(cargo, "update")?;
+    let loaded = load_sources(vec![], &args.sources)?;
+    if loaded.lockfile_path.is_none() {
+        return Err(
+            "update requires a lockfile: use --manifest, --lockfile, or Cargo.toml metadata"
+                .to_owned()
+                .into(),
+        );
+    }
+    let mut lockfile = loaded.load_lockfile()?;
+    let cli_source = cli_source(&args.install, &loaded.requirements)?;
+    for requirement in &loaded.requirements {
+        if !args.packages.is_empty() && !args.packages.contains(&requirement.package) {
+            continue;
+        }
+        // Only crates.io's versions are known, and another source's needn't match them.
+        let source = cli_source.as_ref().unwrap_or(&requirement.source);
+        if *source != Source::Registry {
+            status!(
+                "{}: not updating its locked version, since it comes from {} rather than \
+                 crates.io",
+                requirement.package,
+                source
+            );
+            continue;
+        }
+        let versions = registry::fetch_versions(registry::CRATES_IO_INDEX, &requirement.package)?;
+        match registry::newest_matching(&versions, &requirement.version) {
+            Some(newest) => lockfile.lock(requirement, newest.clone()),
+            None => {
+                return Err(Error::NoMatchingVersion {
+                    package: requirement.package.clone(),
+                    version_req: requirement.raw_version.clone(),
+                }
+                .into())
+            }
+        }
+    }
+    if let Some(format) = args.install.plan {
+        print_plan(format, &plan(cargo, &loaded, &lockfile, &args.install)?);
+        return Ok(());
+    }
+    let ensured = ensure_all(cargo, &loaded, lockfile, &args.install)?;
+    ensure_failed(cargo, &args.install, ensured)
+}
+
+pub fn check(cargo: &Cargo, args: &RequirementArgs) -> Result<(), Failure> {
+    let crates_toml = &cargo.crates_toml;
+    let loaded = load_requirements(args)?;
+    let lockfile = loaded.load_lockfile()?;
+    let contents = read_crates_toml(crates_toml)?;
+    let mut failures = vec![];
+    for requirement in &loaded.requirements {
+        match unsatisfied_reason(crates_toml, &contents, &lockfile.pin(requirement)) {
+            Ok(None) => {}
+            Ok(Some(problem)) => failures.push(unsatisfied(&requirement.package, &problem)),
+            Err(err) => failures.push(package_failure(&requirement.package, &err)),
+        }
+    }
+    if failures.is_empty() {
+        return Ok(());
+    }
+    Err(Failure {
+        message: format!(
+            "{} packages are not satisfied:\n{}",
+            failures.len(),
+            messages(&failures)
+        ),
+        code: common_code(&failures),
+    })
+}
+
+pub fn list(cargo: &Cargo, args: &RequirementArgs) -> Result<(), Failure> {
+    let crates_toml = &cargo.crates_toml;
+    let loaded = load_requirements(args)?;
+    let lockfile = loaded.load_lockfile()?;
+    let contents = read_crates_toml(crates_toml)?;
+    let mut rows = vec![];
+    for requirement in &loaded.requirements {
+        let pinned = lockfile.pin(requirement);
+        let status = package_status(crates_toml, &contents, &pinned)?;
+        rows.push(vec![
+            requirement.package.clone(),
+            pinned.raw_version.clone(),
+            show_version(status.installed_version()),
+            status.name().to_owned(),
+        ]);
+    }
+    print!(
+        "{}",
+        format_table(&["PACKAGE", "REQUIREMENT", "INSTALLED", "STATUS"], &rows)
+    );
+    Ok(())
+}
+
+pub fn outdated(cargo: &Cargo, args: &RequirementArgs) -> Result<(), Failure> {
+    online(cargo, "outdated")?;
+    let crates_toml = &cargo.crates_toml;
+    let loaded = load_requirements(args)?;
+    let contents = read_crates_toml(crates_toml)?;
+    let mut rows = vec![];
+    for requirement in &loaded.requirements {
+        let installed = installed_version(crates_toml, &contents, &requirement.package)?;
+        let versions = registry::fetch_versions(registry::CRATES_IO_INDEX, &requirement.package)?;
+        let newest_matching = registry::newest_matching(&versions, &requirement.version);
+        let newest = versions.iter().filter(|v| v.pre.is_empty()).max();
+        rows.push(vec![
+            requirement.package.clone(),
+            requirement.raw_version.clone(),
+            show_version(installed.as_ref()),
+            show_version(newest_matching),
+            show_version(newest),
+        ]);
+    }
+    print!(
+        "{}",
+        format_table(
+            &[
+                "PACKAGE",
+                "REQUIREMENT",
+                "INSTALLED",
+                "COMPATIBLE",
+                "LATEST"
+            ],
+            &rows
+        )
+    );
+    Ok(())
+}
+
+pub fn prune(cargo: &Cargo, args: &PruneArgs) -> Result<(), Failure> {
+    let crates_toml = &cargo.crates_toml;
+    let loaded = load_requirements(&args.requirements)?;
+    let lockfile = loaded.load_lockfile()?;
+    let contents = read_crates_toml(crates_toml)?;
+    let mut failures = vec![];
+    for requirement in &loaded.requirements {
+        let pinned = lockfile.pin(requirement);
+        if let Some(version) = prunable_version(crates_toml, &contents, &pinned)? {
+            if args.dry_run {
+                println!(
+                    "Would uninstall {} {} (want {})",
+                    requirement.package, version, pinned.raw_version
+                );
+                continue;
+            }
+            match uninstall(cargo, &requirement.package) {
+                Ok(()) => status!(
+                    "{}",
+                    output::human_color(
+                        Color::Yellow,
+                        &format!("Uninstalled {} {}", requirement.package, version)
+                    )
+                ),
+                Err(err) => failures.push(package_failure(&requirement.package, &err)),
+            }
+        }
+    }
+    failed("prune", failures)
+}
+
+/// Fails if `--offline` was given, for `subcommand`s which need to query crates.io.
+fn online(cargo: &Cargo, subcommand: &str) -> Result<(), String> {
+    if cargo.offline {
+        return Err(format!(
+            "{} queries crates.io, so can't be used with --offline",
+            subcommand
+        ));
+    }
+    Ok(())
+}
+
+fn show_version(version: Option<&Version>) -> String {
+    match version {
+        Some(version) => version.to_string(),
+        None => "-".to_owned(),
+    }
+}
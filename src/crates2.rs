@@ -0,0 +1,156 @@
+//! Reading `.crates2.json`, where newer versions of cargo record how each package was installed.
+//!
+//! Cargo keeps `.crates.toml` as the record of what is installed, and only trusts an entry in
+//! `.crates2.json` if it has the same key, since an older cargo may have replaced the package
+//! without updating it.
+
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+
+/// How one package was installed, as recorded in `.crates2.json`.
+#[derive(Debug, Default, PartialEq)]
+pub struct InstallInfo {
+    pub bins: Vec<String>,
+    pub features: Vec<String>,
+    pub all_features: bool,
+    pub no_default_features: bool,
+    pub profile: Option<String>,
+    /// The target triple the binaries were built for.
+    pub target: Option<String>,
+    /// The output of `rustc -vV` for the compiler which built the binaries.
+    pub rustc: Option<String>,
+}
+
+/// The path of `.crates2.json`, which sits next to `.crates.toml`.
+fn path_for(crates_toml: &Path) -> PathBuf {
+    crates_toml.with_file_name(".crates2.json")
+}
+
+/// Reads what `.crates2.json` next to `crates_toml` records about the install with `key`, the
+/// `"name version (source)"` key of its `.crates.toml` entry. Returns `None` if the file or the
+/// entry doesn't exist, so only `.crates.toml` can be relied on.
+pub fn install_info(crates_toml: &Path, key: &str) -> Result<Option<InstallInfo>, String> {
+    match read(crates_toml)? {
+        Some((path, contents)) => parse(&path, &contents, key),
+        None => Ok(None),
+    }
+}
+
+/// Every `"name version (source)"` key which `.crates2.json` next to `crates_toml` records, with
+/// the binaries it installed. Cargo may not have updated these, so `.crates.toml` should usually
+/// be relied on instead.
+pub fn installed(crates_toml: &Path) -> Result<Vec<(String, Vec<String>)>, String> {
+    let (path, contents) = match read(crates_toml)? {
+        Some(read) => read,
+        None => return Ok(vec![]),
+    };
+    let installs = installs(&path, &contents)?;
+    Ok(installs
+        .iter()
+        .map(|(key, install)| (key.clone(), strings(install, "bins")))
+        .collect())
+}
+
+/// The path and contents of `.crates2.json` next to `crates_toml`, if it exists.
+fn read(crates_toml: &Path) -> Result<Option<(PathBuf, String)>, String> {
+    let path = path_for(crates_toml);
+    if !path.exists() {
+        return Ok(None);
+    }
+    match ::read_file_to_string(&path) {
+        Ok(contents) => Ok(Some((path, contents))),
+        Err(err) => Err(format!("Error reading {:?}: {:?}", path, err)),
+    }
+}
+
+fn installs(path: &Path, contents: &str) -> Result<Map<String, Value>, String> {
+    let value: Value = match serde_json::from_str(contents) {
+        Ok(v) => v,
+        Err(err) => return Err(format!("Error parsing {:?}: {}", path, err)),
+    };
+    match value.get("installs") {
+        Some(Value::Object(installs)) => Ok(installs.clone()),
+        _ => Err(format!(
+            "Invalid .crates2.json file at {:?}: installs was not an object.",
+            path
+        )),
+    }
+}
+
+/// The strings in the array `key` of `install`.
+fn strings(install: &Value, key: &str) -> Vec<String> {
+    install
+        .get(key)
+        .and_then(Value::as_array)
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(|value| value.as_str().map(str::to_owned))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn parse(path: &Path, contents: &str, key: &str) -> Result<Option<InstallInfo>, String> {
+    let installs = installs(path, contents)?;
+    let install = match installs.get(key) {
+        Some(install) => install,
+        None => return Ok(None),
+    };
+    let string = |key: &str| install.get(key).and_then(Value::as_str).map(str::to_owned);
+    let strings = |key: &str| strings(install, key);
+    let boolean = |key: &str| install.get(key).and_then(Value::as_bool).unwrap_or(false);
+    Ok(Some(InstallInfo {
+        bins: strings("bins"),
+        features: strings("features"),
+        all_features: boolean("all_features"),
+        no_default_features: boolean("no_default_features"),
+        profile: string("profile"),
+        target: string("target"),
+        rustc: string("rustc"),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, InstallInfo};
+    use std::path::Path;
+
+    const CRATES2_JSON: &str = r###"{
+  "installs": {
+    "rustfmt 0.9.0 (registry+https://github.com/rust-lang/crates.io-index)": {
+      "version_req": null,
+      "bins": ["cargo-fmt", "rustfmt"],
+      "features": ["rustfmt-format-diff"],
+      "all_features": false,
+      "no_default_features": false,
+      "profile": "release",
+      "target": "x86_64-unknown-linux-gnu",
+      "rustc": "rustc 1.75.0 (82e1608df 2023-12-21)"
+    }
+  }
+}"###;
+
+    const KEY: &str = "rustfmt 0.9.0 (registry+https://github.com/rust-lang/crates.io-index)";
+
+    #[test]
+    pub fn reads_install() {
+        let path = Path::new("/home/user/.cargo/.crates2.json");
+        assert_eq!(
+            parse(path, CRATES2_JSON, KEY).unwrap(),
+            Some(InstallInfo {
+                bins: vec!["cargo-fmt".to_owned(), "rustfmt".to_owned()],
+                features: vec!["rustfmt-format-diff".to_owned()],
+                all_features: false,
+                no_default_features: false,
+                profile: Some("release".to_owned()),
+                target: Some("x86_64-unknown-linux-gnu".to_owned()),
+                rustc: Some("rustc 1.75.0 (82e1608df 2023-12-21)".to_owned()),
+            })
+        );
+        // Replaced by a cargo which didn't update .crates2.json.
+        let replaced = "rustfmt 0.10.0 (registry+https://github.com/rust-lang/crates.io-index)";
+        assert_eq!(parse(path, CRATES2_JSON, replaced).unwrap(), None);
+        assert!(parse(path, "{}", KEY).is_err());
+    }
+}
@@ -0,0 +1,344 @@
+//! Parsing `.crates.toml`, where cargo records each package it has installed.
+//!
+//! Cargo has only ever written one format: a `[v1]` table keyed by `"name version (source)"`. The
+//! format is detected before the file is parsed, so that one written by a future cargo gets a clear
+//! error rather than a confusing one, and so that parsers for new formats have somewhere to go.
+
+use error::Error;
+use semver::Version;
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use toml::value::Table;
+use toml::Value;
+
+static LENIENT: AtomicBool = AtomicBool::new(false);
+static WARNED: AtomicBool = AtomicBool::new(false);
+/// The malformed entries which have been reported, since the file is read many times in a run.
+static SKIPPED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Makes problems with `.crates.toml` which can be worked around warnings rather than errors.
+pub fn set_lenient(lenient: bool) {
+    LENIENT.store(lenient, Ordering::Relaxed);
+}
+
+pub fn lenient() -> bool {
+    LENIENT.load(Ordering::Relaxed)
+}
+
+/// Prints `message` as a warning, the first time there is one.
+fn warn_once(message: &str) {
+    if !WARNED.swap(true, Ordering::Relaxed) {
+        verbose!(0, "warning: {}", message);
+    }
+}
+
+/// The versions of the `.crates.toml` format.
+#[derive(Debug, PartialEq)]
+enum Format {
+    V1,
+    /// A newer format, named by its newest section, like `v2`.
+    Unsupported(String),
+    /// No format we recognise.
+    Unknown,
+}
+
+/// Which format `value`, a parsed `.crates.toml`, is in. A file with a `[v1]` section is read as
+/// v1 even if it has newer ones, as cargo may keep writing it for older versions of itself.
+fn detect(value: &Value) -> Format {
+    let table = match value.as_table() {
+        Some(table) => table,
+        None => return Format::Unknown,
+    };
+    if table.contains_key("v1") {
+        return Format::V1;
+    }
+    let newest = table
+        .keys()
+        .filter_map(|key| {
+            let number = key.strip_prefix('v')?.parse::<u32>().ok()?;
+            Some((number, key))
+        })
+        .max();
+    match newest {
+        Some((_, key)) => Format::Unsupported(key.clone()),
+        None => Format::Unknown,
+    }
+}
+
+/// The installs recorded in `.crates.toml` at `path`, whose contents are `contents`, keyed by
+/// `"name version (source)"` with the binaries each installed.
+pub fn installed(path: &Path, contents: &str) -> Result<Table, Error> {
+    if contents.is_empty() {
+        return Ok(Table::new());
+    }
+
+    let value = match contents.parse::<Value>() {
+        Ok(v) => v,
+        Err(err) => {
+            return Err(Error::InvalidCratesToml {
+                path: path.to_owned(),
+                message: format!("{:?}", err),
+            })
+        }
+    };
+    match detect(&value) {
+        Format::V1 => v1(path, &value, lenient()),
+        Format::Unsupported(section) => {
+            let error = Error::UnsupportedCratesToml {
+                path: path.to_owned(),
+                section,
+            };
+            if lenient() {
+                warn_once(&format!("{}; treating nothing as installed", error));
+                return Ok(Table::new());
+            }
+            Err(error)
+        }
+        Format::Unknown => {
+            // Cargo writes `[v1]` as soon as it installs anything, so a file without it, such as
+            // one only holding comments, records nothing.
+            let sections = value.as_table().map_or(0, |table| table.len());
+            if sections > 0 {
+                warn_once(&format!(
+                    "{:?} has no [v1] section; treating nothing as installed",
+                    path
+                ));
+            }
+            Ok(Table::new())
+        }
+    }
+}
+
+/// The `[v1]` table of `value`. With `lenient`, entries which are malformed are left out, with a
+/// warning, rather than failing whichever check runs into them.
+fn v1(path: &Path, value: &Value, lenient: bool) -> Result<Table, Error> {
+    let mut table = match value.get("v1").and_then(Value::as_table) {
+        Some(t) => t.clone(),
+        None => {
+            return Err(Error::InvalidCratesToml {
+                path: path.to_owned(),
+                message: "v1 was not a table.".to_owned(),
+            })
+        }
+    };
+    if lenient {
+        table.retain(|key, bins| match malformed(key, bins) {
+            Some(problem) => {
+                let mut skipped = SKIPPED.lock().unwrap_or_else(|err| err.into_inner());
+                if !skipped.contains(key) {
+                    verbose!(0, "warning: skipping {:?} in {:?}: {}", key, path, problem);
+                    skipped.push(key.clone());
+                }
+                false
+            }
+            None => true,
+        });
+    }
+    Ok(table)
+}
+
+/// What is wrong with the `[v1]` entry `key`, which should look like `"name version (source)"`
+/// and list the binaries `bins`, if anything.
+fn malformed(key: &str, bins: &Value) -> Option<String> {
+    if let Err(problem) = Key::parse(key) {
+        return Some(problem);
+    }
+    let bins_are_strings = bins
+        .as_array()
+        .is_some_and(|bins| bins.iter().all(Value::is_str));
+    if !bins_are_strings {
+        return Some("its binaries are not a list of names".to_owned());
+    }
+    None
+}
+
+/// A `.crates.toml` key, naming the package, version and source of an install.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Key {
+    pub name: String,
+    pub version: Version,
+    /// Where the package came from, like `registry+https://github.com/rust-lang/crates.io-index`.
+    pub source: String,
+}
+
+impl Key {
+    /// Parses a key like `"rustfmt 1.7.0 (registry+https://...)"`, describing what is wrong with
+    /// it if it can't. Everything between the parentheses after the version is the source, which
+    /// may itself contain spaces or parentheses, as a local path can.
+    pub fn parse(key: &str) -> Result<Key, String> {
+        let (name, rest) = match key.split_once(' ') {
+            Some(parts) => parts,
+            None if key.is_empty() => return Err("it has no package name".to_owned()),
+            None => return Err("it has no version".to_owned()),
+        };
+        if name.is_empty() {
+            return Err("it has no package name".to_owned());
+        }
+        if let Some(c) = name
+            .chars()
+            .find(|c| !(c.is_alphanumeric() || *c == '-' || *c == '_'))
+        {
+            return Err(format!(
+                "{:?} is not a valid package name, as it contains {:?}",
+                name, c
+            ));
+        }
+        let (version, source) = rest.split_once(' ').unwrap_or((rest, ""));
+        if version.is_empty() {
+            return Err("it has no version".to_owned());
+        }
+        let version = match Version::parse(version) {
+            Ok(parsed) => parsed,
+            Err(err) => return Err(format!("{:?} is not a valid version: {}", version, err)),
+        };
+        let source = match source.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+            Some(source) if !source.is_empty() => source,
+            _ => return Err("it has no source in parentheses after its version".to_owned()),
+        };
+        Ok(Key {
+            name: name.to_owned(),
+            version,
+            source: source.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} ({})", self.name, self.version, self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{detect, installed, malformed, v1, Format, Key};
+    use error::Error;
+    use semver::Version;
+    use std::path::Path;
+    use toml::Value;
+
+    #[test]
+    pub fn detects_formats() {
+        let format = |contents: &str| detect(&contents.parse::<Value>().unwrap());
+        assert_eq!(format("[v1]"), Format::V1);
+        assert_eq!(format("[v1]\n[v2]"), Format::V1);
+        assert_eq!(
+            format("[v2]\n[v10]\n[vendored]"),
+            Format::Unsupported("v10".to_owned())
+        );
+        assert_eq!(format("[installs]"), Format::Unknown);
+    }
+
+    #[test]
+    pub fn rejects_newer_formats() {
+        let path = Path::new("/path/to/.crates.toml");
+        match installed(path, "[v2]\n\"rustfmt 1.0.0\" = {}") {
+            Err(err @ Error::UnsupportedCratesToml { .. }) => assert_eq!(
+                err.to_string(),
+                "Unsupported .crates.toml format at \"/path/to/.crates.toml\": it has a [v2] \
+                 section, but only [v1] is understood"
+            ),
+            other => panic!("{:?}", other),
+        }
+        assert!(installed(path, "[v1]\n[v2]").unwrap().is_empty());
+    }
+
+    #[test]
+    pub fn missing_v1_is_empty() {
+        let path = Path::new("/path/to/.crates.toml");
+        assert!(installed(path, "# Written by hand\n").unwrap().is_empty());
+        assert!(installed(path, "\n").unwrap().is_empty());
+        assert!(installed(path, "[installs]\nrustfmt = 1")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    pub fn skips_malformed_entries() {
+        let bins = Value::Array(vec![Value::String("rustfmt".to_owned())]);
+        let source = "(registry+https://github.com/rust-lang/crates.io-index)";
+        assert_eq!(malformed(&format!("rustfmt 1.0.0 {}", source), &bins), None);
+        assert_eq!(
+            malformed(&format!("rustfmt 1.0 {}", source), &bins),
+            Some("\"1.0\" is not a valid version: Expected dot".to_owned())
+        );
+        assert_eq!(
+            malformed("rustfmt", &bins),
+            Some("it has no version".to_owned())
+        );
+        assert_eq!(
+            malformed("rustfmt 1.0.0", &bins),
+            Some("it has no source in parentheses after its version".to_owned())
+        );
+        assert_eq!(
+            malformed(&format!("rustfmt 1.0.0 {}", source), &Value::Integer(1)),
+            Some("its binaries are not a list of names".to_owned())
+        );
+
+        let path = Path::new("/path/to/.crates.toml");
+        let value = format!(
+            "[v1]\n\"rustfmt 1.0.0 {}\" = [\"rustfmt\"]\n\"cargo-deny one (local)\" = []",
+            source
+        )
+        .parse::<Value>()
+        .unwrap();
+        assert_eq!(v1(path, &value, false).unwrap().len(), 2);
+        let lenient = v1(path, &value, true).unwrap();
+        assert_eq!(
+            lenient.keys().collect::<Vec<_>>(),
+            vec![&format!("rustfmt 1.0.0 {}", source)]
+        );
+    }
+
+    #[test]
+    pub fn parses_keys() {
+        let key = |name: &str, version: &str, source: &str| Key {
+            name: name.to_owned(),
+            version: Version::parse(version).unwrap(),
+            source: source.to_owned(),
+        };
+        let registry = "registry+https://github.com/rust-lang/crates.io-index";
+        assert_eq!(
+            Key::parse(&format!("rustfmt 1.7.0 ({})", registry)),
+            Ok(key("rustfmt", "1.7.0", registry))
+        );
+        assert_eq!(
+            Key::parse("cargo-deny 0.14.0-rc.1+build.5 (sparse+https://index.crates.io/)"),
+            Ok(key(
+                "cargo-deny",
+                "0.14.0-rc.1+build.5",
+                "sparse+https://index.crates.io/"
+            ))
+        );
+        // Local paths can contain spaces and parentheses.
+        let path = "path+file:///home/me/My Projects (old)/tool";
+        let parsed = Key::parse(&format!("my_tool 0.1.0 ({})", path)).unwrap();
+        assert_eq!(parsed, key("my_tool", "0.1.0", path));
+        assert_eq!(parsed.to_string(), format!("my_tool 0.1.0 ({})", path));
+
+        assert_eq!(Key::parse(""), Err("it has no package name".to_owned()));
+        assert_eq!(
+            Key::parse(" 1.0.0 (local)"),
+            Err("it has no package name".to_owned())
+        );
+        assert_eq!(Key::parse("rustfmt"), Err("it has no version".to_owned()));
+        assert_eq!(
+            Key::parse("rust fmt 1.0.0 (local)"),
+            Err("\"fmt\" is not a valid version: Error parsing major identifier".to_owned())
+        );
+        assert_eq!(
+            Key::parse("rust/fmt 1.0.0 (local)"),
+            Err("\"rust/fmt\" is not a valid package name, as it contains '/'".to_owned())
+        );
+        assert_eq!(
+            Key::parse("rustfmt 1.0.0 local"),
+            Err("it has no source in parentheses after its version".to_owned())
+        );
+        assert_eq!(
+            Key::parse("rustfmt 1.0.0 ()"),
+            Err("it has no source in parentheses after its version".to_owned())
+        );
+    }
+}
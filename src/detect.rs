@@ -0,0 +1,176 @@
+//! Finding the versions of tools which cargo didn't install, from their own `--version` output.
+
+use regex::Regex;
+use requirement::Requirement;
+use semver::Version;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Matches the first semantic version in `--version` output, such as `1.7.0` in
+/// `rustfmt 1.7.0-stable (2024-05-12)`.
+pub const DEFAULT_VERSION_REGEX: &str = r"\d+\.\d+\.\d+(?:-[0-9A-Za-z.-]+)?(?:\+[0-9A-Za-z.-]+)?";
+
+/// Checks that `regex` can be used to find versions.
+pub fn validate_regex(regex: &str) -> Result<(), String> {
+    match Regex::new(regex) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(format!("Invalid version regex {:?}: {}", regex, err)),
+    }
+}
+
+/// A command running the binary at `path` with `--version`. Cargo subcommands are run as cargo
+/// runs them, with the subcommand's name first, since many only understand that.
+pub fn version_command(path: &Path) -> Command {
+    let mut command = Command::new(path);
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = name.trim_end_matches(std::env::consts::EXE_SUFFIX);
+    if let Some(subcommand) = name.strip_prefix("cargo-") {
+        command.arg(subcommand);
+    }
+    command.arg("--version");
+    command
+}
+
+/// The version `regex` finds in `output`: its first capture group if it has one, and otherwise
+/// everything it matched.
+pub fn parse_version(output: &str, regex: &str) -> Result<Option<Version>, String> {
+    let regex = match Regex::new(regex) {
+        Ok(regex) => regex,
+        Err(err) => return Err(format!("Invalid version regex {:?}: {}", regex, err)),
+    };
+    let captures = match regex.captures(output) {
+        Some(captures) => captures,
+        None => return Ok(None),
+    };
+    let found = captures.get(1).or_else(|| captures.get(0)).unwrap();
+    Ok(Version::parse(found.as_str()).ok())
+}
+
+/// The version the binary at `path` reports, or `None` if it can't be run or `regex` doesn't find
+/// one.
+fn reported_version(path: &Path, regex: &str) -> Result<Option<Version>, String> {
+    let output = match version_command(path).output() {
+        Ok(output) => output,
+        Err(_) => return Ok(None),
+    };
+    if !output.status.success() {
+        return Ok(None);
+    }
+    // Some tools print their version to stderr.
+    match parse_version(&String::from_utf8_lossy(&output.stdout), regex)? {
+        Some(version) => Ok(Some(version)),
+        None => parse_version(&String::from_utf8_lossy(&output.stderr), regex),
+    }
+}
+
+/// Where `requirement`'s binary might have been put without cargo: the install root's `bin_dir`,
+/// then wherever it would be run from on `search_path`, or with `--accept-path` every copy there.
+fn candidates(
+    requirement: &Requirement,
+    bin_dir: &Path,
+    search_path: Option<OsString>,
+) -> Vec<PathBuf> {
+    let bin = requirement
+        .bins
+        .first()
+        .unwrap_or(&requirement.package)
+        .clone();
+    let mut candidates = vec![bin_dir.join(&bin)];
+    candidates.retain(|path| path.is_file());
+    let found = ::find_programs(Path::new(&bin), search_path);
+    let found_count = if requirement.accept_path {
+        found.len()
+    } else {
+        1
+    };
+    for path in found.into_iter().take(found_count) {
+        if !candidates.contains(&path) {
+            candidates.push(path);
+        }
+    }
+    candidates
+}
+
+/// The version of `requirement`'s tool which was installed without cargo, if detecting one is
+/// enabled and a copy reports a version satisfying the requirement.
+pub fn detected_version(
+    requirement: &Requirement,
+    bin_dir: &Path,
+) -> Result<Option<(PathBuf, Version)>, String> {
+    let regex = match requirement.version_regex {
+        Some(ref regex) => regex.as_str(),
+        None if requirement.accept_path => DEFAULT_VERSION_REGEX,
+        None => return Ok(None),
+    };
+    for path in candidates(requirement, bin_dir, std::env::var_os("PATH")) {
+        if let Some(version) = reported_version(&path, regex)? {
+            if requirement.version.matches(&version) {
+                return Ok(Some((path, version)));
+            }
+            verbose!(
+                ::output::VERBOSE,
+                "{}: {} reports {}, which does not satisfy {}",
+                requirement.package,
+                path.display(),
+                version,
+                requirement.raw_version
+            );
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{candidates, parse_version, DEFAULT_VERSION_REGEX};
+    use requirement::Requirement;
+    use semver::Version;
+    use std::fs;
+
+    #[test]
+    pub fn parses_versions() {
+        let parse = |output: &str, regex: &str| parse_version(output, regex).unwrap();
+        assert_eq!(
+            parse("rustfmt 1.7.0-stable (2024-05-12)", DEFAULT_VERSION_REGEX),
+            Some(Version::parse("1.7.0-stable").unwrap())
+        );
+        assert_eq!(
+            parse("protoc-gen 2024.1 (built from 3.25.1)", r"from (\S+)\)"),
+            Some(Version::parse("3.25.1").unwrap())
+        );
+        assert_eq!(parse("just 1.2", DEFAULT_VERSION_REGEX), None);
+        assert!(parse_version("", "(").is_err());
+    }
+
+    #[test]
+    pub fn finds_candidates() {
+        let dir = std::env::temp_dir().join(format!(
+            "cargo-ensure-installed-candidates-{}",
+            std::process::id()
+        ));
+        for sub in &["root/bin", "usr/bin", "opt/bin"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        fs::write(dir.join("usr/bin/just"), "").unwrap();
+        fs::write(dir.join("opt/bin/just"), "").unwrap();
+        let search_path = std::env::join_paths(vec![dir.join("usr/bin"), dir.join("opt/bin")]).ok();
+        let mut requirement = Requirement::new("just", "1").unwrap();
+        let bin_dir = dir.join("root/bin");
+        assert_eq!(
+            candidates(&requirement, &bin_dir, search_path.clone()),
+            vec![dir.join("usr/bin/just")]
+        );
+        requirement.accept_path = true;
+        assert_eq!(
+            candidates(&requirement, &bin_dir, search_path.clone()),
+            vec![dir.join("usr/bin/just"), dir.join("opt/bin/just")]
+        );
+        fs::write(dir.join("root/bin/just"), "").unwrap();
+        assert_eq!(candidates(&requirement, &bin_dir, search_path).len(), 3);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,637 @@
+//! Ensuring a single package is installed, for programs using this as a library.
+
+use error::Error;
+use event::Observer;
+use installed::InstalledSource;
+use prompt::Confirm;
+use requirement::Requirement;
+use runner;
+use semver::Version;
+use std::env;
+use std::fmt;
+#[cfg(feature = "tokio")]
+use std::future::Future;
+use std::path::PathBuf;
+#[cfg(feature = "tokio")]
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+#[cfg(feature = "tokio")]
+use std::task::{Context, Poll};
+use std::time::Duration;
+use Cargo;
+
+/// How `ensure_installed` checks and installs a package. The defaults build it from crates.io into
+/// `$CARGO_HOME`, with the cargo named by `$CARGO` or else the first on `$PATH`.
+#[derive(Clone, Default)]
+pub struct Options {
+    /// The install root, if not `$CARGO_HOME`.
+    pub root: Option<PathBuf>,
+    /// The cargo to run, if not `$CARGO` or the first on `$PATH`.
+    pub cargo: Option<PathBuf>,
+    /// Features to install the package with.
+    pub features: Vec<String>,
+    pub all_features: bool,
+    pub no_default_features: bool,
+    /// Whether to build with the package's own `Cargo.lock` (`cargo install --locked`).
+    pub locked: bool,
+    /// The binaries to install, or every binary if empty.
+    pub bins: Vec<String>,
+    /// Whether cargo runs without network access.
+    pub offline: bool,
+    /// Records of what is installed to consult as well as `.crates.toml` and our state file, such
+    /// as the manifest of a container image. A package any of them records at a satisfying version
+    /// is left as-is.
+    pub installed_sources: Vec<Arc<dyn InstalledSource>>,
+    /// What to tell about progress, such as cargo's output, as it happens.
+    pub observer: Option<Arc<dyn Observer>>,
+}
+
+// By hand, as observers are usually closures, which can't be debugged.
+impl fmt::Debug for Options {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Options")
+            .field("root", &self.root)
+            .field("cargo", &self.cargo)
+            .field("features", &self.features)
+            .field("all_features", &self.all_features)
+            .field("no_default_features", &self.no_default_features)
+            .field("locked", &self.locked)
+            .field("bins", &self.bins)
+            .field("offline", &self.offline)
+            .field("installed_sources", &self.installed_sources)
+            .field("observer", &self.observer.as_ref().map(|_| "_"))
+            .finish()
+    }
+}
+
+/// What `ensure_installed` did.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    /// A satisfying version was already installed, so nothing was done.
+    AlreadySatisfied { installed_version: Version },
+    /// The package wasn't installed, and now is at `version`.
+    Installed { version: Version },
+    /// The package was installed at `from`, and has been replaced with `to`. `to` is usually newer,
+    /// but may be older if an older version was asked for, or the same if it was reinstalled.
+    Upgraded { from: Version, to: Version },
+}
+
+impl Outcome {
+    /// The version installed afterwards.
+    pub fn version(&self) -> &Version {
+        match *self {
+            Outcome::AlreadySatisfied {
+                ref installed_version,
+            } => installed_version,
+            Outcome::Installed { ref version } => version,
+            Outcome::Upgraded { ref to, .. } => to,
+        }
+    }
+
+    /// Whether anything was installed.
+    pub fn changed(&self) -> bool {
+        match *self {
+            Outcome::AlreadySatisfied { .. } => false,
+            Outcome::Installed { .. } | Outcome::Upgraded { .. } => true,
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Outcome::AlreadySatisfied {
+                ref installed_version,
+            } => write!(f, "{} is already installed", installed_version),
+            Outcome::Installed { ref version } => write!(f, "installed {}", version),
+            Outcome::Upgraded { ref from, ref to } if from == to => {
+                write!(f, "reinstalled {}", to)
+            }
+            Outcome::Upgraded { ref from, ref to } if from > to => {
+                write!(f, "downgraded from {} to {}", from, to)
+            }
+            Outcome::Upgraded { ref from, ref to } => write!(f, "upgraded from {} to {}", from, to),
+        }
+    }
+}
+
+/// Installs `package` unless a version matching `version_req`, a semver requirement like `^1.4`,
+/// is already installed as `options` ask, in which case it is left as-is.
+pub fn ensure_installed(
+    package: &str,
+    version_req: &str,
+    options: &Options,
+) -> Result<Outcome, Error> {
+    ensure_with(&cargo_for(options)?, package, version_req, options)
+}
+
+/// How to run cargo as `options` ask.
+fn cargo_for(options: &Options) -> Result<Cargo, Error> {
+    let root = match options.root {
+        Some(ref root) => root.clone(),
+        None => ::cargo_home(env::var_os("CARGO_HOME"), ::home_dir())?,
+    };
+    Ok(Cargo {
+        program: ::cargo_program(options.cargo.clone(), env::var_os("CARGO")),
+        rustc_wrapper: None,
+        offline: options.offline,
+        vendored: None,
+        retries: 2,
+        lock_timeout: None,
+        timeout: None,
+        crates_toml: root.join(".crates.toml"),
+        root: options.root.clone(),
+        env: vec![],
+        runner: Rc::new(runner::System),
+        installed_sources: options.installed_sources.clone(),
+        observer: options.observer.clone(),
+    })
+}
+
+fn ensure_with(
+    cargo: &Cargo,
+    package: &str,
+    version_req: &str,
+    options: &Options,
+) -> Result<Outcome, Error> {
+    let mut requirement = Requirement::new(package, version_req)?;
+    for feature in &options.features {
+        requirement.add_feature(feature);
+    }
+    requirement.all_features = options.all_features;
+    requirement.no_default_features = options.no_default_features;
+    requirement.locked = options.locked;
+    requirement.bins = options.bins.clone();
+    match ::ensure_package(cargo, &requirement, false, &mut Confirm::always())? {
+        Some(outcome) => Ok(outcome),
+        // Only dry runs have nothing to say.
+        None => Err(Error::Unrecorded {
+            package: package.to_owned(),
+        }),
+    }
+}
+
+/// Lists the packages being installed on behalf of build scripts, so that a package whose build
+/// script ensures the package being installed for it is caught rather than recursing forever.
+const BUILD_SCRIPT_INSTALLS: &str = "CARGO_ENSURE_INSTALLED_FOR_BUILD_SCRIPT";
+
+/// Variables cargo sets for the build script which would change how the nested `cargo install`
+/// builds, or, in the case of a shared target directory, deadlock it waiting for the lock the
+/// outer cargo holds on it.
+const BUILD_SCRIPT_ONLY: &[&str] = &[
+    "CARGO_TARGET_DIR",
+    "CARGO_BUILD_TARGET_DIR",
+    "CARGO_ENCODED_RUSTFLAGS",
+    "RUSTC_WORKSPACE_WRAPPER",
+];
+
+/// For build scripts: installs `package` unless a version matching `version_req` is already
+/// installed in `$CARGO_HOME`, and panics, failing the build, if it can't be.
+///
+/// ```no_run
+/// // build.rs
+/// cargo_ensure_installed::ensure_installed_or_panic("protoc-gen-prost", "^0.3");
+/// ```
+///
+/// Anything installed is reported to cargo with `cargo:warning=`, so it shows in the build's
+/// output. The nested install is built in its own target directory, gives up rather than waiting
+/// more than two minutes for a lock another cargo holds, and refuses to ensure a package which is
+/// already being installed for a build script further up.
+pub fn ensure_installed_or_panic(package: &str, version_req: &str) -> Outcome {
+    let installs = match nested_installs(env::var(BUILD_SCRIPT_INSTALLS).ok().as_deref(), package) {
+        Ok(installs) => installs,
+        Err(err) => panic!("{}", err),
+    };
+    let options = Options::default();
+    let result = cargo_for(&options).and_then(|mut cargo| {
+        cargo.lock_timeout = Some(Duration::from_secs(120));
+        cargo.env = BUILD_SCRIPT_ONLY
+            .iter()
+            .map(|name| (name.to_string(), None))
+            .collect();
+        cargo
+            .env
+            .push((BUILD_SCRIPT_INSTALLS.to_owned(), Some(installs.into())));
+        ensure_with(&cargo, package, version_req, &options)
+    });
+    match result {
+        Ok(outcome) => {
+            if outcome.changed() {
+                println!("cargo:warning={}: {}", package, outcome);
+            }
+            outcome
+        }
+        Err(err) => {
+            for line in err.to_string().lines() {
+                println!("cargo:warning={}", line);
+            }
+            panic!(
+                "Failed to ensure {} {} is installed: {}",
+                package, version_req, err
+            )
+        }
+    }
+}
+
+/// The packages being installed for build scripts once `package` is, given those already being
+/// installed, or an error if `package` is one of them.
+fn nested_installs(installs: Option<&str>, package: &str) -> Result<String, String> {
+    let installs: Vec<&str> = installs
+        .unwrap_or("")
+        .split(',')
+        .filter(|install| !install.is_empty())
+        .collect();
+    if installs.contains(&package) {
+        return Err(format!(
+            "{} is already being installed for a build script ({}): ensuring it again from its \
+             own build would never finish",
+            package,
+            installs.join(" -> ")
+        ));
+    }
+    Ok(installs
+        .into_iter()
+        .chain(Some(package))
+        .collect::<Vec<_>>()
+        .join(","))
+}
+
+/// Builds up how to ensure a package is installed, starting from the defaults of `Options`, for
+/// callers which would rather not write out an `Options`:
+///
+/// ```no_run
+/// # use cargo_ensure_installed::EnsureInstalled;
+/// let outcome = EnsureInstalled::new("cargo-nextest")
+///     .version_req("^0.9")
+///     .features(["self-update"])
+///     .locked(true)
+///     .run()?;
+/// # Ok::<(), cargo_ensure_installed::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct EnsureInstalled {
+    package: String,
+    version_req: String,
+    options: Options,
+}
+
+impl EnsureInstalled {
+    /// Ensures any version of `package` is installed, unless `version_req` narrows it down.
+    pub fn new(package: &str) -> EnsureInstalled {
+        EnsureInstalled {
+            package: package.to_owned(),
+            version_req: "*".to_owned(),
+            options: Options::default(),
+        }
+    }
+
+    /// The semver requirement, like `^1.4`, which the installed version must match.
+    pub fn version_req(mut self, version_req: &str) -> EnsureInstalled {
+        self.version_req = version_req.to_owned();
+        self
+    }
+
+    /// Replaces all of the options at once.
+    pub fn options(mut self, options: Options) -> EnsureInstalled {
+        self.options = options;
+        self
+    }
+
+    pub fn root<P: Into<PathBuf>>(mut self, root: P) -> EnsureInstalled {
+        self.options.root = Some(root.into());
+        self
+    }
+
+    pub fn cargo<P: Into<PathBuf>>(mut self, cargo: P) -> EnsureInstalled {
+        self.options.cargo = Some(cargo.into());
+        self
+    }
+
+    /// Adds to the features to install the package with.
+    pub fn features<I, S>(mut self, features: I) -> EnsureInstalled
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.options
+            .features
+            .extend(features.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn all_features(mut self, all_features: bool) -> EnsureInstalled {
+        self.options.all_features = all_features;
+        self
+    }
+
+    pub fn no_default_features(mut self, no_default_features: bool) -> EnsureInstalled {
+        self.options.no_default_features = no_default_features;
+        self
+    }
+
+    pub fn locked(mut self, locked: bool) -> EnsureInstalled {
+        self.options.locked = locked;
+        self
+    }
+
+    /// Adds to the binaries to install, which are otherwise all of them.
+    pub fn bins<I, S>(mut self, bins: I) -> EnsureInstalled
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.options.bins.extend(bins.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn offline(mut self, offline: bool) -> EnsureInstalled {
+        self.options.offline = offline;
+        self
+    }
+
+    /// Adds a record of what is installed to consult as well as `.crates.toml`.
+    pub fn installed_source<S: InstalledSource + 'static>(mut self, source: S) -> EnsureInstalled {
+        self.options.installed_sources.push(Arc::new(source));
+        self
+    }
+
+    /// Tells `observer` about progress as it happens.
+    pub fn observer<O: Observer + 'static>(mut self, observer: O) -> EnsureInstalled {
+        self.options.observer = Some(Arc::new(observer));
+        self
+    }
+
+    /// Installs the package unless it's already installed as asked, like `ensure_installed`.
+    pub fn run(&self) -> Result<Outcome, Error> {
+        ensure_installed(&self.package, &self.version_req, &self.options)
+    }
+
+    /// Like `run`, but on a blocking thread, like `ensure_installed_async`.
+    #[cfg(feature = "tokio")]
+    pub fn run_async(&self) -> Ensuring {
+        ensure_installed_async(&self.package, &self.version_req, &self.options)
+    }
+}
+
+/// Like `ensure_installed`, but runs on tokio's blocking thread pool, so that async callers can
+/// ensure several packages at once without blocking their worker threads. Must be called from
+/// within a tokio runtime.
+#[cfg(feature = "tokio")]
+pub fn ensure_installed_async(package: &str, version_req: &str, options: &Options) -> Ensuring {
+    let package = package.to_owned();
+    let version_req = version_req.to_owned();
+    let options = options.clone();
+    Ensuring {
+        handle: tokio::task::spawn_blocking(move || {
+            ensure_installed(&package, &version_req, &options)
+        }),
+    }
+}
+
+/// The result of `ensure_installed_async`, once it has finished.
+#[cfg(feature = "tokio")]
+pub struct Ensuring {
+    handle: tokio::task::JoinHandle<Result<Outcome, Error>>,
+}
+
+#[cfg(feature = "tokio")]
+impl Future for Ensuring {
+    type Output = Result<Outcome, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        match Pin::new(&mut self.handle).poll(cx) {
+            Poll::Ready(Ok(result)) => Poll::Ready(result),
+            Poll::Ready(Err(err)) => Poll::Ready(Err(Error::Other(format!(
+                "Error waiting for the install to finish: {}",
+                err
+            )))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ensure_installed, nested_installs, EnsureInstalled, Options, Outcome};
+    use error::Error;
+    use installed::{InstalledPackage, InstalledSource};
+    use semver::Version;
+    use std::fs;
+
+    #[test]
+    pub fn leaves_satisfied_packages() {
+        let root = std::env::temp_dir().join(format!(
+            "cargo-ensure-installed-library-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(root.join("bin")).unwrap();
+        fs::write(root.join("bin/rustfmt"), "").unwrap();
+        fs::write(
+            root.join(".crates.toml"),
+            "[v1]\n\"rustfmt 1.4.2 (registry+https://github.com/rust-lang/crates.io-index)\" = \
+             [\"rustfmt\"]\n",
+        )
+        .unwrap();
+        let options = Options {
+            root: Some(root.clone()),
+            // Would fail if it were run.
+            cargo: Some(root.join("no-such-cargo")),
+            ..Options::default()
+        };
+        assert_eq!(
+            ensure_installed("rustfmt", "^1.4", &options).unwrap(),
+            Outcome::AlreadySatisfied {
+                installed_version: Version::parse("1.4.2").unwrap()
+            }
+        );
+        assert_eq!(
+            EnsureInstalled::new("rustfmt")
+                .root(&root)
+                .cargo(root.join("no-such-cargo"))
+                .run()
+                .unwrap(),
+            Outcome::AlreadySatisfied {
+                installed_version: Version::parse("1.4.2").unwrap()
+            }
+        );
+        match ensure_installed("rustfmt", "1.4 or so", &options) {
+            Err(Error::InvalidVersionReq { version_req, .. }) => {
+                assert_eq!(version_req, "1.4 or so")
+            }
+            other => panic!("{:?}", other),
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    pub fn ensures_concurrently() {
+        use super::ensure_installed_async;
+
+        let root = std::env::temp_dir().join(format!(
+            "cargo-ensure-installed-async-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(root.join("bin")).unwrap();
+        fs::write(root.join("bin/rustfmt"), "").unwrap();
+        fs::write(root.join("bin/cargo-deny"), "").unwrap();
+        fs::write(
+            root.join(".crates.toml"),
+            "[v1]\n\
+             \"cargo-deny 0.14.3 (registry+https://github.com/rust-lang/crates.io-index)\" = \
+             [\"cargo-deny\"]\n\
+             \"rustfmt 1.4.2 (registry+https://github.com/rust-lang/crates.io-index)\" = \
+             [\"rustfmt\"]\n",
+        )
+        .unwrap();
+        let options = Options {
+            root: Some(root.clone()),
+            cargo: Some(root.join("no-such-cargo")),
+            ..Options::default()
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let _runtime = runtime.enter();
+        // Both start running before either is waited for.
+        let rustfmt = ensure_installed_async("rustfmt", "^1.4", &options);
+        let cargo_deny = EnsureInstalled::new("cargo-deny")
+            .version_req("^0.14")
+            .options(options.clone())
+            .run_async();
+        assert_eq!(
+            runtime.block_on(cargo_deny).unwrap().version(),
+            &Version::parse("0.14.3").unwrap()
+        );
+        assert_eq!(
+            runtime.block_on(rustfmt).unwrap().version(),
+            &Version::parse("1.4.2").unwrap()
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    /// Says `cargo-deny 0.14.3` is installed, as an image's manifest might.
+    #[derive(Debug)]
+    struct ImageManifest;
+
+    impl InstalledSource for ImageManifest {
+        fn describe(&self) -> String {
+            "the image manifest".to_owned()
+        }
+
+        fn installed(&self) -> Result<Vec<InstalledPackage>, Error> {
+            Ok(vec![InstalledPackage {
+                name: "cargo-deny".to_owned(),
+                version: Version::parse("0.14.3").unwrap(),
+                source: "registry+https://github.com/rust-lang/crates.io-index".to_owned(),
+                bins: vec!["cargo-deny".to_owned()],
+            }])
+        }
+    }
+
+    #[test]
+    pub fn consults_other_records() {
+        let root = std::env::temp_dir().join(format!(
+            "cargo-ensure-installed-other-records-{}",
+            std::process::id()
+        ));
+        let ensure = |version_req: &str| {
+            EnsureInstalled::new("cargo-deny")
+                .version_req(version_req)
+                .root(&root)
+                // Fails if it is run.
+                .cargo(root.join("no-such-cargo"))
+                .installed_source(ImageManifest)
+                .run()
+        };
+        assert_eq!(
+            ensure("^0.14").unwrap(),
+            Outcome::AlreadySatisfied {
+                installed_version: Version::parse("0.14.3").unwrap()
+            }
+        );
+        assert!(ensure("^0.15").is_err());
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    pub fn catches_recursive_build_scripts() {
+        assert_eq!(
+            nested_installs(None, "protoc-gen-prost"),
+            Ok("protoc-gen-prost".to_owned())
+        );
+        assert_eq!(
+            nested_installs(Some("protoc-gen-prost"), "protoc"),
+            Ok("protoc-gen-prost,protoc".to_owned())
+        );
+        assert_eq!(
+            nested_installs(Some("protoc-gen-prost,protoc"), "protoc-gen-prost"),
+            Err(
+                "protoc-gen-prost is already being installed for a build script \
+                 (protoc-gen-prost -> protoc): ensuring it again from its own build would never \
+                 finish"
+                    .to_owned()
+            )
+        );
+    }
+
+    #[test]
+    pub fn builds_options() {
+        let builder = EnsureInstalled::new("cargo-nextest")
+            .version_req("^0.9")
+            .features(["self-update"])
+            .features(vec!["default-no-update".to_owned()])
+            .locked(true)
+            .bins(["cargo-nextest"])
+            .root("/opt/tools");
+        assert_eq!(builder.version_req, "^0.9");
+        assert_eq!(
+            builder.options.features,
+            vec!["self-update".to_owned(), "default-no-update".to_owned()]
+        );
+        assert!(builder.options.locked);
+        assert!(!builder.options.offline);
+        assert_eq!(builder.options.bins, vec!["cargo-nextest".to_owned()]);
+        assert_eq!(builder.options.root, Some("/opt/tools".into()));
+    }
+
+    #[test]
+    pub fn describes_outcomes() {
+        let version = |version| Version::parse(version).unwrap();
+        let describe = |outcome: Outcome| (outcome.to_string(), outcome.changed());
+        assert_eq!(
+            describe(Outcome::AlreadySatisfied {
+                installed_version: version("1.4.2")
+            }),
+            ("1.4.2 is already installed".to_owned(), false)
+        );
+        assert_eq!(
+            describe(Outcome::Installed {
+                version: version("1.4.2")
+            }),
+            ("installed 1.4.2".to_owned(), true)
+        );
+        assert_eq!(
+            describe(Outcome::Upgraded {
+                from: version("1.4.1"),
+                to: version("1.4.2")
+            }),
+            ("upgraded from 1.4.1 to 1.4.2".to_owned(), true)
+        );
+        assert_eq!(
+            describe(Outcome::Upgraded {
+                from: version("1.4.2"),
+                to: version("1.4.1")
+            }),
+            ("downgraded from 1.4.2 to 1.4.1".to_owned(), true)
+        );
+        assert_eq!(
+            describe(Outcome::Upgraded {
+                from: version("1.4.2"),
+                to: version("1.4.2")
+            }),
+            ("reinstalled 1.4.2".to_owned(), true)
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! Why checking or installing a package failed.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Why a package couldn't be checked or installed.
+#[derive(Debug)]
+pub enum Error {
+    /// A file couldn't be read.
+    Read { path: PathBuf, error: io::Error },
+    /// `.crates.toml` couldn't be parsed at all.
+    InvalidCratesToml { path: PathBuf, message: String },
+    /// `.crates.toml` was written in a newer format, with this section instead of `[v1]`.
+    UnsupportedCratesToml { path: PathBuf, section: String },
+    /// An entry in `.crates.toml` couldn't be parsed.
+    InvalidEntry {
+        path: PathBuf,
+        key: String,
+        problem: String,
+    },
+    /// A version requirement wasn't valid semver.
+    InvalidVersionReq {
+        package: String,
+        version_req: String,
+        message: String,
+    },
+    /// No published version of `package` matches `version_req`.
+    NoMatchingVersion {
+        package: String,
+        version_req: String,
+    },
+    /// Cargo ran, but didn't succeed. `code` is its exit code, if it exited rather than being
+    /// killed.
+    Cargo {
+        package: String,
+        subcommand: String,
+        code: Option<i32>,
+    },
+    /// Cargo waited longer than `timeout` for another cargo process to release `lock`.
+    LockTimeout { lock: String, timeout: Duration },
+    /// Installing `package` took longer than `timeout`, so cargo was killed.
+    Timeout { package: String, timeout: Duration },
+    /// An installed binary has changed since it was installed, and checksums are strict.
+    ChecksumMismatch { package: String, problem: String },
+    /// Every backend failed, for the reasons given with each backend's name.
+    AllBackendsFailed {
+        package: String,
+        failures: Vec<(&'static str, Error)>,
+    },
+    /// We were interrupted by `signal`, and stopped cargo.
+    Interrupted { signal: i32 },
+    /// Installing `package` was declined when we asked to.
+    Declined { package: String },
+    /// Cargo said it installed `package`, but `.crates.toml` doesn't record any version of it.
+    Unrecorded { package: String },
+    /// Anything else.
+    Other(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Read {
+                ref path,
+                ref error,
+            } => write!(f, "Error reading {:?}: {:?}", path, error),
+            Error::InvalidCratesToml {
+                ref path,
+                ref message,
+            } => write!(f, "Error parsing {:?}: {}", path, message),
+            Error::UnsupportedCratesToml {
+                ref path,
+                ref section,
+            } => write!(
+                f,
+                "Unsupported .crates.toml format at {:?}: it has a [{}] section, but only [v1] is \
+                 understood",
+                path, section
+            ),
+            Error::InvalidEntry {
+                ref path,
+                ref key,
+                ref problem,
+            } => write!(
+                f,
+                "Invalid crates.toml file at {:?}: entry {:?}: {}",
+                path, key, problem
+            ),
+            Error::InvalidVersionReq {
+                ref package,
+                ref version_req,
+                ref message,
+            } => write!(
+                f,
+                "Invalid version specified for {} '{:?}': {}",
+                package, version_req, message
+            ),
+            Error::NoMatchingVersion {
+                ref package,
+                ref version_req,
+            } => write!(
+                f,
+                "No published version of {} matches {}",
+                package, version_req
+            ),
+            Error::Cargo {
+                ref package,
+                ref subcommand,
+                code,
+            } => {
+                write!(f, "Error running cargo {} for {}", subcommand, package)?;
+                match code {
+                    Some(code) => write!(f, " (exit code {})", code),
+                    None => Ok(()),
+                }
+            }
+            Error::LockTimeout { ref lock, timeout } => write!(
+                f,
+                "Gave up after {}s waiting for another cargo process to release its lock on {}",
+                timeout.as_secs(),
+                lock
+            ),
+            Error::Timeout {
+                ref package,
+                timeout,
+            } => write!(
+                f,
+                "Gave up installing {} after {}s",
+                package,
+                timeout.as_secs()
+            ),
+            Error::ChecksumMismatch {
+                ref package,
+                ref problem,
+            } => write!(f, "{}: {}", package, problem),
+            Error::AllBackendsFailed {
+                ref package,
+                ref failures,
+            } => {
+                write!(f, "Every backend failed to install {}:", package)?;
+                for (backend, error) in failures {
+                    write!(f, "\n  {}: {}", backend, error)?;
+                }
+                Ok(())
+            }
+            Error::Interrupted { signal } => {
+                write!(f, "Interrupted by {}", ::signals::name(signal))
+            }
+            Error::Declined { ref package } => write!(f, "Installing {} was declined", package),
+            Error::Unrecorded { ref package } => write!(
+                f,
+                "{} was installed, but .crates.toml doesn't record it",
+                package
+            ),
+            Error::Other(ref message) => f.write_str(message),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Read { ref error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Error {
+        Error::Other(message)
+    }
+}
+
+/// For code which only reports errors, rather than telling them apart.
+impl From<Error> for String {
+    fn from(error: Error) -> String {
+        error.to_string()
+    }
+}
@@ -0,0 +1,231 @@
+//! Events reported while packages are checked and installed, so that frontends can show progress
+//! without scraping our output.
+
+use semver::{Version, VersionReq};
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// Something which happened while ensuring a package is installed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// Checking whether `package` needs installing has started.
+    CheckStarted { package: String },
+    /// Whether `package`, which is installed at `installed` if at all, will be installed, and if
+    /// so, why.
+    Decided {
+        package: String,
+        installed: Option<Version>,
+        reason: Option<InstallReason>,
+    },
+    /// Installing `package` to satisfy `version_req` has started.
+    InstallStarted {
+        package: String,
+        version_req: String,
+    },
+    /// Cargo wrote `line` to stderr while working on `package`.
+    StderrLine { package: String, line: String },
+    /// Installing `package` has finished, with the version recorded afterwards, or why it failed.
+    InstallFinished {
+        package: String,
+        result: Result<Option<Version>, String>,
+    },
+}
+
+/// Why a package is to be installed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InstallReason {
+    /// No version of it is installed.
+    NotInstalled,
+    /// The version which is installed doesn't satisfy the requirement.
+    Unsatisfied {
+        installed: Version,
+        version_req: VersionReq,
+    },
+    /// It isn't recorded as installed, but these binaries of it are on disk.
+    Untracked(Vec<String>),
+    /// A satisfying version is installed, but differently from how it was asked for, such as with
+    /// other features, or with a binary missing from disk.
+    InstalledDifferently(String),
+}
+
+impl fmt::Display for InstallReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InstallReason::NotInstalled => write!(f, "not present"),
+            InstallReason::Unsatisfied {
+                ref installed,
+                ref version_req,
+            } => write!(
+                f,
+                "installed {} does not satisfy {}",
+                installed, version_req
+            ),
+            InstallReason::Untracked(ref bins) => {
+                write!(
+                    f,
+                    "{} present but not recorded in .crates.toml",
+                    bins.join(", ")
+                )
+            }
+            InstallReason::InstalledDifferently(ref problem) => write!(f, "{}", problem),
+        }
+    }
+}
+
+impl Event {
+    /// Logs the event, for `--log-format`.
+    pub(crate) fn log(&self) {
+        let show = |version: &Option<Version>| match *version {
+            Some(ref version) => version.to_string(),
+            None => "none".to_owned(),
+        };
+        match *self {
+            Event::CheckStarted { ref package } => info!(package = %package, "check started"),
+            Event::Decided {
+                ref package,
+                ref installed,
+                ref reason,
+            } => {
+                let why = reason
+                    .as_ref()
+                    .map_or("none".to_owned(), ToString::to_string);
+                let install = reason.is_some();
+                info!(package = %package, installed = %show(installed), install, reason = %why, "decided")
+            }
+            Event::InstallStarted {
+                ref package,
+                ref version_req,
+            } => info!(package = %package, version_req = %version_req, "install started"),
+            Event::StderrLine {
+                ref package,
+                ref line,
+            } => debug!(package = %package, line = %line, "cargo stderr"),
+            Event::InstallFinished {
+                ref package,
+                result: Ok(ref version),
+            } => info!(package = %package, version = %show(version), "install finished"),
+            Event::InstallFinished {
+                ref package,
+                result: Err(ref error),
+            } => warn!(package = %package, error = %error, "install failed"),
+        }
+    }
+
+    /// The event as a JSON object, as `--message-format json-events` prints it: its kind under
+    /// `event`, and its fields, with versions as strings and `null` for those which are missing.
+    pub(crate) fn to_json(&self) -> Value {
+        let string = |s: &str| Value::String(s.to_owned());
+        let version = |version: &Option<Version>| match *version {
+            Some(ref version) => Value::String(version.to_string()),
+            None => Value::Null,
+        };
+        let mut object = Map::new();
+        let (kind, package) = match *self {
+            Event::CheckStarted { ref package } => ("check-started", package),
+            Event::Decided {
+                ref package,
+                ref installed,
+                ref reason,
+            } => {
+                object.insert("installed".to_owned(), version(installed));
+                object.insert("install".to_owned(), Value::Bool(reason.is_some()));
+                object.insert(
+                    "reason".to_owned(),
+                    reason
+                        .as_ref()
+                        .map_or(Value::Null, |reason| string(&reason.to_string())),
+                );
+                ("decided", package)
+            }
+            Event::InstallStarted {
+                ref package,
+                ref version_req,
+            } => {
+                object.insert("version_req".to_owned(), string(version_req));
+                ("install-started", package)
+            }
+            Event::StderrLine {
+                ref package,
+                ref line,
+            } => {
+                object.insert("line".to_owned(), string(line));
+                ("stderr", package)
+            }
+            Event::InstallFinished {
+                ref package,
+                ref result,
+            } => {
+                let (installed, error) = match *result {
+                    Ok(ref installed) => (version(installed), Value::Null),
+                    Err(ref error) => (Value::Null, string(error)),
+                };
+                object.insert("version".to_owned(), installed);
+                object.insert("error".to_owned(), error);
+                ("install-finished", package)
+            }
+        };
+        object.insert("event".to_owned(), string(kind));
+        object.insert("package".to_owned(), string(package));
+        Value::Object(object)
+    }
+}
+
+/// Something which wants to hear about events. Closures taking an `Event` are observers.
+pub trait Observer: Send + Sync {
+    fn event(&self, event: Event);
+}
+
+impl<F: Fn(Event) + Send + Sync> Observer for F {
+    fn event(&self, event: Event) {
+        self(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, InstallReason};
+    use semver::{Version, VersionReq};
+
+    #[test]
+    pub fn formats_json() {
+        let json = |event: Event| event.to_json().to_string();
+        assert_eq!(
+            json(Event::Decided {
+                package: "rustfmt".to_owned(),
+                installed: Some(Version::parse("1.4.2").unwrap()),
+                reason: Some(InstallReason::Unsatisfied {
+                    installed: Version::parse("1.4.2").unwrap(),
+                    version_req: VersionReq::parse("^1.5").unwrap(),
+                }),
+            }),
+            r#"{"event":"decided","install":true,"installed":"1.4.2","package":"rustfmt","reason":"installed 1.4.2 does not satisfy ^1.5"}"#
+        );
+        assert_eq!(
+            json(Event::Decided {
+                package: "rustfmt".to_owned(),
+                installed: Some(Version::parse("1.5.0").unwrap()),
+                reason: None,
+            }),
+            r#"{"event":"decided","install":false,"installed":"1.5.0","package":"rustfmt","reason":null}"#
+        );
+        assert_eq!(
+            InstallReason::Untracked(vec!["rustfmt".to_owned(), "cargo-fmt".to_owned()])
+                .to_string(),
+            "rustfmt, cargo-fmt present but not recorded in .crates.toml"
+        );
+        assert_eq!(
+            json(Event::StderrLine {
+                package: "rustfmt".to_owned(),
+                line: "   Compiling rustfmt".to_owned(),
+            }),
+            r#"{"event":"stderr","line":"   Compiling rustfmt","package":"rustfmt"}"#
+        );
+        assert_eq!(
+            json(Event::InstallFinished {
+                package: "rustfmt".to_owned(),
+                result: Err("Error running cargo install for rustfmt".to_owned()),
+            }),
+            r#"{"error":"Error running cargo install for rustfmt","event":"install-finished","package":"rustfmt","version":null}"#
+        );
+    }
+}
@@ -0,0 +1,230 @@
+//! Telling which platform an executable was built for, from its header or its target triple, so
+//! that binaries restored from a cache made on another machine aren't trusted.
+
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// The kind of executable a platform runs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Elf,
+    MachO,
+    Pe,
+}
+
+/// What an executable needs to run: its file format and CPU architecture, as named by
+/// `std::env::consts::ARCH`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Platform {
+    pub format: Format,
+    pub arch: &'static str,
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let format = match self.format {
+            Format::Elf => "ELF",
+            Format::MachO => "Mach-O",
+            Format::Pe => "PE",
+        };
+        write!(f, "{} {}", self.arch, format)
+    }
+}
+
+/// The platform we're running on.
+pub fn host() -> Platform {
+    Platform {
+        format: format_for_os(std::env::consts::OS),
+        arch: std::env::consts::ARCH,
+    }
+}
+
+fn format_for_os(os: &str) -> Format {
+    match os {
+        "macos" | "ios" => Format::MachO,
+        "windows" => Format::Pe,
+        _ => Format::Elf,
+    }
+}
+
+/// The platform a target triple like `aarch64-apple-darwin` builds for, or `None` if we don't
+/// recognise its architecture.
+pub fn for_target(target: &str) -> Option<Platform> {
+    let arch = match target.split('-').next()? {
+        "x86_64" => "x86_64",
+        "aarch64" | "arm64" => "aarch64",
+        "i386" | "i586" | "i686" => "x86",
+        arch if arch.starts_with("arm") || arch.starts_with("thumb") => "arm",
+        "riscv64gc" | "riscv64" => "riscv64",
+        "powerpc64" | "powerpc64le" => "powerpc64",
+        "s390x" => "s390x",
+        _ => return None,
+    };
+    let format = if target.contains("-apple-") {
+        Format::MachO
+    } else if target.contains("-windows") {
+        Format::Pe
+    } else {
+        Format::Elf
+    };
+    Some(Platform { format, arch })
+}
+
+/// The platform the executable at `path` was built for, or `None` if its header isn't one we
+/// recognise, such as a script or a universal macOS binary.
+pub fn for_file(path: &Path) -> Result<Option<Platform>, String> {
+    let mut header = vec![];
+    let read = File::open(path).and_then(|file| file.take(4096).read_to_end(&mut header));
+    if let Err(err) = read {
+        return Err(format!("Error reading {:?}: {:?}", path, err));
+    }
+    Ok(parse_header(&header))
+}
+
+fn parse_header(header: &[u8]) -> Option<Platform> {
+    let u16_at = |offset: usize, little_endian: bool| {
+        let bytes = [*header.get(offset)?, *header.get(offset + 1)?];
+        Some(if little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    };
+    let u32_le_at = |offset: usize| {
+        let bytes = header.get(offset..offset + 4)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+    if header.starts_with(b"\x7fELF") {
+        let little_endian = *header.get(5)? == 1;
+        let arch = match u16_at(18, little_endian)? {
+            0x03 => "x86",
+            0x28 => "arm",
+            0x3e => "x86_64",
+            0xb7 => "aarch64",
+            0xf3 => "riscv64",
+            0x15 => "powerpc64",
+            0x16 => "s390x",
+            _ => return None,
+        };
+        return Some(Platform {
+            format: Format::Elf,
+            arch,
+        });
+    }
+    if header.starts_with(b"\xcf\xfa\xed\xfe") || header.starts_with(b"\xce\xfa\xed\xfe") {
+        let arch = match u32_le_at(4)? {
+            0x0100_0007 => "x86_64",
+            0x0100_000c => "aarch64",
+            7 => "x86",
+            12 => "arm",
+            _ => return None,
+        };
+        return Some(Platform {
+            format: Format::MachO,
+            arch,
+        });
+    }
+    if header.starts_with(b"MZ") {
+        let pe = u32_le_at(0x3c)? as usize;
+        if header.get(pe..pe + 4)? != b"PE\0\0" {
+            return None;
+        }
+        let arch = match u16_at(pe + 4, true)? {
+            0x8664 => "x86_64",
+            0xaa64 => "aarch64",
+            0x014c => "x86",
+            0x01c4 => "arm",
+            _ => return None,
+        };
+        return Some(Platform {
+            format: Format::Pe,
+            arch,
+        });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{for_file, for_target, host, parse_header, Format, Platform};
+
+    fn elf(machine: u16) -> Vec<u8> {
+        let mut header = b"\x7fELF\x02\x01\x01".to_vec();
+        header.resize(18, 0);
+        header.extend(machine.to_le_bytes().iter());
+        header.resize(64, 0);
+        header
+    }
+
+    #[test]
+    pub fn parses_headers() {
+        assert_eq!(
+            parse_header(&elf(0x3e)),
+            Some(Platform {
+                format: Format::Elf,
+                arch: "x86_64"
+            })
+        );
+        assert_eq!(parse_header(&elf(0xb7)).unwrap().arch, "aarch64");
+        assert_eq!(parse_header(&elf(0x9999)), None);
+
+        let mut macho = b"\xcf\xfa\xed\xfe".to_vec();
+        macho.extend(0x0100_000cu32.to_le_bytes().iter());
+        assert_eq!(
+            parse_header(&macho),
+            Some(Platform {
+                format: Format::MachO,
+                arch: "aarch64"
+            })
+        );
+
+        let mut pe = b"MZ".to_vec();
+        pe.resize(0x3c, 0);
+        pe.extend(0x80u32.to_le_bytes().iter());
+        pe.resize(0x80, 0);
+        pe.extend(b"PE\0\0".iter());
+        pe.extend(0x8664u16.to_le_bytes().iter());
+        assert_eq!(
+            parse_header(&pe),
+            Some(Platform {
+                format: Format::Pe,
+                arch: "x86_64"
+            })
+        );
+
+        assert_eq!(parse_header(b"#!/bin/sh\necho hello\n"), None);
+        assert_eq!(parse_header(b"\x7fEL"), None);
+    }
+
+    #[test]
+    pub fn recognises_host_executables() {
+        let exe = std::env::current_exe().unwrap();
+        assert_eq!(for_file(&exe), Ok(Some(host())));
+    }
+
+    #[test]
+    pub fn parses_targets() {
+        assert_eq!(
+            for_target("aarch64-apple-darwin"),
+            Some(Platform {
+                format: Format::MachO,
+                arch: "aarch64"
+            })
+        );
+        assert_eq!(
+            for_target("x86_64-pc-windows-msvc"),
+            Some(Platform {
+                format: Format::Pe,
+                arch: "x86_64"
+            })
+        );
+        assert_eq!(
+            for_target("x86_64-unknown-linux-musl"),
+            for_target("x86_64-unknown-linux-gnu")
+        );
+        assert_eq!(for_target("i686-unknown-linux-gnu").unwrap().arch, "x86");
+        assert_eq!(for_target("wasm32-wasip1"), None);
+    }
+}
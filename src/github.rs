@@ -0,0 +1,196 @@
+//! Output for GitHub Actions, when running in it: each package's install is a collapsible group,
+//! failures and drifted versions are annotations, and the job gets a summary table.
+
+use event::Event;
+use std::env;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use summary::{Decision, PackageSummary};
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Writes output for GitHub Actions from now on, if `GITHUB_ACTIONS` says we are running in it.
+pub fn init() {
+    ACTIVE.store(
+        env::var("GITHUB_ACTIONS").is_ok_and(|value| value == "true"),
+        Ordering::Relaxed,
+    );
+}
+
+/// Whether we are writing output for GitHub Actions.
+pub fn active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Opens a group when a package starts installing, and closes it when it's done, so that cargo's
+/// output is folded away under the package's name.
+pub fn event(event: &Event) {
+    if !active() {
+        return;
+    }
+    match *event {
+        Event::InstallStarted {
+            ref package,
+            ref version_req,
+        } => human!("::group::Installing {} {}", package, escape(version_req)),
+        Event::InstallFinished { .. } => human!("::endgroup::"),
+        _ => {}
+    }
+}
+
+/// Annotates the run with what went wrong for `packages`, and appends a table of what was done to
+/// them to `$GITHUB_STEP_SUMMARY`.
+pub fn report(packages: &[PackageSummary]) {
+    if !active() {
+        return;
+    }
+    for annotation in annotations(packages) {
+        human!("{}", annotation);
+    }
+    if let Some(path) = env::var_os("GITHUB_STEP_SUMMARY") {
+        if let Err(err) = append(Path::new(&path), &step_summary(packages)) {
+            verbose!(0, "Error writing the step summary to {:?}: {:?}", path, err);
+        }
+    }
+}
+
+fn append(path: &Path, contents: &str) -> io::Result<()> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(contents.as_bytes())
+}
+
+/// An `::error::` for each package which failed, and a `::warning::` for each whose installed
+/// version didn't satisfy its requirement.
+fn annotations(packages: &[PackageSummary]) -> Vec<String> {
+    let mut annotations = vec![];
+    for package in packages {
+        let requirement = package.requirement.as_deref().unwrap_or("*");
+        let (level, message) = match (package.decision, &package.before) {
+            (Decision::Failed, _) => (
+                "error",
+                format!(
+                    "{}: {}",
+                    package.package,
+                    package.error.as_deref().unwrap_or("failed")
+                ),
+            ),
+            (Decision::Unsatisfied, _) => (
+                "warning",
+                format!(
+                    "{}: {}",
+                    package.package,
+                    package.error.as_deref().unwrap_or("not satisfied")
+                ),
+            ),
+            (Decision::Upgraded, Some(before)) | (Decision::Downgraded, Some(before)) => (
+                "warning",
+                format!(
+                    "{} {} didn't satisfy {}, so was {}",
+                    package.package,
+                    before,
+                    requirement,
+                    package.decision.name()
+                ),
+            ),
+            _ => continue,
+        };
+        annotations.push(format!(
+            "::{} title=cargo-ensure-installed::{}",
+            level,
+            escape(&message)
+        ));
+    }
+    annotations
+}
+
+/// What a workflow command's message can't contain, escaped as GitHub Actions expects.
+fn escape(message: &str) -> String {
+    message
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// A Markdown table of what was done to `packages`.
+fn step_summary(packages: &[PackageSummary]) -> String {
+    let version = |version: &Option<::semver::Version>| match *version {
+        Some(ref version) => format!("`{}`", version),
+        None => "-".to_owned(),
+    };
+    let mut summary = "### cargo ensure-installed\n\n\
+                       | Package | Before | Action | After | Duration |\n\
+                       | --- | --- | --- | --- | ---: |\n"
+        .to_owned();
+    for package in packages {
+        summary.push_str(&format!(
+            "| {} | {} | {} | {} | {:.1}s |\n",
+            package.package,
+            version(&package.before),
+            package.decision.name(),
+            version(&package.after),
+            package.duration.as_secs_f64()
+        ));
+    }
+    summary.push('\n');
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{annotations, escape, step_summary};
+    use semver::Version;
+    use std::time::Duration;
+    use summary::{Decision, PackageSummary};
+
+    fn summary(package: &str, decision: Decision, before: Option<&str>) -> PackageSummary {
+        PackageSummary {
+            package: package.to_owned(),
+            requirement: Some("^14".to_owned()),
+            decision,
+            reason: None,
+            before: before.map(|before| Version::parse(before).unwrap()),
+            after: Some(Version::parse("14.1.0").unwrap()),
+            duration: Duration::from_millis(1500),
+            install_duration: None,
+            backend: None,
+            error: None,
+        }
+    }
+
+    #[test]
+    pub fn annotates() {
+        let mut failed = summary("cargo-deny", Decision::Failed, None);
+        failed.error = Some("cargo exited 101\nerror: boom".to_owned());
+        let packages = [
+            summary("rustfmt", Decision::Satisfied, Some("14.1.0")),
+            summary("ripgrep", Decision::Upgraded, Some("13.0.0")),
+            failed,
+        ];
+        assert_eq!(
+            annotations(&packages),
+            [
+                "::warning title=cargo-ensure-installed::ripgrep 13.0.0 didn't satisfy ^14, so \
+                 was upgraded",
+                "::error title=cargo-ensure-installed::cargo-deny: cargo exited 101%0Aerror: boom",
+            ]
+        );
+        assert_eq!(escape("100%\r\n"), "100%25%0D%0A");
+    }
+
+    #[test]
+    pub fn summarises_steps() {
+        let packages = [summary("ripgrep", Decision::Installed, None)];
+        assert_eq!(
+            step_summary(&packages),
+            "### cargo ensure-installed\n\n\
+             | Package | Before | Action | After | Duration |\n\
+             | --- | --- | --- | --- | ---: |\n\
+             | ripgrep | - | installed | `14.1.0` | 1.5s |\n\n"
+        );
+    }
+}
@@ -0,0 +1,315 @@
+//! Records of what is installed: `.crates.toml`, `.crates2.json` and our own state file, and any
+//! other record a library user has, such as the manifest of a container image.
+
+use crates2;
+use crates_toml::{self, Key};
+use error::Error;
+use semver::{Version, VersionReq};
+use state::State;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use toml::value::Table;
+
+/// A package which a record says is installed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InstalledPackage {
+    pub name: String,
+    pub version: Version,
+    /// Where it came from, as in the source part of a `.crates.toml` key, like
+    /// `registry+https://github.com/rust-lang/crates.io-index`.
+    pub source: String,
+    pub bins: Vec<String>,
+}
+
+impl InstalledPackage {
+    /// The package recorded under `key`, a `"name version (source)"` key like `.crates.toml`'s, in
+    /// the record at `path`.
+    fn from_key(path: &Path, key: &str, bins: Vec<String>) -> Result<InstalledPackage, Error> {
+        let key = ::parse_key(path, key)?;
+        Ok(InstalledPackage {
+            name: key.name,
+            version: key.version,
+            source: key.source,
+            bins,
+        })
+    }
+
+    /// The package's `"name version (source)"` key, as `.crates.toml` records it.
+    pub fn key(&self) -> String {
+        Key {
+            name: self.name.clone(),
+            version: self.version.clone(),
+            source: self.source.clone(),
+        }
+        .to_string()
+    }
+}
+
+/// The packages installed in an install root, as `.crates.toml` records them, along with those our
+/// state file records which cargo doesn't know about.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InstalledCrates {
+    /// In the order of their keys.
+    packages: Vec<InstalledPackage>,
+}
+
+impl InstalledCrates {
+    /// Reads the install root whose `.crates.toml` is at `crates_toml`, which has nothing installed
+    /// if it doesn't exist.
+    pub fn read(crates_toml: &Path) -> Result<InstalledCrates, Error> {
+        let contents = ::read_crates_toml(crates_toml)?;
+        InstalledCrates::from_table(crates_toml, &::installed_table(crates_toml, &contents)?)
+    }
+
+    /// Parses `contents`, a `.crates.toml` read from `path`, without looking at anything else.
+    pub fn parse(path: &Path, contents: &str) -> Result<InstalledCrates, Error> {
+        InstalledCrates::from_table(path, &crates_toml::installed(path, contents)?)
+    }
+
+    /// The packages in the `[v1]` table of a `.crates.toml` at `path`.
+    pub(crate) fn from_table(path: &Path, table: &Table) -> Result<InstalledCrates, Error> {
+        let packages = table
+            .iter()
+            .map(|(key, bins)| {
+                let bins = match bins.as_array() {
+                    Some(bins) => bins
+                        .iter()
+                        .filter_map(|bin| bin.as_str().map(str::to_owned))
+                        .collect(),
+                    None => vec![],
+                };
+                debug!(path = %path.display(), key = %key, bins = ?bins, "parsed entry");
+                InstalledPackage::from_key(path, key, bins)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(InstalledCrates { packages })
+    }
+
+    /// How `package` is installed, if it is. A corrupted or hand-edited `.crates.toml` may record
+    /// it several times, in which case the one with the highest version is used.
+    pub fn get(&self, package: &str) -> Option<&InstalledPackage> {
+        let mut found: Option<&InstalledPackage> = None;
+        for installed in self.packages.iter().filter(|p| p.name == package) {
+            if found.is_none_or(|found| installed.version > found.version) {
+                found = Some(installed);
+            }
+        }
+        found
+    }
+
+    /// The installed version of `package`, if it is installed.
+    pub fn version(&self, package: &str) -> Option<&Version> {
+        self.get(package).map(|installed| &installed.version)
+    }
+
+    /// Whether `package` is installed at a version matching `version_req`.
+    pub fn satisfies(&self, package: &str, version_req: &VersionReq) -> bool {
+        self.version(package)
+            .is_some_and(|version| version_req.matches(version))
+    }
+
+    /// Every installed package, in the order of their `"name version (source)"` keys.
+    pub fn iter(&self) -> impl Iterator<Item = &InstalledPackage> {
+        self.packages.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.packages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty()
+    }
+}
+
+/// Somewhere which records what is installed.
+pub trait InstalledSource: fmt::Debug + Send + Sync {
+    /// Where the record is, like a path, for messages.
+    fn describe(&self) -> String;
+
+    /// Every package it records as installed.
+    fn installed(&self) -> Result<Vec<InstalledPackage>, Error>;
+}
+
+/// Cargo's `.crates.toml`, its record of every package it has installed.
+#[derive(Clone, Debug)]
+pub struct CratesToml {
+    pub path: PathBuf,
+}
+
+impl InstalledSource for CratesToml {
+    fn describe(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn installed(&self) -> Result<Vec<InstalledPackage>, Error> {
+        let contents = ::read_crates_toml(&self.path)?;
+        Ok(InstalledCrates::parse(&self.path, &contents)?.packages)
+    }
+}
+
+/// The `.crates2.json` next to `.crates.toml` at `crates_toml`, where newer versions of cargo also
+/// record what they install. An older cargo may have replaced a package without updating it.
+#[derive(Clone, Debug)]
+pub struct Crates2Json {
+    pub crates_toml: PathBuf,
+}
+
+impl InstalledSource for Crates2Json {
+    fn describe(&self) -> String {
+        self.crates_toml
+            .with_file_name(".crates2.json")
+            .display()
+            .to_string()
+    }
+
+    fn installed(&self) -> Result<Vec<InstalledPackage>, Error> {
+        let path = self.crates_toml.with_file_name(".crates2.json");
+        crates2::installed(&self.crates_toml)?
+            .into_iter()
+            .map(|(key, bins)| InstalledPackage::from_key(&path, &key, bins))
+            .collect()
+    }
+}
+
+/// Our state file next to `.crates.toml` at `crates_toml`, which records the packages installed
+/// from prebuilt binaries, which cargo doesn't know about.
+#[derive(Clone, Debug)]
+pub struct StateFile {
+    pub crates_toml: PathBuf,
+}
+
+impl InstalledSource for StateFile {
+    fn describe(&self) -> String {
+        self.crates_toml
+            .with_file_name(".cargo-ensure-installed.json")
+            .display()
+            .to_string()
+    }
+
+    fn installed(&self) -> Result<Vec<InstalledPackage>, Error> {
+        let path = self
+            .crates_toml
+            .with_file_name(".cargo-ensure-installed.json");
+        State::load(&self.crates_toml)?
+            .installed()
+            .into_iter()
+            .map(|(key, bins)| InstalledPackage::from_key(&path, &key, bins))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Crates2Json, CratesToml, InstalledCrates, InstalledPackage, InstalledSource, StateFile,
+    };
+    use backend::Backend;
+    use semver::{Version, VersionReq};
+    use state::State;
+    use std::fs;
+
+    #[test]
+    pub fn reads_records() {
+        let root = std::env::temp_dir().join(format!(
+            "cargo-ensure-installed-installed-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&root).unwrap();
+        let crates_toml = root.join(".crates.toml");
+        let sources: Vec<Box<dyn InstalledSource>> = vec![
+            Box::new(CratesToml {
+                path: crates_toml.clone(),
+            }),
+            Box::new(Crates2Json {
+                crates_toml: crates_toml.clone(),
+            }),
+            Box::new(StateFile {
+                crates_toml: crates_toml.clone(),
+            }),
+        ];
+        for source in &sources {
+            assert_eq!(source.installed().unwrap(), vec![]);
+        }
+
+        let rustfmt = InstalledPackage {
+            name: "rustfmt".to_owned(),
+            version: Version::parse("1.4.2").unwrap(),
+            source: "registry+https://github.com/rust-lang/crates.io-index".to_owned(),
+            bins: vec!["cargo-fmt".to_owned(), "rustfmt".to_owned()],
+        };
+        fs::write(
+            &crates_toml,
+            format!("[v1]\n{:?} = [\"cargo-fmt\", \"rustfmt\"]\n", rustfmt.key()),
+        )
+        .unwrap();
+        fs::write(
+            root.join(".crates2.json"),
+            format!(
+                "{{\"installs\": {{{:?}: {{\"bins\": [\"cargo-fmt\", \"rustfmt\"]}}}}}}",
+                rustfmt.key()
+            ),
+        )
+        .unwrap();
+        let mut state = State::load(&crates_toml).unwrap();
+        state.record(&rustfmt.key(), &rustfmt.bins, Backend::Quickinstall);
+        state.save().unwrap();
+        for source in &sources {
+            assert_eq!(source.installed().unwrap(), vec![rustfmt.clone()]);
+        }
+        assert_eq!(
+            sources[1].describe(),
+            root.join(".crates2.json").display().to_string()
+        );
+
+        // Only the state file records cargo-nextest.
+        let mut state = State::load(&crates_toml).unwrap();
+        state.record(
+            "cargo-nextest 0.9.67 (registry+https://github.com/rust-lang/crates.io-index)",
+            &["cargo-nextest".to_owned()],
+            Backend::Quickinstall,
+        );
+        state.save().unwrap();
+        let installed = InstalledCrates::read(&crates_toml).unwrap();
+        let names: Vec<_> = installed.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["cargo-nextest", "rustfmt"]);
+        let contents = fs::read_to_string(&crates_toml).unwrap();
+        assert_eq!(
+            InstalledCrates::parse(&crates_toml, &contents)
+                .unwrap()
+                .len(),
+            1
+        );
+
+        fs::write(&crates_toml, "[v1]\n\"rustfmt\" = []\n").unwrap();
+        assert!(sources[0].installed().is_err());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    pub fn looks_up_packages() {
+        let installed = InstalledCrates::parse(
+            "/path/to/.crates.toml".as_ref(),
+            r###"[v1]
+"cargo-deny 0.14.3 (registry+https://github.com/rust-lang/crates.io-index)" = ["cargo-deny"]
+"rustfmt 0.9.0 (registry+https://github.com/rust-lang/crates.io-index)" = ["rustfmt"]
+"rustfmt 1.0.0 (git+https://github.com/rust-lang/rustfmt#0123456789abcdef)" = ["rustfmt"]
+"rustfmt 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)" = ["rustfmt"]"###,
+        )
+        .unwrap();
+        assert_eq!(installed.len(), 4);
+        assert_eq!(
+            installed.get("rustfmt").map(|p| p.source.as_str()),
+            Some("git+https://github.com/rust-lang/rustfmt#0123456789abcdef")
+        );
+        assert_eq!(
+            installed.version("cargo-deny"),
+            Some(&Version::parse("0.14.3").unwrap())
+        );
+        assert_eq!(installed.get("cargo"), None);
+        assert!(installed.satisfies("rustfmt", &VersionReq::parse("^1").unwrap()));
+        assert!(!installed.satisfies("cargo-deny", &VersionReq::parse("^0.15").unwrap()));
+        assert!(!installed.satisfies("cargo", &VersionReq::parse("*").unwrap()));
+        assert!(InstalledCrates::default().is_empty());
+    }
+}
@@ -0,0 +1,895 @@
+//! Installing packages with the backends they ask for.
+
+use backend::Backend;
+use cargo_config;
+use crates_toml;
+use error::Error;
+use output;
+use prebuilt;
+use prebuilt::Release;
+use registry;
+use requirement::Requirement;
+use semver::Version;
+use source::{GitReference, Source};
+use state::{self, Build, State};
+use std::thread;
+use std::time::Duration;
+use Cargo;
+
+/// Something which can install packages.
+pub trait Installer {
+    /// The name of the backend, for messages.
+    fn name(&self) -> &'static str;
+
+    /// Describes how `requirement` would be installed, for `--dry-run`, or fails if this can't
+    /// install it.
+    fn describe(&self, cargo: &Cargo, requirement: &Requirement) -> Result<String, Error>;
+
+    fn install(&self, cargo: &Cargo, requirement: &Requirement) -> Result<(), Error>;
+}
+
+/// Builds from source with `cargo install`.
+pub struct CargoInstall;
+
+/// Runs `cargo binstall`. With `fallback`, cargo-binstall doesn't build from source itself,
+/// leaving that to a later installer.
+pub struct Binstall {
+    pub fallback: bool,
+}
+
+/// Downloads a release archive for the `quickinstall` or `github-release` backend.
+pub struct Download {
+    pub backend: Backend,
+}
+
+/// The installers for `backends`, in order.
+pub fn installers(backends: &[Backend]) -> Vec<Box<dyn Installer>> {
+    backends
+        .iter()
+        .enumerate()
+        .map(|(index, &backend)| -> Box<dyn Installer> {
+            match backend {
+                Backend::Source => Box::new(CargoInstall),
+                Backend::Binstall => Box::new(Binstall {
+                    fallback: index + 1 < backends.len(),
+                }),
+                Backend::Quickinstall | Backend::GithubRelease => Box::new(Download { backend }),
+            }
+        })
+        .collect()
+}
+
+/// Installs `requirement` with the first of its backends which succeeds.
+pub fn install(cargo: &Cargo, requirement: &Requirement) -> Result<(), Error> {
+    install_with(cargo, requirement, &installers(&requirement.backends))
+}
+
+/// Describes how the first of `requirement`'s backends which can install it would do so.
+pub fn describe(cargo: &Cargo, requirement: &Requirement) -> Result<String, Error> {
+    let mut result = Err(Error::Other(format!(
+        "No backends to install {} with",
+        requirement.package
+    )));
+    for installer in installers(&requirement.backends) {
+        result = installer.describe(cargo, requirement);
+        if result.is_ok() {
+            break;
+        }
+    }
+    result
+}
+
+fn install_with(
+    cargo: &Cargo,
+    requirement: &Requirement,
+    installers: &[Box<dyn Installer>],
+) -> Result<(), Error> {
+    let mut failures = vec![];
+    for (index, installer) in installers.iter().enumerate() {
+        let span = info_span!("install", backend = installer.name());
+        let _entered = span.enter();
+        let err = match installer.install(cargo, requirement) {
+            Ok(()) => return Ok(()),
+            Err(err @ Error::Interrupted { .. }) => return Err(err),
+            Err(err) => err,
+        };
+        warn!(error = %err, "backend failed");
+        if index + 1 < installers.len() {
+            verbose!(
+                output::VERBOSE,
+                "{}: {}: trying the next backend",
+                requirement.package,
+                err
+            );
+        } else if failures.is_empty() {
+            return Err(err);
+        }
+        failures.push((installer.name(), err));
+    }
+    if failures.is_empty() {
+        return Err(Error::Other(format!(
+            "No backends to install {} with",
+            requirement.package
+        )));
+    }
+    Err(Error::AllBackendsFailed {
+        package: requirement.package.clone(),
+        failures,
+    })
+}
+
+impl Installer for CargoInstall {
+    fn name(&self) -> &'static str {
+        "source"
+    }
+
+    fn describe(&self, cargo: &Cargo, requirement: &Requirement) -> Result<String, Error> {
+        Ok(cargo.command_line(&install_args(cargo, requirement)))
+    }
+
+    fn install(&self, cargo: &Cargo, requirement: &Requirement) -> Result<(), Error> {
+        run_cargo(
+            cargo,
+            requirement,
+            Backend::Source,
+            &install_args(cargo, requirement),
+        )
+    }
+}
+
+impl Installer for Binstall {
+    fn name(&self) -> &'static str {
+        "binstall"
+    }
+
+    fn describe(&self, cargo: &Cargo, requirement: &Requirement) -> Result<String, Error> {
+        let args = binstall_args(cargo, requirement, self.fallback)?;
+        Ok(cargo.command_line(&args))
+    }
+
+    fn install(&self, cargo: &Cargo, requirement: &Requirement) -> Result<(), Error> {
+        let args = binstall_args(cargo, requirement, self.fallback)?;
+        run_cargo(cargo, requirement, Backend::Binstall, &args)
+    }
+}
+
+impl Installer for Download {
+    fn name(&self) -> &'static str {
+        self.backend.name()
+    }
+
+    fn describe(&self, cargo: &Cargo, requirement: &Requirement) -> Result<String, Error> {
+        let release = self.release(cargo, requirement)?;
+        Ok(format!(
+            "download {} into {}",
+            release.url,
+            cargo.bin_dir().display()
+        ))
+    }
+
+    /// Downloads the prebuilt binaries of `requirement`, and records them in our state file.
+    fn install(&self, cargo: &Cargo, requirement: &Requirement) -> Result<(), Error> {
+        let release = self.release(cargo, requirement)?;
+        verbose!(output::VERBOSE, "Downloading {}", release.url);
+        let fetched = prebuilt::fetch(
+            &release,
+            &requirement.package,
+            &requirement.bins,
+            &cargo.bin_dir(),
+        )?;
+        let bins = match fetched {
+            Some(bins) => bins,
+            None if self.backend == Backend::Quickinstall => {
+                return Err(format!(
+                    "cargo-quickinstall has no prebuilt binary of {} {} for this target ({} was \
+                     not found); try --backend quickinstall,source to build it when there isn't \
+                     one",
+                    requirement.package, release.version, release.url
+                )
+                .into())
+            }
+            None => {
+                return Err(format!(
+                    "No release of {} {} was found at {}",
+                    requirement.package, release.version, release.url
+                )
+                .into())
+            }
+        };
+        Ok(State::update(&cargo.crates_toml, |state| {
+            state.record(
+                &format!(
+                    "{} {} ({})",
+                    requirement.package, release.version, release.source
+                ),
+                &bins,
+                self.backend,
+            );
+            record_checksums(cargo, state, &requirement.package, &bins);
+        })?)
+    }
+}
+
+/// Runs `cargo` with `args` to install `requirement` with `backend`, retrying with exponential
+/// backoff if it fails because of the network, and records how it was built.
+fn run_cargo(
+    cargo: &Cargo,
+    requirement: &Requirement,
+    backend: Backend,
+    args: &[String],
+) -> Result<(), Error> {
+    let mut attempt = 0;
+    loop {
+        verbose!(output::VERBOSE, "Running {}", cargo.command_line(args));
+        info!(command = %cargo.command_line(args), attempt, "running cargo");
+        let exit = cargo.runner.run(
+            cargo.command().args(args),
+            &requirement.package,
+            cargo.lock_timeout,
+            cargo.timeout,
+            &|line| cargo.notify_line(&requirement.package, line),
+        )?;
+        info!(code = ?exit.code, "cargo exited");
+        if exit.success() {
+            break;
+        }
+        if attempt < cargo.retries && is_network_error(&exit.stderr) {
+            let delay = Duration::from_secs(1 << attempt.min(6));
+            verbose!(
+                0,
+                "{}: cargo failed to reach the network; retrying in {}s",
+                requirement.package,
+                delay.as_secs()
+            );
+            thread::sleep(delay);
+            attempt += 1;
+            continue;
+        }
+        if is_unmatched_version(&exit.stderr) {
+            return Err(Error::NoMatchingVersion {
+                package: requirement.package.clone(),
+                version_req: requirement.raw_version.clone(),
+            });
+        }
+        let subcommand = args.iter().find(|arg| !arg.starts_with('+'));
+        return Err(Error::Cargo {
+            package: requirement.package.clone(),
+            subcommand: subcommand
+                .map(String::as_str)
+                .unwrap_or("install")
+                .to_owned(),
+            code: exit.code,
+        });
+    }
+    let contents = ::read_crates_toml(&cargo.crates_toml)?;
+    let table = crates_toml::installed(&cargo.crates_toml, &contents)?;
+    let installed = ::package_key(&table, &requirement.package)
+        .and_then(|key| table.get(&key).map(|bins| (key, bins)));
+    let (key, bins) = match installed {
+        Some((key, bins)) => {
+            let bins = match bins.as_array() {
+                Some(bins) => bins
+                    .iter()
+                    .filter_map(|bin| bin.as_str().map(str::to_owned))
+                    .collect(),
+                None => vec![],
+            };
+            (Some(key), bins)
+        }
+        None => (None, vec![]),
+    };
+    let mut build = Build {
+        backend: Some(backend),
+        installed_at: Some(state::now()),
+        profile: requirement.profile.clone(),
+        target: requirement.target.clone(),
+        features: requirement.features.clone(),
+        all_features: requirement.all_features,
+        no_default_features: requirement.no_default_features,
+        ..Build::default()
+    };
+    if let Some(key) = key.and_then(|key| crates_toml::Key::parse(&key).ok()) {
+        build.version = Some(key.version.to_string());
+        build.source = Some(key.source);
+    }
+    // cargo-binstall usually downloads a binary built elsewhere, with another compiler.
+    if backend == Backend::Source {
+        build.rustc = ::rustc_version(requirement.toolchain.as_deref());
+        build.rustflags = Some(::rustflags(&cargo.env));
+    }
+    Ok(State::update(&cargo.crates_toml, |state| {
+        // Cargo now records the package, so any download we recorded has been replaced.
+        state.forget(&requirement.package);
+        state.record_build(&requirement.package, &build);
+        record_checksums(cargo, state, &requirement.package, &bins);
+    })?)
+}
+
+/// Records the SHA-256 of `package`'s freshly installed `bins` in `state`, so that later runs can
+/// tell if they've changed.
+fn record_checksums(cargo: &Cargo, state: &mut State, package: &str, bins: &[String]) {
+    let mut checksums = vec![];
+    for bin in bins {
+        match prebuilt::sha256(&cargo.bin_dir().join(bin)) {
+            Ok(hash) => checksums.push((bin.clone(), hash)),
+            Err(err) => verbose!(
+                output::VERBOSE,
+                "{}: {}: not recording its checksum",
+                package,
+                err
+            ),
+        }
+    }
+    state.record_checksums(package, &checksums);
+}
+
+/// Whether cargo's `stderr` shows that it failed to fetch something, rather than to build it, so
+/// that trying again may help.
+fn is_network_error(stderr: &str) -> bool {
+    const NETWORK_ERRORS: &[&str] = &[
+        "failed to download",
+        "failed to fetch",
+        "failed to get `",
+        "failed to update registry",
+        "failed to query replaced source",
+        "failed to load source for dependency",
+        "download of config.json failed",
+        "spurious network error",
+        "network failure",
+        "couldn't resolve host",
+        "could not resolve host",
+        "connection reset",
+        "connection refused",
+        "timed out",
+        "ssl connect error",
+        "http2 framing layer",
+        "[55] failed sending data",
+        "[56] failure when receiving data",
+    ];
+    let stderr = stderr.to_lowercase();
+    if stderr.contains("could not compile") {
+        return false;
+    }
+    NETWORK_ERRORS.iter().any(|error| stderr.contains(error))
+}
+
+/// Whether cargo's `stderr` shows that no version of the package matches what was asked for.
+fn is_unmatched_version(stderr: &str) -> bool {
+    stderr
+        .lines()
+        .any(|line| line.starts_with("error: could not find `") && line.contains("with version"))
+}
+
+/// The arguments to pass to `cargo` to install `requirement`.
+fn install_args(cargo: &Cargo, requirement: &Requirement) -> Vec<String> {
+    let mut args = vec![];
+    if let Some(ref toolchain) = requirement.toolchain {
+        args.push(format!("+{}", toolchain));
+    }
+    args.push("install".to_owned());
+    if requirement.force {
+        args.push("--force".to_owned());
+    }
+    // A local crate can only be installed at whatever version it currently has.
+    if !matches!(requirement.source, Source::Path(_)) {
+        args.push("--vers".to_owned());
+        args.push(cargo_version_arg(&requirement.raw_version));
+    }
+    args.push(requirement.package.clone());
+    for bin in &requirement.bins {
+        args.push("--bin".to_owned());
+        args.push(bin.clone());
+    }
+    if !requirement.features.is_empty() {
+        args.push("--features".to_owned());
+        args.push(requirement.features.join(","));
+    }
+    if requirement.all_features {
+        args.push("--all-features".to_owned());
+    }
+    if requirement.no_default_features {
+        args.push("--no-default-features".to_owned());
+    }
+    if requirement.locked {
+        args.push("--locked".to_owned());
+    }
+    if let Some(ref profile) = requirement.profile {
+        args.push("--profile".to_owned());
+        args.push(profile.clone());
+    }
+    if let Some(ref target) = requirement.target {
+        args.push("--target".to_owned());
+        args.push(target.clone());
+    }
+    if let Some(jobs) = requirement.jobs {
+        args.push("--jobs".to_owned());
+        args.push(jobs.to_string());
+    }
+    args.extend(requirement.source.install_args());
+    args.extend(cargo.common_args());
+    if let Some(ref dir) = cargo.vendored {
+        args.extend(cargo_config::vendored_source_args(dir));
+    }
+    args.extend(requirement.extra_args.iter().cloned());
+    args
+}
+
+/// The options of `requirement` which only make sense when building from source.
+fn source_only_options(requirement: &Requirement) -> Vec<&'static str> {
+    let mut options = vec![];
+    if !requirement.features.is_empty() || requirement.all_features {
+        options.push("features");
+    }
+    if requirement.no_default_features {
+        options.push("no-default-features");
+    }
+    if requirement.profile.is_some() {
+        options.push("profile");
+    }
+    if requirement.toolchain.is_some() {
+        options.push("toolchain");
+    }
+    if let Source::Path(_) = requirement.source {
+        options.push("path");
+    }
+    options
+}
+
+fn unsupported(requirement: &Requirement, tool: &str, options: &[&str]) -> Result<(), Error> {
+    if options.is_empty() {
+        return Ok(());
+    }
+    Err(format!(
+        "Can't install {} with {}, which doesn't support {}",
+        requirement.package,
+        tool,
+        options.join(", ")
+    )
+    .into())
+}
+
+/// The arguments to pass to `cargo` to install `requirement` with `cargo binstall`. With
+/// `fallback`, cargo-binstall doesn't build from source itself, leaving that to a later backend.
+fn binstall_args(
+    cargo: &Cargo,
+    requirement: &Requirement,
+    fallback: bool,
+) -> Result<Vec<String>, String> {
+    let mut options = source_only_options(requirement);
+    if !requirement.bins.is_empty() {
+        options.insert(0, "bins");
+    }
+    if let Source::Git { ref reference, .. } = requirement.source {
+        if *reference != GitReference::DefaultBranch {
+            options.push("branch, tag or rev");
+        }
+    }
+    if cargo.offline {
+        options.push("--offline");
+    }
+    unsupported(requirement, "cargo-binstall", &options)?;
+    let mut args = vec!["binstall".to_owned(), "--no-confirm".to_owned()];
+    if requirement.force {
+        args.push("--force".to_owned());
+    }
+    args.extend(vec![
+        "--version".to_owned(),
+        cargo_version_arg(&requirement.raw_version),
+        requirement.package.clone(),
+    ]);
+    if fallback {
+        args.push("--disable-strategies".to_owned());
+        args.push("compile".to_owned());
+    }
+    if requirement.locked {
+        args.push("--locked".to_owned());
+    }
+    if let Some(ref target) = requirement.target {
+        args.push("--targets".to_owned());
+        args.push(target.clone());
+    }
+    args.extend(requirement.source.install_args());
+    if let Some(ref root) = cargo.root {
+        args.push("--root".to_owned());
+        args.push(root.display().to_string());
+    }
+    args.extend(requirement.extra_args.iter().cloned());
+    Ok(args)
+}
+
+/// `cargo install --vers` treats a bare version as exact, and rejects partial ones like `0.14`, so
+/// partial versions are passed with the implicit `^` made explicit.
+fn cargo_version_arg(raw_version: &str) -> String {
+    let raw_version = raw_version.trim();
+    let bare = raw_version.chars().all(|c| c.is_ascii_digit() || c == '.');
+    if bare && Version::parse(raw_version).is_err() {
+        format!("^{}", raw_version)
+    } else {
+        raw_version.to_owned()
+    }
+}
+
+impl Download {
+    /// The release of `requirement` which this would download.
+    fn release(&self, cargo: &Cargo, requirement: &Requirement) -> Result<Release, String> {
+        if cargo.offline {
+            return Err(format!(
+                "Can't download {} with --offline",
+                requirement.package
+            ));
+        }
+        let mut options = source_only_options(requirement);
+        if !requirement.extra_args.is_empty() {
+            options.push("extra cargo install arguments");
+        }
+        match self.backend {
+            Backend::Quickinstall => {
+                if requirement.source != Source::Registry {
+                    options.push("sources other than crates.io");
+                }
+                unsupported(requirement, "cargo-quickinstall", &options)?;
+                let version = prebuilt_version(requirement)?;
+                let url = format!(
+                    "https://github.com/cargo-bins/cargo-quickinstall/releases/download/\
+                     {package}-{version}/{package}-{version}-{target}.tar.gz",
+                    package = requirement.package,
+                    version = version,
+                    target = prebuilt_target(requirement)?
+                );
+                Ok(Release {
+                    version,
+                    url,
+                    checksum_url: None,
+                    source: format!("registry+{}", registry::CRATES_IO_GIT_INDEX),
+                })
+            }
+            Backend::GithubRelease => {
+                let github = match requirement.github {
+                    Some(ref github) => github,
+                    None => {
+                        return Err(format!(
+                            "Can't install {} from GitHub releases without a github entry in its \
+                             manifest entry",
+                            requirement.package
+                        ))
+                    }
+                };
+                unsupported(requirement, "github-release", &options)?;
+                let version = prebuilt_version(requirement)?;
+                Ok(github.release(
+                    &requirement.package,
+                    &version,
+                    &prebuilt_target(requirement)?,
+                ))
+            }
+            Backend::Source | Backend::Binstall => Err(format!(
+                "{} is built by cargo, not downloaded",
+                requirement.package
+            )),
+        }
+    }
+}
+
+/// The exact version to download for `requirement`: the newest one on crates.io which matches,
+/// unless the requirement names one.
+fn prebuilt_version(requirement: &Requirement) -> Result<Version, String> {
+    if let Ok(version) = Version::parse(requirement.raw_version.trim().trim_start_matches('=')) {
+        return Ok(version);
+    }
+    let versions = registry::fetch_versions(registry::CRATES_IO_INDEX, &requirement.package)?;
+    match registry::newest_matching(&versions, &requirement.version) {
+        Some(version) => Ok(version.clone()),
+        None => Err(format!(
+            "No version of {} matching {} is published on crates.io",
+            requirement.package, requirement.raw_version
+        )),
+    }
+}
+
+fn prebuilt_target(requirement: &Requirement) -> Result<String, Error> {
+    match requirement.target {
+        Some(ref target) => Ok(target.clone()),
+        None => Ok(prebuilt::host_target()?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        binstall_args, cargo_version_arg, describe, install_args, install_with, is_network_error,
+        is_unmatched_version, Download, Installer,
+    };
+    use backend::Backend;
+    use error::Error;
+    use format_command;
+    use requirement::Requirement;
+    use runner;
+    use semver::Version;
+    use source::{GitReference, Source};
+    use std::cell::Cell;
+    use std::path::PathBuf;
+    use std::rc::Rc;
+    use Cargo;
+
+    fn cargo() -> Cargo {
+        Cargo {
+            program: PathBuf::from("cargo"),
+            rustc_wrapper: None,
+            offline: false,
+            vendored: None,
+            retries: 0,
+            lock_timeout: None,
+            timeout: None,
+            crates_toml: PathBuf::from("/path/to/.crates.toml"),
+            root: None,
+            env: vec![],
+            runner: Rc::new(runner::System),
+            installed_sources: vec![],
+            observer: None,
+        }
+    }
+
+    /// Fails with `error`, if there is one, counting how often it's run.
+    struct Fake {
+        error: Option<&'static str>,
+        runs: Cell<usize>,
+    }
+
+    impl Installer for Fake {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        fn describe(&self, _: &Cargo, _: &Requirement) -> Result<String, Error> {
+            Ok("fake".to_owned())
+        }
+
+        fn install(&self, _: &Cargo, _: &Requirement) -> Result<(), Error> {
+            self.runs.set(self.runs.get() + 1);
+            match self.error {
+                Some(error) => Err(Error::Other(error.to_owned())),
+                None => Ok(()),
+            }
+        }
+    }
+
+    fn fake(error: Option<&'static str>) -> Box<dyn Installer> {
+        Box::new(Fake {
+            error,
+            runs: Cell::new(0),
+        })
+    }
+
+    #[test]
+    pub fn falls_back() {
+        let requirement = Requirement::new("rustfmt", "1").unwrap();
+        let install = |installers: &[Box<dyn Installer>]| {
+            install_with(&cargo(), &requirement, installers).map_err(|err| err.to_string())
+        };
+        assert_eq!(install(&[fake(Some("no binary")), fake(None)]), Ok(()));
+        assert_eq!(
+            install(&[fake(Some("compile error"))]),
+            Err("compile error".to_owned())
+        );
+        assert_eq!(
+            install(&[fake(Some("no binary")), fake(Some("compile error"))]),
+            Err("Every backend failed to install rustfmt:\n  \
+                 fake: no binary\n  \
+                 fake: compile error"
+                .to_owned())
+        );
+    }
+
+    #[test]
+    pub fn version_args() {
+        assert_eq!(cargo_version_arg("0.9.0"), "0.9.0");
+        assert_eq!(cargo_version_arg("0.14"), "^0.14");
+        assert_eq!(cargo_version_arg("1"), "^1");
+        assert_eq!(cargo_version_arg("^1.4"), "^1.4");
+        assert_eq!(cargo_version_arg("=0.9.3"), "=0.9.3");
+        assert_eq!(cargo_version_arg("*"), "*");
+    }
+
+    #[test]
+    pub fn install_command_line() {
+        let mut requirement = Requirement::new("rustfmt", ">= 0.9, < 0.10").unwrap();
+        assert_eq!(
+            format_command("cargo", &install_args(&cargo(), &requirement)),
+            "cargo install --vers '>= 0.9, < 0.10' rustfmt"
+        );
+        requirement.force = true;
+        requirement.locked = true;
+        assert_eq!(
+            format_command("cargo", &install_args(&cargo(), &requirement)),
+            "cargo install --force --vers '>= 0.9, < 0.10' rustfmt --locked"
+        );
+        let root = Cargo {
+            program: PathBuf::from("cargo"),
+            rustc_wrapper: None,
+            offline: false,
+            vendored: None,
+            retries: 0,
+            lock_timeout: None,
+            timeout: None,
+            crates_toml: PathBuf::from("/opt/tools/.crates.toml"),
+            root: Some(PathBuf::from("/opt/tools")),
+            env: vec![],
+            runner: Rc::new(runner::System),
+            installed_sources: vec![],
+            observer: None,
+        };
+        assert_eq!(
+            format_command("cargo", &install_args(&root, &requirement)),
+            "cargo install --force --vers '>= 0.9, < 0.10' rustfmt --locked --root /opt/tools"
+        );
+        requirement.toolchain = Some("nightly-2024-01-01".to_owned());
+        assert_eq!(
+            install_args(&cargo(), &requirement)[..2],
+            ["+nightly-2024-01-01", "install"]
+        );
+        requirement.toolchain = None;
+        requirement.jobs = Some(2);
+        assert_eq!(
+            format_command("cargo", &install_args(&cargo(), &requirement)),
+            "cargo install --force --vers '>= 0.9, < 0.10' rustfmt --locked --jobs 2"
+        );
+        requirement.jobs = None;
+        requirement.source = Source::Path(PathBuf::from("../rustfmt"));
+        assert_eq!(
+            format_command("cargo", &install_args(&cargo(), &requirement)),
+            "cargo install --force rustfmt --locked --path ../rustfmt"
+        );
+        let mut requirement = Requirement::new("grpcio-compiler", "0.2").unwrap();
+        requirement.source = Source::git(
+            "https://github.com/pingcap/grpc-rs.git",
+            None,
+            None,
+            Some("ccc979370c40892d58cbe3e6f478e77fb4bedd4d"),
+        )
+        .unwrap();
+        requirement.add_feature("prost-codec");
+        requirement.no_default_features = true;
+        assert_eq!(
+            format_command("cargo", &install_args(&cargo(), &requirement)),
+            "cargo install --vers ^0.2 grpcio-compiler --features prost-codec \
+             --no-default-features \
+             --git https://github.com/pingcap/grpc-rs.git \
+             --rev ccc979370c40892d58cbe3e6f478e77fb4bedd4d"
+        );
+    }
+
+    #[test]
+    pub fn binstall_command_line() {
+        let mut requirement = Requirement::new("cargo-nextest", "0.9").unwrap();
+        requirement.backends = vec![Backend::Binstall];
+        requirement.target = Some("x86_64-unknown-linux-musl".to_owned());
+        assert_eq!(
+            format_command(
+                "cargo",
+                &binstall_args(&cargo(), &requirement, false).unwrap()
+            ),
+            "cargo binstall --no-confirm --version ^0.9 cargo-nextest \
+             --targets x86_64-unknown-linux-musl"
+        );
+        requirement.force = true;
+        assert_eq!(
+            binstall_args(&cargo(), &requirement, true).unwrap()[..7],
+            [
+                "binstall",
+                "--no-confirm",
+                "--force",
+                "--version",
+                "^0.9",
+                "cargo-nextest",
+                "--disable-strategies"
+            ]
+        );
+        requirement.source = Source::Git {
+            url: "https://github.com/nextest-rs/nextest".to_owned(),
+            reference: GitReference::Tag("cargo-nextest-0.9.67".to_owned()),
+        };
+        requirement.add_feature("self-update");
+        assert_eq!(
+            binstall_args(&cargo(), &requirement, false),
+            Err(
+                "Can't install cargo-nextest with cargo-binstall, which doesn't support \
+                 features, branch, tag or rev"
+                    .to_owned()
+            )
+        );
+        requirement.backends = vec![Backend::Binstall, Backend::Source];
+        assert_eq!(
+            describe(&cargo(), &requirement).map_err(|err| err.to_string()),
+            Ok(
+                "cargo install --force --vers ^0.9 cargo-nextest --features self-update \
+                --target x86_64-unknown-linux-musl \
+                --git https://github.com/nextest-rs/nextest --tag cargo-nextest-0.9.67"
+                    .to_owned()
+            )
+        );
+    }
+
+    #[test]
+    pub fn offline() {
+        let offline = Cargo {
+            offline: true,
+            ..cargo()
+        };
+        let mut requirement = Requirement::new("cargo-nextest", "=0.9.67").unwrap();
+        requirement.backends = vec![Backend::Quickinstall, Backend::Binstall, Backend::Source];
+        assert_eq!(
+            describe(&offline, &requirement).map_err(|err| err.to_string()),
+            Ok("cargo install --vers =0.9.67 cargo-nextest --offline".to_owned())
+        );
+        requirement.backends.pop();
+        assert_eq!(
+            describe(&offline, &requirement).map_err(|err| err.to_string()),
+            Err(
+                "Can't install cargo-nextest with cargo-binstall, which doesn't support --offline"
+                    .to_owned()
+            )
+        );
+        requirement.backends.pop();
+        assert_eq!(
+            describe(&offline, &requirement).map_err(|err| err.to_string()),
+            Err("Can't download cargo-nextest with --offline".to_owned())
+        );
+    }
+
+    #[test]
+    pub fn network_errors() {
+        assert!(is_network_error(
+            "    Updating crates.io index\n\
+             error: failed to download from `https://static.crates.io/crates/ripgrep/14.1.0/download`\n\
+             \n\
+             Caused by:\n  [28] Timeout was reached (Operation timed out after 30000 milliseconds)\n"
+        ));
+        assert!(is_network_error(
+            "warning: spurious network error (3 tries remaining): [6] Could not resolve host: \
+             index.crates.io\n"
+        ));
+        assert!(!is_network_error(
+            "error[E0308]: mismatched types\n\
+             error: could not compile `ripgrep` (bin \"rg\") due to 1 previous error\n\
+             error: failed to compile `ripgrep v14.1.0`, intermediate artifacts can be found at \
+             `/tmp/cargo-install`.\n"
+        ));
+        assert!(!is_network_error(
+            "error: could not find `ripgrep` in registry `crates-io` with version `=99`\n"
+        ));
+        assert!(is_unmatched_version(
+            "    Updating crates.io index\n\
+             error: could not find `ripgrep` in registry `crates-io` with version `=99`\n"
+        ));
+        assert!(!is_unmatched_version(
+            "error: could not find `Cargo.toml` in `/tmp` or any parent directory\n"
+        ));
+    }
+
+    #[test]
+    pub fn quickinstall_urls() {
+        let mut requirement = Requirement::new("cargo-nextest", "=0.9.67").unwrap();
+        requirement.backends = vec![Backend::Quickinstall];
+        requirement.target = Some("x86_64-unknown-linux-gnu".to_owned());
+        let quickinstall = Download {
+            backend: Backend::Quickinstall,
+        };
+        let release = quickinstall.release(&cargo(), &requirement).unwrap();
+        assert_eq!(release.version, Version::parse("0.9.67").unwrap());
+        assert_eq!(
+            release.url,
+            "https://github.com/cargo-bins/cargo-quickinstall/releases/download/\
+             cargo-nextest-0.9.67/cargo-nextest-0.9.67-x86_64-unknown-linux-gnu.tar.gz"
+        );
+        requirement.locked = true;
+        requirement.extra_args = vec!["--jobs=1".to_owned()];
+        assert_eq!(
+            quickinstall.release(&cargo(), &requirement),
+            Err(
+                "Can't install cargo-nextest with cargo-quickinstall, which doesn't support \
+                 extra cargo install arguments"
+                    .to_owned()
+            )
+        );
+    }
+}
@@ -0,0 +1,147 @@
+//! JUnit XML reports, for `--report junit=PATH`, so that CI systems which only understand test
+//! reports can show which packages drifted or failed.
+
+use semver::Version;
+use std::time::Duration;
+use summary::{Decision, PackageSummary};
+
+/// A JUnit XML report with a test case for each of `packages`: those which were satisfied or
+/// installed pass, those which failed or weren't satisfied fail, and those a dry run would have
+/// changed, or whose install was declined, are skipped.
+pub fn format_junit(packages: &[PackageSummary]) -> String {
+    let failed = |package: &&PackageSummary| {
+        matches!(package.decision, Decision::Failed | Decision::Unsatisfied)
+    };
+    let skipped = |package: &&PackageSummary| {
+        matches!(
+            package.decision,
+            Decision::WouldInstall | Decision::WouldUninstall | Decision::Declined
+        )
+    };
+    let failures = packages.iter().filter(failed).count();
+    let time = seconds(packages.iter().map(|package| package.duration).sum());
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites name=\"cargo-ensure-installed\" tests=\"{}\" failures=\"{}\" time=\"{}\">\n",
+        packages.len(),
+        failures,
+        time
+    ));
+    xml.push_str(&format!(
+        "  <testsuite name=\"cargo-ensure-installed\" tests=\"{}\" failures=\"{}\" errors=\"0\" \
+         skipped=\"{}\" time=\"{}\">\n",
+        packages.len(),
+        failures,
+        packages.iter().filter(skipped).count(),
+        time
+    ));
+    for package in packages {
+        xml.push_str(&format!(
+            "    <testcase name=\"{}\" classname=\"cargo-ensure-installed\" time=\"{}\">\n",
+            escape(&package.package),
+            seconds(package.duration)
+        ));
+        if failed(&package) {
+            xml.push_str(&format!(
+                "      <failure message=\"{}\"/>\n",
+                escape(package.error.as_deref().unwrap_or(package.decision.name()))
+            ));
+        } else {
+            if skipped(&package) {
+                xml.push_str("      <skipped/>\n");
+            }
+            xml.push_str(&format!(
+                "      <system-out>{}</system-out>\n",
+                escape(&describe(package))
+            ));
+        }
+        xml.push_str("    </testcase>\n");
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+/// What was done to `package`, like `upgraded from 13.0.0 to 14.1.0 to satisfy ^14`, or
+/// `would-install from none to none to satisfy ^14` for a dry run.
+fn describe(package: &PackageSummary) -> String {
+    let version = |version: &Option<Version>| match *version {
+        Some(ref version) => version.to_string(),
+        None => "none".to_owned(),
+    };
+    let mut what = format!(
+        "{} from {} to {}",
+        package.decision.name(),
+        version(&package.before),
+        version(&package.after)
+    );
+    if let Some(ref requirement) = package.requirement {
+        what.push_str(&format!(" to satisfy {}", requirement));
+    }
+    what
+}
+
+fn seconds(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64())
+}
+
+/// `text` escaped for XML text or attribute values.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_junit;
+    use semver::Version;
+    use std::time::Duration;
+    use summary::{Decision, PackageSummary};
+
+    #[test]
+    pub fn formats_reports() {
+        let packages = [
+            PackageSummary {
+                package: "ripgrep".to_owned(),
+                requirement: Some("^14".to_owned()),
+                decision: Decision::Upgraded,
+                reason: None,
+                before: Some(Version::parse("13.0.0").unwrap()),
+                after: Some(Version::parse("14.1.0").unwrap()),
+                duration: Duration::from_millis(1500),
+                install_duration: None,
+                backend: None,
+                error: None,
+            },
+            PackageSummary {
+                package: "cargo-deny".to_owned(),
+                requirement: Some(">=99, <100".to_owned()),
+                decision: Decision::Failed,
+                reason: None,
+                before: None,
+                after: None,
+                duration: Duration::from_millis(250),
+                install_duration: None,
+                backend: None,
+                error: Some("No published version of cargo-deny matches >=99, <100".to_owned()),
+            },
+        ];
+        assert_eq!(
+            format_junit(&packages),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="cargo-ensure-installed" tests="2" failures="1" time="1.750">
+  <testsuite name="cargo-ensure-installed" tests="2" failures="1" errors="0" skipped="0" time="1.750">
+    <testcase name="ripgrep" classname="cargo-ensure-installed" time="1.500">
+      <system-out>upgraded from 13.0.0 to 14.1.0 to satisfy ^14</system-out>
+    </testcase>
+    <testcase name="cargo-deny" classname="cargo-ensure-installed" time="0.250">
+      <failure message="No published version of cargo-deny matches &gt;=99, &lt;100"/>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+}
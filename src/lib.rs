@@ -0,0 +1,2479 @@
+//! Like `cargo install`, but if a suitable version is already installed, leaves it as-is.
+//!
+//! This is the library behind `cargo ensure-installed`, for tools like an `xtask` which want to
+//! make sure a package is installed without running the command:
+//!
+//! ```no_run
+//! use cargo_ensure_installed::{ensure_installed, Options, Outcome};
+//!
+//! match ensure_installed("cargo-deny", "^0.14", &Options::default()) {
+//!     Ok(Outcome::AlreadySatisfied { installed_version }) => {
+//!         println!("cargo-deny {} was already installed", installed_version)
+//!     }
+//!     Ok(Outcome::Installed { version }) => println!("installed cargo-deny {}", version),
+//!     Ok(Outcome::Upgraded { from, to }) => println!("upgraded cargo-deny from {} to {}", from, to),
+//!     Err(err) => panic!("{}", err),
+//! }
+//! ```
+
+extern crate clap;
+extern crate indicatif;
+#[cfg(unix)]
+extern crate libc;
+extern crate regex;
+extern crate semver;
+extern crate serde_json;
+extern crate serde_yaml;
+#[cfg(unix)]
+extern crate signal_hook;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+extern crate toml;
+#[macro_use]
+extern crate tracing;
+
+#[macro_use]
+mod output;
+
+mod backend;
+mod backup;
+mod cargo_config;
+mod cfg;
+mod cli;
+mod commands;
+mod crates2;
+mod crates_toml;
+mod detect;
+mod ensure;
+mod error;
+mod event;
+mod executable;
+mod github;
+mod installed;
+mod installer;
+mod junit;
+mod local;
+mod lock;
+mod lockfile;
+mod log;
+mod manifest;
+mod plan;
+mod prebuilt;
+mod progress;
+mod prompt;
+mod registry;
+mod requirement;
+mod runner;
+mod signals;
+mod source;
+mod state;
+mod summary;
+mod table;
+mod teamcity;
+mod timings;
+mod workspace;
+
+pub use ensure::{ensure_installed, ensure_installed_or_panic, EnsureInstalled, Options, Outcome};
+#[cfg(feature = "tokio")]
+pub use ensure::{ensure_installed_async, Ensuring};
+pub use error::Error;
+pub use event::{Event, InstallReason, Observer};
+pub use installed::{
+    Crates2Json, CratesToml, InstalledCrates, InstalledPackage, InstalledSource, StateFile,
+};
+pub use semver::{Version, VersionReq};
+
+use crates2::InstallInfo;
+use requirement::Requirement;
+use source::Source;
+use state::{Build, State};
+use std::cmp::Reverse;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+use toml::Value;
+
+/// The exit code for failures which don't have a code of their own, or when several packages
+/// failed for different reasons.
+pub const EXIT_FAILURE: i32 = 1;
+/// The exit code when packages couldn't be installed with `--offline`.
+pub const EXIT_OFFLINE: i32 = 3;
+/// The exit code when a version requirement is invalid, or nothing published matches it.
+pub const EXIT_UNSATISFIABLE: i32 = 4;
+/// The exit code when cargo failed.
+pub const EXIT_CARGO: i32 = 5;
+/// The exit code when cargo was killed for taking longer than `--lock-timeout` or `--timeout`.
+pub const EXIT_TIMEOUT: i32 = 6;
+/// The exit code when packages aren't satisfied, and weren't to be installed: for `check`,
+/// `--frozen` and declined installs.
+pub const EXIT_UNSATISFIED: i32 = 7;
+/// The exit code with `--detailed-exit-codes` when everything is now satisfied, but something had
+/// to be installed, upgraded or uninstalled to get there.
+pub const EXIT_CHANGED: i32 = 10;
+
+/// An error, and the code to exit with because of it.
+pub struct Failure {
+    pub message: String,
+    pub code: i32,
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Failure {
+        Failure {
+            message,
+            code: EXIT_FAILURE,
+        }
+    }
+}
+
+impl From<Error> for Failure {
+    fn from(error: Error) -> Failure {
+        Failure {
+            message: error_message(&error),
+            code: exit_code(&error),
+        }
+    }
+}
+
+/// The code to exit with because of `error`.
+fn exit_code(error: &Error) -> i32 {
+    match *error {
+        Error::InvalidVersionReq { .. } | Error::NoMatchingVersion { .. } => EXIT_UNSATISFIABLE,
+        Error::Cargo { .. } => EXIT_CARGO,
+        Error::LockTimeout { .. } | Error::Timeout { .. } => EXIT_TIMEOUT,
+        Error::Interrupted { signal } => signals::exit_code(signal),
+        Error::Declined { .. } => EXIT_UNSATISFIED,
+        Error::AllBackendsFailed { ref failures, .. } => {
+            let mut codes = failures.iter().map(|(_, error)| exit_code(error));
+            match codes.next() {
+                Some(code) if codes.all(|other| other == code) => code,
+                _ => EXIT_FAILURE,
+            }
+        }
+        _ => EXIT_FAILURE,
+    }
+}
+
+/// Describes `error` for the command line, with what to do about it, where that depends on our
+/// flags.
+fn error_message(error: &Error) -> String {
+    let remedy = match *error {
+        Error::UnsupportedCratesToml { .. } => {
+            "Upgrade cargo-ensure-installed, or pass --lenient to treat nothing as installed."
+        }
+        Error::InvalidEntry { .. } => "Pass --lenient to skip entries like this one.",
+        Error::LockTimeout { .. } => "Pass a longer --lock-timeout to wait longer.",
+        Error::Timeout { .. } => "Pass a longer --timeout to wait longer.",
+        _ => return error.to_string(),
+    };
+    format!("{}. {}", error, remedy)
+}
+
+/// Runs `cargo ensure-installed` with the process's command line arguments.
+#[doc(hidden)]
+pub fn run_cli() -> Result<(), Failure> {
+    let cli = cli::parse();
+    output::set_verbosity(if cli.quiet {
+        output::QUIET
+    } else {
+        cli.verbose as isize
+    });
+    crates_toml::set_lenient(cli.lenient);
+    output::set_message_format(cli.message_format);
+    output::init_color();
+    github::init();
+    if cli.output == Some(cli::Output::Teamcity) {
+        teamcity::init();
+    }
+    let root = match cli.root {
+        Some(ref root) => root.clone(),
+        None => cargo_home(std::env::var_os("CARGO_HOME"), home_dir())?,
+    };
+    let mut sinks = vec![];
+    match std::env::var("RUST_LOG") {
+        Ok(ref directives) if !directives.is_empty() => match log::Filter::parse(directives) {
+            Ok(filter) => sinks.push(log::Sink::stderr(
+                cli.log_format.unwrap_or(cli::LogFormat::Human),
+                filter,
+            )),
+            Err(err) => verbose!(0, "Ignoring RUST_LOG: {}", err),
+        },
+        _ => {
+            if let Some(format) = cli.log_format {
+                sinks.push(log::Sink::stderr(
+                    format,
+                    log::Filter::level(tracing::Level::INFO),
+                ));
+            }
+        }
+    }
+    let args: Vec<String> = std::env::args().collect();
+    match cli.log_file {
+        Some(ref path) => match log::Sink::file(path, &args) {
+            Ok(sink) => sinks.push(sink),
+            Err(err) => return Err(format!("Error opening log file {:?}: {:?}", path, err).into()),
+        },
+        // There is nowhere to log to until something is installed.
+        None if root.is_dir() => {
+            let path = root.join(".cargo-ensure-installed.log");
+            match log::Sink::file(&path, &args) {
+                Ok(sink) => sinks.push(sink),
+                Err(err) => verbose!(output::VERBOSE, "Not logging to {:?}: {:?}", path, err),
+            }
+        }
+        None => {}
+    }
+    log::init(sinks);
+    if let Err(err) = signals::install() {
+        verbose!(output::VERBOSE, "Not handling Ctrl-C: {:?}", err);
+    }
+
+    debug!(
+        root = %root.display(),
+        cargo_home = ?std::env::var_os("CARGO_HOME"),
+        "resolved install root"
+    );
+    if let Some(ref wrapper) = cli.rustc_wrapper {
+        if find_program(wrapper, std::env::var_os("PATH")).is_none() {
+            return Err(format!("The RUSTC_WRAPPER {} was not found", wrapper.display()).into());
+        }
+    }
+    let vendored = match cli.vendored {
+        Some(ref dir) => match dir.canonicalize() {
+            Ok(dir) => Some(dir),
+            Err(err) => return Err(format!("Error finding {:?}: {:?}", dir, err).into()),
+        },
+        None => None,
+    };
+    let cargo = Cargo {
+        program: cargo_program(cli.cargo_path.clone(), std::env::var_os("CARGO")),
+        rustc_wrapper: cli.rustc_wrapper.clone(),
+        offline: cli.offline,
+        vendored,
+        retries: cli.retries,
+        lock_timeout: cli.lock_timeout.map(Duration::from_secs),
+        timeout: cli.timeout.map(Duration::from_secs),
+        crates_toml: root.join(".crates.toml"),
+        root: cli.root.clone(),
+        env: vec![],
+        runner: Rc::new(runner::System),
+        installed_sources: vec![],
+        observer: {
+            let json_events = cli.message_format == cli::MessageFormat::JsonEvents;
+            let observe = move |event: Event| {
+                progress::event(&event);
+                github::event(&event);
+                teamcity::event(&event);
+                summary::event(&event);
+                timings::event(&event);
+                if json_events {
+                    println!("{}", event.to_json());
+                }
+            };
+            Some(Arc::new(observe))
+        },
+    };
+
+    debug!(program = %cargo.program.display(), "resolved cargo");
+
+    let result = match cli.command {
+        None => commands::ensure(&cargo, &cli.ensure),
+        Some(cli::Command::Ensure(ref args)) => commands::ensure(&cargo, args),
+        Some(cli::Command::Check(ref args)) => commands::check(&cargo, args),
+        Some(cli::Command::List(ref args)) => commands::list(&cargo, args),
+        Some(cli::Command::Outdated(ref args)) => commands::outdated(&cargo, args),
+        Some(cli::Command::Prune(ref args)) => commands::prune(&cargo, args),
+        Some(cli::Command::Sync(ref args)) => commands::sync(&cargo, args),
+        Some(cli::Command::Update(ref args)) => commands::update(&cargo, args),
+    };
+    match signals::interrupted() {
+        Some(signal) => Err(Failure {
+            message: format!("Interrupted by {}", signals::name(signal)),
+            code: signals::exit_code(signal),
+        }),
+        None => result,
+    }
+}
+
+/// How to run cargo, and where it installs packages.
+struct Cargo {
+    /// The cargo binary to run.
+    pub program: PathBuf,
+    /// What cargo builds packages with as `RUSTC_WRAPPER`, if anything other than the
+    /// environment's.
+    pub rustc_wrapper: Option<PathBuf>,
+    /// Whether cargo runs without network access.
+    pub offline: bool,
+    /// A local copy of crates.io to install packages from, instead of the network.
+    pub vendored: Option<PathBuf>,
+    /// How many times to retry cargo when it fails because of the network.
+    pub retries: u32,
+    /// How long to wait for another cargo process to release a lock cargo needs, or `None` to
+    /// wait as long as it takes.
+    pub lock_timeout: Option<Duration>,
+    /// How long an install may run for before cargo is killed, or `None` for as long as it takes.
+    pub timeout: Option<Duration>,
+    /// `.crates.toml` in the install root, which records what is installed.
+    pub crates_toml: PathBuf,
+    /// The install root to pass to cargo, if it isn't `$CARGO_HOME`.
+    pub root: Option<PathBuf>,
+    /// Environment variables to set when running cargo, or with `None`, to remove.
+    pub env: Vec<(String, Option<OsString>)>,
+    /// What runs cargo: a fake in tests.
+    pub runner: Rc<dyn runner::Runner>,
+    /// Other records of what is installed, whose packages are left as-is if they satisfy their
+    /// requirements.
+    pub installed_sources: Vec<Arc<dyn InstalledSource>>,
+    /// What to tell about progress, if anything.
+    pub observer: Option<Arc<dyn Observer>>,
+}
+
+/// Where cargo installs packages by default: `$CARGO_HOME`, or `.cargo` in the user's `home`
+/// directory, as cargo itself decides.
+fn cargo_home(cargo_home_env: Option<OsString>, home: Option<PathBuf>) -> Result<PathBuf, String> {
+    match (cargo_home_env, home) {
+        (Some(ref env), _) if !env.is_empty() => Ok(PathBuf::from(env)),
+        (_, Some(home)) => Ok(home.join(".cargo")),
+        (_, None) => Err(
+            "Couldn't find where cargo installs packages: CARGO_HOME isn't set, and there's no \
+             home directory to default to. Set CARGO_HOME or pass --root."
+                .to_owned(),
+        ),
+    }
+}
+
+/// The user's home directory: `%USERPROFILE%` on Windows, and `$HOME` elsewhere.
+fn home_dir() -> Option<PathBuf> {
+    let var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+    match std::env::var_os(var) {
+        Some(ref home) if !home.is_empty() => Some(PathBuf::from(home)),
+        _ => None,
+    }
+}
+
+/// The cargo to run: `--cargo-path` if given, and otherwise `$CARGO`, which cargo sets for the
+/// subcommands it runs, so that `cargo +nightly ensure-installed` keeps using that cargo.
+fn cargo_program(cargo_path: Option<PathBuf>, cargo_env: Option<OsString>) -> PathBuf {
+    match (cargo_path, cargo_env) {
+        (Some(path), _) => path,
+        (None, Some(ref env)) if !env.is_empty() => PathBuf::from(env),
+        (None, _) => PathBuf::from("cargo"),
+    }
+}
+
+/// Where `program` would be run from: itself if it's a path, and otherwise the first match in
+/// `search_path`.
+fn find_program(program: &Path, search_path: Option<OsString>) -> Option<PathBuf> {
+    find_programs(program, search_path).into_iter().next()
+}
+
+/// Every copy of `program` which could be run from `search_path`, in the order they're searched:
+/// just itself if it's a path.
+fn find_programs(program: &Path, search_path: Option<OsString>) -> Vec<PathBuf> {
+    if program.components().count() > 1 {
+        return if program.is_file() {
+            vec![program.to_owned()]
+        } else {
+            vec![]
+        };
+    }
+    let search_path = match search_path {
+        Some(search_path) => search_path,
+        None => return vec![],
+    };
+    std::env::split_paths(&search_path)
+        .flat_map(|dir| {
+            let candidate = dir.join(program);
+            let mut exe = candidate.clone().into_os_string();
+            exe.push(std::env::consts::EXE_SUFFIX);
+            vec![candidate, PathBuf::from(exe)]
+        })
+        .filter(|candidate| candidate.is_file())
+        .collect()
+}
+
+impl Cargo {
+    /// A command running cargo, with the environment it should build in.
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        if let Some(ref wrapper) = self.rustc_wrapper {
+            command.env("RUSTC_WRAPPER", wrapper);
+        }
+        for (name, value) in &self.env {
+            match *value {
+                Some(ref value) => command.env(name, value),
+                None => command.env_remove(name),
+            };
+        }
+        command
+    }
+
+    /// `args` as a command line for running cargo, for messages.
+    fn command_line(&self, args: &[String]) -> String {
+        let line = format_command(&self.program.to_string_lossy(), args);
+        match self.rustc_wrapper {
+            Some(ref wrapper) => format!("RUSTC_WRAPPER={} {}", wrapper.display(), line),
+            None => line,
+        }
+    }
+
+    /// Tells the observer, if there is one, about the event `event` makes, and logs it.
+    fn notify<F: FnOnce() -> Event>(&self, event: F) {
+        if self.observer.is_none() && !tracing::dispatcher::has_been_set() {
+            return;
+        }
+        let event = event();
+        event.log();
+        if let Some(ref observer) = self.observer {
+            observer.event(event);
+        }
+    }
+
+    /// Tells the observer, if there is one, that cargo wrote `line` to stderr for `package`.
+    fn notify_line(&self, package: &str, line: &str) {
+        self.notify(|| Event::StderrLine {
+            package: package.to_owned(),
+            line: line.to_owned(),
+        });
+    }
+
+    /// Where installed binaries are placed.
+    fn bin_dir(&self) -> PathBuf {
+        self.crates_toml.with_file_name("bin")
+    }
+
+    /// Arguments to pass to every `cargo install` or `cargo uninstall`.
+    fn common_args(&self) -> Vec<String> {
+        let mut args = vec![];
+        if let Some(ref root) = self.root {
+            args.push("--root".to_owned());
+            args.push(root.display().to_string());
+        }
+        if self.offline {
+            args.push("--offline".to_owned());
+        }
+        args.extend(cargo_verbosity_arg());
+        args
+    }
+}
+
+/// Installs `requirement` if needed, returning what was done.
+///
+/// With `dry_run`, prints the install command instead of running it, and returns `None` if an
+/// install would have been needed. Otherwise, installs only if `confirm` agrees.
+fn ensure_package(
+    cargo: &Cargo,
+    requirement: &Requirement,
+    dry_run: bool,
+    confirm: &mut prompt::Confirm,
+) -> Result<Option<Outcome>, Error> {
+    let crates_toml = &cargo.crates_toml;
+    let span = info_span!(
+        "ensure",
+        package = %requirement.package,
+        version_req = %requirement.raw_version
+    );
+    let _entered = span.enter();
+    cargo.notify(|| Event::CheckStarted {
+        package: requirement.package.clone(),
+    });
+    // Held until we return, so that another process installing the package finishes before we
+    // check it, and we finish before it checks.
+    let _lock = if dry_run {
+        None
+    } else {
+        let waiting = || {
+            verbose!(
+                0,
+                "{}: waiting for another process to finish installing it",
+                requirement.package
+            )
+        };
+        match lock::lock(crates_toml, &requirement.package, &waiting) {
+            Ok(lock) => Some(lock),
+            Err(err) => {
+                verbose!(
+                    output::VERBOSE,
+                    "{}: {}: continuing without a lock",
+                    requirement.package,
+                    err
+                );
+                None
+            }
+        }
+    };
+    let contents = read_crates_toml(crates_toml)?;
+    if installed_version(crates_toml, &contents, &requirement.package)?.is_none() {
+        if let Some((source, version)) = recorded_elsewhere(cargo, requirement)? {
+            verbose!(
+                output::VERBOSE,
+                "{}: {} records {}, which satisfies {}: not installing",
+                requirement.package,
+                source,
+                version,
+                requirement.raw_version
+            );
+            cargo.notify(|| Event::Decided {
+                package: requirement.package.clone(),
+                installed: Some(version.clone()),
+                reason: None,
+            });
+            return Ok(Some(Outcome::AlreadySatisfied {
+                installed_version: version,
+            }));
+        }
+        if let Some((path, version)) = detect::detected_version(requirement, &cargo.bin_dir())? {
+            verbose!(
+                output::VERBOSE,
+                "{}: {} reports {}, which satisfies {}: not installing",
+                requirement.package,
+                path.display(),
+                version,
+                requirement.raw_version
+            );
+            cargo.notify(|| Event::Decided {
+                package: requirement.package.clone(),
+                installed: Some(version.clone()),
+                reason: None,
+            });
+            return Ok(Some(Outcome::AlreadySatisfied {
+                installed_version: version,
+            }));
+        }
+    }
+    let untracked = match installed_version(crates_toml, &contents, &requirement.package)? {
+        Some(_) => vec![],
+        None => untracked_binaries(&cargo.bin_dir(), requirement),
+    };
+    let mut reason = should_install(
+        crates_toml,
+        &contents,
+        &requirement.package,
+        &requirement.version,
+    )?;
+    if !untracked.is_empty() {
+        // Reinstalling with --force brings them back in sync with cargo's records.
+        reason = Some(InstallReason::Untracked(untracked.clone()));
+    }
+    if reason.is_none() {
+        if let Some(mismatch) = install_mismatch(crates_toml, &contents, requirement, &cargo.env)? {
+            debug!(mismatch = %mismatch, "installed differently from how it was asked for");
+            reason = Some(InstallReason::InstalledDifferently(mismatch));
+        }
+    }
+    let installed = installed_version(crates_toml, &contents, &requirement.package)?;
+    cargo.notify(|| Event::Decided {
+        package: requirement.package.clone(),
+        installed: installed.clone(),
+        reason: reason.clone(),
+    });
+    if reason.is_some() {
+        // Only replacing something needs `--force`, which could otherwise overwrite another
+        // package's binaries of the same name.
+        let mut requirement = requirement.clone();
+        requirement.force |= installed.is_some() || !untracked.is_empty();
+        let requirement = &requirement;
+        if dry_run {
+            human!("{}", installer::describe(cargo, requirement)?);
+            return Ok(None);
+        }
+        let question = match installed.as_ref() {
+            Some(version) => format!(
+                "Install {} {} (replacing {})?",
+                requirement.package, requirement.raw_version, version
+            ),
+            None if !untracked.is_empty() => format!(
+                "Install {} {} (replacing untracked {})?",
+                requirement.package,
+                requirement.raw_version,
+                untracked.join(", ")
+            ),
+            None => format!(
+                "Install {} {}?",
+                requirement.package, requirement.raw_version
+            ),
+        };
+        if !confirm.confirm(&question)? {
+            return Err(Error::Declined {
+                package: requirement.package.clone(),
+            });
+        }
+        // Until the old binaries are restored or discarded, a signal must let us finish first.
+        let _critical = signals::critical();
+        let backup = match installed {
+            Some(_) => {
+                let bins = installed_binaries(crates_toml, &contents, &requirement.package)?;
+                match backup::back_up(&cargo.bin_dir(), &requirement.package, &bins) {
+                    Ok(backup) => Some(backup),
+                    Err(err) => {
+                        verbose!(
+                            output::VERBOSE,
+                            "{}: {}: reinstalling without a backup",
+                            requirement.package,
+                            err
+                        );
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+        cargo.notify(|| Event::InstallStarted {
+            package: requirement.package.clone(),
+            version_req: requirement.raw_version.clone(),
+        });
+        let result = installer::install(cargo, requirement);
+        match (backup, &result) {
+            (Some(backup), Ok(())) => backup.discard(),
+            (Some(backup), Err(_)) => match backup.restore() {
+                Ok(()) => verbose!(
+                    0,
+                    "{}: install failed: restored the previously installed binaries",
+                    requirement.package
+                ),
+                Err(err) => verbose!(0, "{}: {}", requirement.package, err),
+            },
+            (None, _) => {}
+        }
+        let result = result.and_then(|()| {
+            let contents = read_crates_toml(crates_toml)?;
+            installed_version(crates_toml, &contents, &requirement.package)?.ok_or_else(|| {
+                Error::Unrecorded {
+                    package: requirement.package.clone(),
+                }
+            })
+        });
+        cargo.notify(|| Event::InstallFinished {
+            package: requirement.package.clone(),
+            result: match result {
+                Ok(ref version) => Ok(Some(version.clone())),
+                Err(ref err) => Err(err.to_string()),
+            },
+        });
+        let version = result?;
+        return Ok(Some(match installed {
+            Some(from) => Outcome::Upgraded { from, to: version },
+            None => Outcome::Installed { version },
+        }));
+    }
+    Ok(installed.map(|installed_version| Outcome::AlreadySatisfied { installed_version }))
+}
+
+/// The newest version of `requirement`'s package which satisfies it in any of `cargo`'s other
+/// records of what is installed, along with which record that was.
+fn recorded_elsewhere(
+    cargo: &Cargo,
+    requirement: &Requirement,
+) -> Result<Option<(String, Version)>, Error> {
+    let mut newest: Option<(String, Version)> = None;
+    for source in &cargo.installed_sources {
+        for package in source.installed()? {
+            if package.name != requirement.package || !requirement.version.matches(&package.version)
+            {
+                continue;
+            }
+            if newest.as_ref().is_none_or(|(_, v)| package.version > *v) {
+                newest = Some((source.describe(), package.version));
+            }
+        }
+    }
+    Ok(newest)
+}
+
+/// The state of a required package according to `.crates.toml`.
+#[derive(Debug, PartialEq)]
+enum PackageStatus {
+    /// Installed at a version satisfying the requirement.
+    Satisfied(Version),
+    /// Installed, but at a version which doesn't satisfy the requirement.
+    Mismatched(Version),
+    Missing,
+}
+
+impl PackageStatus {
+    fn name(&self) -> &'static str {
+        match *self {
+            PackageStatus::Satisfied(_) => "satisfied",
+            PackageStatus::Mismatched(_) => "mismatched",
+            PackageStatus::Missing => "missing",
+        }
+    }
+
+    fn installed_version(&self) -> Option<&Version> {
+        match *self {
+            PackageStatus::Satisfied(ref version) | PackageStatus::Mismatched(ref version) => {
+                Some(version)
+            }
+            PackageStatus::Missing => None,
+        }
+    }
+}
+
+/// The status of `requirement`'s package, for commands which run cargo in our own environment.
+fn package_status(
+    crates_toml: &Path,
+    contents: &str,
+    requirement: &Requirement,
+) -> Result<PackageStatus, Error> {
+    match installed_version(crates_toml, contents, &requirement.package)? {
+        Some(version) => {
+            if requirement.version.matches(&version)
+                && install_mismatch(crates_toml, contents, requirement, &[])?.is_none()
+            {
+                Ok(PackageStatus::Satisfied(version))
+            } else {
+                Ok(PackageStatus::Mismatched(version))
+            }
+        }
+        None => {
+            let bin_dir = crates_toml.with_file_name("bin");
+            match detect::detected_version(requirement, &bin_dir)? {
+                Some((_, version)) => Ok(PackageStatus::Satisfied(version)),
+                None => Ok(PackageStatus::Missing),
+            }
+        }
+    }
+}
+
+/// The installed version of `requirement`'s package, if it no longer satisfies the requirement, so
+/// `prune` should uninstall it. Packages whose version satisfies it are kept even if they were
+/// installed differently, such as from another source, since `ensure` reinstalls those instead.
+fn prunable_version(
+    crates_toml: &Path,
+    contents: &str,
+    requirement: &Requirement,
+) -> Result<Option<Version>, Error> {
+    Ok(
+        installed_version(crates_toml, contents, &requirement.package)?
+            .filter(|version| !requirement.version.matches(version)),
+    )
+}
+
+/// Describes why `requirement` is unsatisfied, or returns `None` if it is satisfied.
+fn unsatisfied_reason(
+    crates_toml: &Path,
+    contents: &str,
+    requirement: &Requirement,
+) -> Result<Option<String>, Error> {
+    match package_status(crates_toml, contents, requirement)? {
+        PackageStatus::Satisfied(_) => Ok(None),
+        PackageStatus::Mismatched(ref version) if requirement.version.matches(version) => {
+            let mismatch = install_mismatch(crates_toml, contents, requirement, &[])?;
+            Ok(Some(format!(
+                "installed {}: {}",
+                version,
+                mismatch.unwrap_or_default()
+            )))
+        }
+        PackageStatus::Mismatched(version) => Ok(Some(format!(
+            "installed {} does not satisfy {}",
+            version, requirement.raw_version
+        ))),
+        PackageStatus::Missing => {
+            let bin_dir = crates_toml.with_file_name("bin");
+            let untracked = untracked_binaries(&bin_dir, requirement);
+            if untracked.is_empty() {
+                return Ok(Some(format!(
+                    "not installed (want {})",
+                    requirement.raw_version
+                )));
+            }
+            Ok(Some(format!(
+                "not recorded in .crates.toml (want {}), but {} is in {}; ensure reinstalls it \
+                 with --force to bring them back in sync",
+                requirement.raw_version,
+                untracked.join(", "),
+                bin_dir.display()
+            )))
+        }
+    }
+}
+
+fn read_crates_toml(crates_toml: &Path) -> Result<String, Error> {
+    if crates_toml.exists() {
+        match read_file_to_string(crates_toml) {
+            Ok(s) => Ok(s),
+            Err(error) => Err(Error::Read {
+                path: crates_toml.to_owned(),
+                error,
+            }),
+        }
+    } else {
+        Ok(String::new())
+    }
+}
+
+/// Passes our verbosity on to cargo.
+fn cargo_verbosity_arg() -> Option<String> {
+    match output::verbosity() {
+        v if v <= output::QUIET => Some("--quiet".to_owned()),
+        v if v >= output::VERY_VERBOSE => Some("--verbose".to_owned()),
+        _ => None,
+    }
+}
+
+/// Formats a command line for display, quoting arguments as a POSIX shell would need.
+fn format_command(program: &str, args: &[String]) -> String {
+    let mut line = program.to_owned();
+    for arg in args {
+        line.push(' ');
+        let plain = !arg.is_empty()
+            && arg
+                .chars()
+                .all(|c| c.is_alphanumeric() || "-_.,/:=@+^%".contains(c));
+        if plain {
+            line.push_str(arg);
+        } else {
+            line.push('\'');
+            line.push_str(&arg.replace('\'', "'\\''"));
+            line.push('\'');
+        }
+    }
+    line
+}
+
+fn uninstall(cargo: &Cargo, package: &str) -> Result<(), Error> {
+    let span = info_span!("uninstall", package = %package);
+    let _entered = span.enter();
+    let bins = State::update(&cargo.crates_toml, |state| {
+        state.forget_build(package);
+        state.forget(package)
+    })?;
+    if let Some(bins) = bins {
+        let contents = read_crates_toml(&cargo.crates_toml)?;
+        let prefix = format!("{} ", package);
+        let cargo_installed = crates_toml::installed(&cargo.crates_toml, &contents)?
+            .keys()
+            .any(|key| key.starts_with(&prefix));
+        if !cargo_installed {
+            for bin in bins {
+                let path = cargo.bin_dir().join(bin);
+                verbose!(output::VERBOSE, "Removing {:?}", path);
+                if let Err(err) = std::fs::remove_file(&path) {
+                    if err.kind() != std::io::ErrorKind::NotFound {
+                        return Err(Error::Other(format!(
+                            "Error removing {:?}: {:?}",
+                            path, err
+                        )));
+                    }
+                }
+            }
+            return Ok(());
+        }
+    }
+    let mut args = vec!["uninstall".to_owned(), package.to_owned()];
+    args.extend(cargo.common_args());
+    verbose!(output::VERBOSE, "Running {}", cargo.command_line(&args));
+    info!(command = %cargo.command_line(&args), "running cargo");
+    let exit = cargo.runner.run(
+        cargo.command().args(args),
+        package,
+        cargo.lock_timeout,
+        None,
+        &|line| cargo.notify_line(package, line),
+    )?;
+    if !exit.success() {
+        return Err(Error::Cargo {
+            package: package.to_owned(),
+            subcommand: "uninstall".to_owned(),
+            code: exit.code,
+        });
+    }
+    Ok(())
+}
+
+/// Why the package needs installing to satisfy `want_version`, judging only by the version
+/// recorded in `.crates.toml`, or `None` if it doesn't.
+fn should_install(
+    crates_toml_path: &Path,
+    crates_toml_contents: &str,
+    package: &str,
+    want_version: &VersionReq,
+) -> Result<Option<InstallReason>, Error> {
+    match installed_version(crates_toml_path, crates_toml_contents, package)? {
+        Some(have_version) => {
+            let satisfied = want_version.matches(&have_version);
+            debug!(
+                package = %package,
+                installed = %have_version,
+                version_req = %want_version,
+                satisfied,
+                "compared versions"
+            );
+            if satisfied {
+                verbose!(
+                    output::VERBOSE,
+                    "{}: installed {} satisfies {}: ok",
+                    package,
+                    have_version,
+                    want_version
+                );
+                return Ok(None);
+            }
+            Ok(Some(InstallReason::Unsatisfied {
+                installed: have_version,
+                version_req: want_version.clone(),
+            }))
+        }
+        None => {
+            debug!(package = %package, version_req = %want_version, "not installed");
+            Ok(Some(InstallReason::NotInstalled))
+        }
+    }
+}
+
+fn installed_version(
+    crates_toml_path: &Path,
+    crates_toml_contents: &str,
+    package: &str,
+) -> Result<Option<Version>, Error> {
+    let installed = installed_crates(crates_toml_path, crates_toml_contents)?;
+    Ok(installed.version(package).cloned())
+}
+
+/// Parses `key` from the `.crates.toml` at `crates_toml_path`.
+fn parse_key(crates_toml_path: &Path, key: &str) -> Result<crates_toml::Key, Error> {
+    crates_toml::Key::parse(key).map_err(|problem| Error::InvalidEntry {
+        path: crates_toml_path.to_owned(),
+        key: key.to_owned(),
+        problem,
+    })
+}
+
+/// Describes how the installed copy of `requirement`'s package differs from the way it asks to be
+/// installed, other than by version, or returns `None` if it doesn't. `env` is what `Cargo::env`
+/// changes in cargo's environment, for comparing the `RUSTFLAGS` it would build with.
+fn install_mismatch(
+    crates_toml_path: &Path,
+    crates_toml_contents: &str,
+    requirement: &Requirement,
+    env: &[(String, Option<OsString>)],
+) -> Result<Option<String>, Error> {
+    let table = installed_table(crates_toml_path, crates_toml_contents)?;
+    let installed = match package_key(&table, &requirement.package) {
+        Some(key) => key,
+        None => return Ok(Some("not installed".to_owned())),
+    };
+    let key = parse_key(crates_toml_path, &installed)?;
+    let source = key.source.as_str();
+    if !requirement.any_source {
+        let wanted = match requirement.source {
+            Source::NamedRegistry(ref name) => {
+                let cargo_home = crates_toml_path.parent().unwrap_or_else(|| Path::new(""));
+                let cwd = std::env::current_dir().unwrap_or_default();
+                match cargo_config::registry_index(cargo_home, &cwd, name)? {
+                    Some(index) => Source::Index(index),
+                    None => requirement.source.clone(),
+                }
+            }
+            ref source => source.clone(),
+        };
+        if !wanted.matches_installed(source) {
+            return Ok(Some(format!(
+                "source is {}, not {}",
+                source, requirement.source
+            )));
+        }
+        if let Some(mismatch) = git_mismatch(requirement, source) {
+            return Ok(Some(mismatch));
+        }
+    }
+
+    let package = &requirement.package;
+    let info = crates2::install_info(crates_toml_path, &installed)?;
+    // Both of cargo's records list the binaries; a newer cargo may have added one the other
+    // doesn't know about, so every binary either lists must be there.
+    let mut bins = installed_binaries(crates_toml_path, crates_toml_contents, package)?;
+    if let Some(ref info) = info {
+        for bin in &info.bins {
+            if !bins.contains(bin) {
+                bins.push(bin.clone());
+            }
+        }
+    }
+    // What we recorded stands in for `.crates2.json` when an older cargo didn't write it.
+    let state = State::load(crates_toml_path)?;
+    let build = state.build(package);
+    let info = match info {
+        Some(info) => Some(info),
+        None => build.as_ref().map(|build| build.install_info(bins.clone())),
+    };
+    if let Some(bin) = requirement.bins.iter().find(|bin| !bins.contains(bin)) {
+        return Ok(Some(format!("binary {} is not installed", bin)));
+    }
+    let bin_dir = crates_toml_path.with_file_name("bin");
+    let missing: Vec<_> = bins
+        .iter()
+        .filter(|bin| !binary_exists(&bin_dir, bin))
+        .map(String::as_str)
+        .collect();
+    match missing.len() {
+        0 => {}
+        1 => {
+            return Ok(Some(format!(
+                "binary {} is missing from {}",
+                missing[0],
+                bin_dir.display()
+            )))
+        }
+        count => {
+            return Ok(Some(format!(
+                "{} of its {} binaries are missing from {}: {}",
+                count,
+                bins.len(),
+                bin_dir.display(),
+                missing.join(", ")
+            )))
+        }
+    }
+    for (bin, expected) in state.checksums(package) {
+        if !bins.contains(&bin) {
+            continue;
+        }
+        let actual = match prebuilt::sha256(&bin_dir.join(&bin)) {
+            Ok(actual) => actual,
+            Err(err) => {
+                verbose!(
+                    output::VERBOSE,
+                    "{}: {}: not checking its checksum",
+                    package,
+                    err
+                );
+                continue;
+            }
+        };
+        if actual != expected {
+            let problem = format!(
+                "binary {} has changed since it was installed: its SHA-256 is {}, not {}",
+                bin, actual, expected
+            );
+            if requirement.strict_checksums {
+                return Err(Error::ChecksumMismatch {
+                    package: package.clone(),
+                    problem,
+                });
+            }
+            return Ok(Some(problem));
+        }
+    }
+    // A package built for another target is expected not to match this host.
+    if requirement.target.is_none() {
+        if let Some(mismatch) = platform_mismatch(&bin_dir, &bins, info.as_ref()) {
+            return Ok(Some(mismatch));
+        }
+    }
+    if requirement.verify {
+        let version = key.version.to_string();
+        for bin in &bins {
+            if let Some(problem) = verify_binary(&bin_dir, bin, &version) {
+                return Ok(Some(problem));
+            }
+        }
+    }
+
+    if let Some(ref info) = info {
+        if let Some(mismatch) = feature_mismatch(info, requirement) {
+            return Ok(Some(mismatch));
+        }
+        let profile = info.profile.as_deref().unwrap_or("release");
+        let want_profile = requirement.profile.as_deref().unwrap_or("release");
+        if profile != want_profile {
+            return Ok(Some(format!(
+                "built with profile {}, not {}",
+                profile, want_profile
+            )));
+        }
+        if let (Some(rustc), Some(toolchain)) = (&info.rustc, &requirement.toolchain) {
+            if rustc_version(Some(toolchain)).as_ref() != Some(rustc) {
+                return Ok(Some(format!(
+                    "built with {}, not the {} toolchain",
+                    rustc.lines().next().unwrap_or(""),
+                    toolchain
+                )));
+            }
+        }
+        if let (Some(target), Some(want_target)) = (&info.target, &requirement.target) {
+            if target != want_target {
+                return Ok(Some(format!("built for {}, not {}", target, want_target)));
+            }
+        }
+    }
+
+    if let Some(recorded) = build.as_ref().and_then(|build| build.rustflags.as_ref()) {
+        let current = rustflags(env);
+        if *recorded != current {
+            return Ok(Some(format!(
+                "built with RUSTFLAGS {:?}, not {:?}",
+                recorded, current
+            )));
+        }
+    }
+    if requirement.rebuild_on_rustc_change {
+        if let Some(mismatch) = rustc_mismatch(requirement, build.as_ref(), info.as_ref()) {
+            return Ok(Some(mismatch));
+        }
+    }
+
+    if let Source::Path(ref dir) = requirement.source {
+        return path_mismatch(crates_toml_path, crates_toml_contents, package, dir);
+    }
+    Ok(None)
+}
+
+/// Describes why a package installed with `bins` in `bin_dir` can't run on this host, if it was
+/// built for another platform, such as when a cached `$CARGO_HOME` is restored on a machine of
+/// another architecture. The target `info` records is checked first, then each binary's header.
+fn platform_mismatch(
+    bin_dir: &Path,
+    bins: &[String],
+    info: Option<&InstallInfo>,
+) -> Option<String> {
+    let host = executable::host();
+    if let Some(target) = info.and_then(|info| info.target.as_ref()) {
+        match executable::for_target(target) {
+            Some(platform) if platform != host => {
+                return Some(format!(
+                    "built for {}, but this host runs {} executables",
+                    target, host
+                ));
+            }
+            _ => {}
+        }
+    }
+    for bin in bins {
+        match executable::for_file(&bin_dir.join(bin)) {
+            Ok(Some(platform)) if platform != host => {
+                return Some(format!(
+                    "binary {} is a {} executable, but this host runs {} executables",
+                    bin, platform, host
+                ));
+            }
+            Ok(_) => {}
+            Err(err) => verbose!(output::VERBOSE, "{}: not checking its platform", err),
+        }
+    }
+    None
+}
+
+/// Describes how the rustc `requirement` was built with, according to our `build` record or else
+/// `.crates2.json`, differs from the one it would be built with now, if it does and we know.
+fn rustc_mismatch(
+    requirement: &Requirement,
+    build: Option<&Build>,
+    info: Option<&InstallInfo>,
+) -> Option<String> {
+    let recorded = match build.and_then(|build| build.rustc.as_ref()) {
+        Some(rustc) => rustc,
+        None => info?.rustc.as_ref()?,
+    };
+    let current = rustc_version(requirement.toolchain.as_deref())?;
+    if *recorded == current {
+        return None;
+    }
+    Some(format!(
+        "built with {}, but rustc is now {}",
+        recorded.lines().next().unwrap_or(""),
+        current.lines().next().unwrap_or("")
+    ))
+}
+
+/// The extra flags cargo passes to rustc from the environment, space-separated, as cargo reads
+/// them: `CARGO_ENCODED_RUSTFLAGS` if set, and otherwise `RUSTFLAGS`. `env` is what we set or
+/// remove in cargo's environment, as in `Cargo::env`, so the flags are those cargo actually runs
+/// with, rather than our own.
+fn rustflags(env: &[(String, Option<OsString>)]) -> String {
+    let var = |name: &str| match env.iter().rev().find(|(var, _)| var == name) {
+        Some((_, value)) => value
+            .as_ref()
+            .map(|value| value.to_string_lossy().into_owned()),
+        None => std::env::var(name).ok(),
+    };
+    match var("CARGO_ENCODED_RUSTFLAGS") {
+        Some(encoded) => encoded.split('\x1f').collect::<Vec<_>>().join(" "),
+        None => var("RUSTFLAGS").unwrap_or_default(),
+    }
+}
+
+/// Describes how the commit a git package was installed from, recorded in `installed`, differs from
+/// the one its branch or tag now points to, if it does.
+fn git_mismatch(requirement: &Requirement, installed: &str) -> Option<String> {
+    let (url, reference) = match requirement.source {
+        Source::Git {
+            ref url,
+            ref reference,
+        } => (url, reference),
+        _ => return None,
+    };
+    let installed_commit = source::installed_commit(installed)?;
+    match source::remote_commit(url, reference) {
+        Ok(Some(ref commit)) if !commit.starts_with(installed_commit) => Some(format!(
+            "installed from commit {}, but {} is now at {}",
+            installed_commit, requirement.source, commit
+        )),
+        Ok(_) => None,
+        Err(err) => {
+            verbose!(
+                output::VERBOSE,
+                "{}: {}: assuming commit {} is current",
+                requirement.package,
+                err,
+                installed_commit
+            );
+            None
+        }
+    }
+}
+
+/// Describes how the features `info` was installed with differ from the ones `requirement` asks
+/// for, if they do.
+fn feature_mismatch(info: &InstallInfo, requirement: &Requirement) -> Option<String> {
+    let mut installed = info.features.clone();
+    installed.sort();
+    let mut wanted = requirement.features.clone();
+    wanted.sort();
+    wanted.dedup();
+    let describe = |features: &[String]| {
+        if features.is_empty() {
+            "no features".to_owned()
+        } else {
+            format!("features {}", features.join(", "))
+        }
+    };
+    if installed != wanted {
+        return Some(format!(
+            "installed with {}, but {} were requested",
+            describe(&installed),
+            describe(&wanted)
+        ));
+    }
+    if info.all_features != requirement.all_features {
+        return Some(format!(
+            "installed {} --all-features",
+            if info.all_features { "with" } else { "without" }
+        ));
+    }
+    if info.no_default_features != requirement.no_default_features {
+        return Some(format!(
+            "installed {} default features",
+            if info.no_default_features {
+                "without"
+            } else {
+                "with"
+            }
+        ));
+    }
+    None
+}
+
+/// Whether the binary `bin` is in `bin_dir`, where it has an `.exe` suffix on Windows whether or
+/// not cargo recorded one.
+fn binary_exists(bin_dir: &Path, bin: &str) -> bool {
+    let suffix = std::env::consts::EXE_SUFFIX;
+    bin_dir.join(bin).is_file()
+        || (!suffix.is_empty() && bin_dir.join(format!("{}{}", bin, suffix)).is_file())
+}
+
+/// The binaries `requirement` would install which are already in `bin_dir`, for a package which
+/// isn't recorded as installed, so they were put there some other way or cargo's records were lost.
+/// Without `bins`, the package is assumed to have a binary of its own name.
+fn untracked_binaries(bin_dir: &Path, requirement: &Requirement) -> Vec<String> {
+    let package = [requirement.package.clone()];
+    let bins = if requirement.bins.is_empty() {
+        &package[..]
+    } else {
+        &requirement.bins[..]
+    };
+    bins.iter()
+        .filter(|bin| binary_exists(bin_dir, bin))
+        .cloned()
+        .collect()
+}
+
+/// Runs `bin` from `bin_dir` with `--version`, and describes what is wrong if it fails or doesn't
+/// mention `version`. Cargo subcommands are run as cargo runs them, with the subcommand's name
+/// first, since many only understand that.
+fn verify_binary(bin_dir: &Path, bin: &str, version: &str) -> Option<String> {
+    let output = match detect::version_command(&bin_dir.join(bin)).output() {
+        Ok(output) => output,
+        Err(err) => return Some(format!("binary {} could not be run: {}", bin, err)),
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Some(format!(
+            "binary {} failed running --version ({}): {}",
+            bin,
+            output.status,
+            stderr.trim()
+        ));
+    }
+    if !stdout.contains(version) && !stderr.contains(version) {
+        return Some(format!(
+            "binary {} reported {:?} for --version, not {}",
+            bin,
+            stdout.trim(),
+            version
+        ));
+    }
+    None
+}
+
+/// The output of `rustc -vV` for `toolchain`, or the default toolchain if `None`, as recorded in
+/// `.crates2.json`, or `None` if it couldn't be run (for example, if the toolchain isn't
+/// installed).
+fn rustc_version(toolchain: Option<&str>) -> Option<String> {
+    let mut command = Command::new("rustc");
+    if let Some(toolchain) = toolchain {
+        command.arg(format!("+{}", toolchain));
+    }
+    let output = command.arg("-vV").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+/// Describes why the installed copy of `package` from the local `dir` is out of date, if it
+/// doesn't have the version in its `Cargo.toml` or its binaries were built before a source file was
+/// last modified.
+fn path_mismatch(
+    crates_toml_path: &Path,
+    crates_toml_contents: &str,
+    package: &str,
+    dir: &Path,
+) -> Result<Option<String>, Error> {
+    let installed = installed_version(crates_toml_path, crates_toml_contents, package)?;
+    if let Some(version) = local::package_version(dir)? {
+        if installed.as_ref() != Some(&version) {
+            return Ok(Some(format!("{:?} is now at version {}", dir, version)));
+        }
+    }
+    let bin_dir = crates_toml_path
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join("bin");
+    let built = installed_binaries(crates_toml_path, crates_toml_contents, package)?
+        .iter()
+        .map(|bin| local::modified(&bin_dir.join(bin)))
+        .min()
+        .unwrap_or(None);
+    let changed = local::newest_modification(dir)?;
+    if built.is_none() || changed > built {
+        return Ok(Some(format!(
+            "{:?} has changed since it was installed",
+            dir
+        )));
+    }
+    Ok(None)
+}
+
+/// The names of every package recorded in `.crates.toml`.
+fn installed_packages(
+    crates_toml_path: &Path,
+    crates_toml_contents: &str,
+) -> Result<Vec<String>, Error> {
+    let installed = installed_crates(crates_toml_path, crates_toml_contents)?;
+    Ok(installed
+        .iter()
+        .map(|package| package.name.clone())
+        .collect())
+}
+
+/// The `"name version (source)"` keys of the `[v1]` table in `.crates.toml`.
+fn installed_keys(
+    crates_toml_path: &Path,
+    crates_toml_contents: &str,
+) -> Result<Vec<String>, Error> {
+    let table = installed_table(crates_toml_path, crates_toml_contents)?;
+    Ok(table.keys().cloned().collect())
+}
+
+/// The binaries `.crates.toml` records as installed by `package`.
+fn installed_binaries(
+    crates_toml_path: &Path,
+    crates_toml_contents: &str,
+    package: &str,
+) -> Result<Vec<String>, Error> {
+    let installed = installed_crates(crates_toml_path, crates_toml_contents)?;
+    Ok(installed
+        .get(package)
+        .map(|package| package.bins.clone())
+        .unwrap_or_default())
+}
+
+/// What `.crates.toml` and our state file record as installed.
+fn installed_crates(
+    crates_toml_path: &Path,
+    crates_toml_contents: &str,
+) -> Result<InstalledCrates, Error> {
+    let table = installed_table(crates_toml_path, crates_toml_contents)?;
+    InstalledCrates::from_table(crates_toml_path, &table)
+}
+
+/// The key `table` records `package` under. A corrupted or hand-edited `.crates.toml` may have
+/// several, in which case the one with the highest version is used, whatever order they're in.
+fn package_key(table: &toml::value::Table, package: &str) -> Option<String> {
+    let prefix = format!("{} ", package);
+    let mut keys: Vec<_> = table
+        .keys()
+        .filter(|key| key.starts_with(&prefix))
+        .collect();
+    if keys.len() > 1 {
+        // Stable, so keys with the same version stay in order.
+        keys.sort_by_key(|key| Reverse(crates_toml::Key::parse(key).ok().map(|key| key.version)));
+        verbose!(
+            output::VERBOSE,
+            "{}: .crates.toml has {} entries for it; using {}",
+            package,
+            keys.len(),
+            keys[0]
+        );
+    }
+    keys.first().map(|key| (*key).clone())
+}
+
+/// The `[v1]` table of `.crates.toml`, plus the packages recorded in our state file which cargo
+/// doesn't know about.
+fn installed_table(
+    crates_toml_path: &Path,
+    crates_toml_contents: &str,
+) -> Result<toml::value::Table, Error> {
+    let mut table = crates_toml::installed(crates_toml_path, crates_toml_contents)?;
+    let state = installed::StateFile {
+        crates_toml: crates_toml_path.to_owned(),
+    };
+    for package in state.installed()? {
+        let prefix = format!("{} ", package.name);
+        if !table.keys().any(|k| k.starts_with(&prefix)) {
+            let bins = package.bins.iter().cloned().map(Value::String).collect();
+            table.insert(package.key(), Value::Array(bins));
+        }
+    }
+    Ok(table)
+}
+
+fn read_file_to_string(p: &Path) -> Result<String, std::io::Error> {
+    let mut s = String::new();
+    let mut f = File::open(p)?;
+    f.read_to_string(&mut s)?;
+    Ok(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        cargo_home, cargo_program, exit_code, feature_mismatch, find_program, installed_packages,
+        installed_version, package_key, package_status, prunable_version, rustc_mismatch,
+        rustc_version, rustflags, should_install, unsatisfied_reason, verify_binary, Cargo, Error,
+        Event, InstallReason, Outcome, PackageStatus, EXIT_CARGO, EXIT_FAILURE, EXIT_TIMEOUT,
+        EXIT_UNSATISFIABLE, EXIT_UNSATISFIED,
+    };
+    use crates2::InstallInfo;
+    use crates_toml;
+    use prompt::Confirm;
+    use requirement::Requirement;
+    use runner::{self, Exit};
+    use semver::{Version, VersionReq};
+    use source::Source;
+    use state::{Build, State};
+    use std::ffi::OsString;
+    use std::path::{Path, PathBuf};
+    use std::rc::Rc;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    pub fn some_path() -> PathBuf {
+        PathBuf::from("/path/to/.crates.toml")
+    }
+
+    /// The `.crates.toml` path of a new install root called `name`, whose `bin` directory contains
+    /// `bins`.
+    fn install_root(name: &str, bins: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "cargo-ensure-installed-{}-{}",
+            name,
+            std::process::id()
+        ));
+        std::fs::create_dir_all(dir.join("bin")).unwrap();
+        for bin in bins {
+            std::fs::write(dir.join("bin").join(bin), "").unwrap();
+        }
+        dir.join(".crates.toml")
+    }
+
+    #[test]
+    pub fn features() {
+        let info = InstallInfo {
+            features: vec!["rustls".to_owned(), "postgres".to_owned()],
+            ..InstallInfo::default()
+        };
+        let mut requirement = Requirement::new("sqlx-cli", "0.7").unwrap();
+        assert_eq!(
+            feature_mismatch(&info, &requirement),
+            Some(
+                "installed with features postgres, rustls, but no features were requested"
+                    .to_owned()
+            )
+        );
+        requirement.add_feature("postgres");
+        requirement.add_feature("rustls");
+        assert_eq!(feature_mismatch(&info, &requirement), None);
+        requirement.no_default_features = true;
+        assert_eq!(
+            feature_mismatch(&info, &requirement),
+            Some("installed with default features".to_owned())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    pub fn verifies_binaries() {
+        use std::os::unix::fs::PermissionsExt;
+        let crates_toml = install_root("verify", &[]);
+        let bin_dir = crates_toml.with_file_name("bin");
+        let script = |bin: &str, body: &str| {
+            let path = bin_dir.join(bin);
+            std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        };
+        script("rustfmt", "echo rustfmt 1.7.0-stable");
+        script(
+            "cargo-fmt",
+            "[ \"$1 $2\" = \"fmt --version\" ] && echo rustfmt 1.7.0-stable",
+        );
+        script(
+            "broken",
+            "echo 'error while loading shared libraries' >&2; exit 127",
+        );
+        assert_eq!(verify_binary(&bin_dir, "rustfmt", "1.7.0"), None);
+        assert_eq!(verify_binary(&bin_dir, "cargo-fmt", "1.7.0"), None);
+        assert_eq!(
+            verify_binary(&bin_dir, "rustfmt", "1.8.0"),
+            Some(
+                "binary rustfmt reported \"rustfmt 1.7.0-stable\" for --version, not 1.8.0"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            verify_binary(&bin_dir, "broken", "1.0.0"),
+            Some(
+                "binary broken failed running --version (exit status: 127): error while loading \
+                 shared libraries"
+                    .to_owned()
+            )
+        );
+        std::fs::remove_dir_all(crates_toml.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    pub fn rustc_changes() {
+        let requirement = Requirement::new("clippy-driver", "*").unwrap();
+        let old_rustc = InstallInfo {
+            rustc: Some("rustc 1.0.0 (a59de37e9 2015-05-13)\n".to_owned()),
+            ..InstallInfo::default()
+        };
+        assert_eq!(rustc_mismatch(&requirement, None, None), None);
+        let mismatch = rustc_mismatch(&requirement, None, Some(&old_rustc)).unwrap();
+        assert!(
+            mismatch.starts_with("built with rustc 1.0.0 (a59de37e9 2015-05-13), but rustc is now")
+        );
+        let build = Build {
+            rustc: rustc_version(None),
+            ..Build::default()
+        };
+        assert_eq!(
+            rustc_mismatch(&requirement, Some(&build), Some(&old_rustc)),
+            None
+        );
+    }
+
+    #[test]
+    pub fn exit_codes() {
+        let cargo_failed = || Error::Cargo {
+            package: "ripgrep".to_owned(),
+            subcommand: "install".to_owned(),
+            code: Some(101),
+        };
+        assert_eq!(exit_code(&cargo_failed()), EXIT_CARGO);
+        let unmatched = || Error::NoMatchingVersion {
+            package: "ripgrep".to_owned(),
+            version_req: "=99".to_owned(),
+        };
+        assert_eq!(exit_code(&unmatched()), EXIT_UNSATISFIABLE);
+        assert_eq!(
+            exit_code(&Error::Timeout {
+                package: "ripgrep".to_owned(),
+                timeout: Duration::from_secs(600),
+            }),
+            EXIT_TIMEOUT
+        );
+        assert_eq!(exit_code(&Error::Interrupted { signal: 15 }), 143);
+        assert_eq!(exit_code(&Error::Other("oops".to_owned())), EXIT_FAILURE);
+        let all_failed = |failures| Error::AllBackendsFailed {
+            package: "ripgrep".to_owned(),
+            failures,
+        };
+        assert_eq!(
+            exit_code(&all_failed(vec![
+                ("quickinstall", unmatched()),
+                ("source", unmatched())
+            ])),
+            EXIT_UNSATISFIABLE
+        );
+        assert_eq!(
+            exit_code(&all_failed(vec![
+                ("quickinstall", unmatched()),
+                ("source", cargo_failed())
+            ])),
+            EXIT_FAILURE
+        );
+    }
+
+    #[test]
+    pub fn cargo_homes() {
+        let home = Some(PathBuf::from("/home/ferris"));
+        assert_eq!(
+            cargo_home(Some(OsString::from("/opt/cargo")), home.clone()),
+            Ok(PathBuf::from("/opt/cargo"))
+        );
+        assert_eq!(
+            cargo_home(None, home.clone()),
+            Ok(PathBuf::from("/home/ferris/.cargo"))
+        );
+        assert_eq!(
+            cargo_home(Some(OsString::new()), home),
+            Ok(PathBuf::from("/home/ferris/.cargo"))
+        );
+        assert!(cargo_home(None, None).is_err());
+    }
+
+    #[test]
+    pub fn cargo_path() {
+        let env = Some(OsString::from("/rustup/toolchains/nightly/bin/cargo"));
+        assert_eq!(
+            cargo_program(Some(PathBuf::from("/opt/cargo")), env.clone()),
+            PathBuf::from("/opt/cargo")
+        );
+        assert_eq!(
+            cargo_program(None, env),
+            PathBuf::from("/rustup/toolchains/nightly/bin/cargo")
+        );
+        assert_eq!(
+            cargo_program(None, Some(OsString::new())),
+            PathBuf::from("cargo")
+        );
+        assert_eq!(cargo_program(None, None), PathBuf::from("cargo"));
+    }
+
+    #[test]
+    pub fn rustc_wrapper() {
+        let dir = std::env::temp_dir().join(format!(
+            "cargo-ensure-installed-find-program-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("sccache"), "").unwrap();
+        let search_path =
+            Some(std::env::join_paths(["/nonexistent".as_ref(), dir.as_path()]).unwrap());
+        assert_eq!(
+            find_program("sccache".as_ref(), search_path.clone()),
+            Some(dir.join("sccache"))
+        );
+        assert_eq!(find_program("cachepot".as_ref(), search_path), None);
+        assert_eq!(
+            find_program(&dir.join("sccache"), None),
+            Some(dir.join("sccache"))
+        );
+        assert_eq!(find_program("/nonexistent/sccache".as_ref(), None), None);
+
+        let cargo = Cargo {
+            program: PathBuf::from("cargo"),
+            rustc_wrapper: Some(PathBuf::from("sccache")),
+            offline: false,
+            vendored: None,
+            retries: 0,
+            lock_timeout: None,
+            timeout: None,
+            crates_toml: some_path(),
+            root: None,
+            env: vec![
+                ("CARGO_TARGET_DIR".to_owned(), None),
+                ("CARGO_TERM_QUIET".to_owned(), Some("true".into())),
+            ],
+            runner: Rc::new(runner::System),
+            installed_sources: vec![],
+            observer: None,
+        };
+        assert_eq!(
+            cargo.command_line(&["install".to_owned(), "rustfmt".to_owned()]),
+            "RUSTC_WRAPPER=sccache cargo install rustfmt"
+        );
+        let command = cargo.command();
+        let envs: Vec<_> = command.get_envs().collect();
+        assert!(envs.contains(&("CARGO_TARGET_DIR".as_ref(), None)));
+        assert!(envs.contains(&("CARGO_TERM_QUIET".as_ref(), Some("true".as_ref()))));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    pub fn installs_with_cargo() {
+        let crates_toml = install_root("installs", &[]);
+        let bin = crates_toml.with_file_name("bin").join("rustfmt");
+        let cargo = |runner: Rc<dyn runner::Runner>| Cargo {
+            program: PathBuf::from("cargo"),
+            rustc_wrapper: None,
+            offline: false,
+            vendored: None,
+            retries: 0,
+            lock_timeout: None,
+            timeout: None,
+            crates_toml: crates_toml.clone(),
+            root: None,
+            env: vec![],
+            runner,
+            installed_sources: vec![],
+            observer: None,
+        };
+        let ensure = |cargo: &Cargo, version: &str| {
+            let requirement = Requirement::new("rustfmt", version).unwrap();
+            super::ensure_package(cargo, &requirement, false, &mut Confirm::always())
+        };
+        // Installs the exact version it's asked for, as cargo would.
+        let installs = Rc::new(runner::Fake::new({
+            let crates_toml = crates_toml.clone();
+            let bin = bin.clone();
+            move |args: &[String]| {
+                let version = &args[args.iter().position(|arg| arg == "--vers").unwrap() + 1];
+                let version = version.trim_start_matches('=');
+                std::fs::write(
+                    &crates_toml,
+                    format!(
+                    "[v1]\n\"rustfmt {} (registry+https://github.com/rust-lang/crates.io-index)\" \
+                     = [\"rustfmt\"]\n",
+                    version
+                ),
+                )
+                .unwrap();
+                std::fs::write(&bin, format!("rustfmt {}", version)).unwrap();
+                Exit {
+                    code: Some(0),
+                    stderr: String::new(),
+                }
+            }
+        }));
+        let installing = cargo(installs.clone());
+        let version = |version| Version::parse(version).unwrap();
+
+        assert_eq!(
+            ensure(&installing, "=1.4.0").unwrap(),
+            Some(Outcome::Installed {
+                version: version("1.4.0")
+            })
+        );
+        assert_eq!(
+            ensure(&installing, "^1.4").unwrap(),
+            Some(Outcome::AlreadySatisfied {
+                installed_version: version("1.4.0")
+            })
+        );
+        assert_eq!(
+            ensure(&installing, "=1.5.0").unwrap(),
+            Some(Outcome::Upgraded {
+                from: version("1.4.0"),
+                to: version("1.5.0")
+            })
+        );
+        let ran = installs.ran.borrow();
+        assert_eq!(ran.len(), 2);
+        assert_eq!(ran[0][..2], ["install", "--vers"]);
+        // Only replacing an install needs --force.
+        assert!(!ran[0].contains(&"--force".to_owned()));
+        assert!(ran[1].contains(&"--force".to_owned()));
+
+        let fails = Rc::new(runner::Fake::new({
+            let bin = bin.clone();
+            move |_: &[String]| {
+                std::fs::write(&bin, "half-written").unwrap();
+                Exit {
+                    code: Some(101),
+                    stderr: "error: could not compile `rustfmt`\n".to_owned(),
+                }
+            }
+        }));
+        match ensure(&cargo(fails), "=1.6.0") {
+            Err(Error::Cargo {
+                package,
+                subcommand,
+                code,
+            }) => assert_eq!(
+                (package.as_str(), subcommand.as_str(), code),
+                ("rustfmt", "install", Some(101))
+            ),
+            other => panic!("{:?}", other),
+        }
+        // The previous install is put back.
+        assert_eq!(std::fs::read_to_string(&bin).unwrap(), "rustfmt 1.5.0");
+        std::fs::remove_dir_all(crates_toml.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    pub fn declines() {
+        let crates_toml = install_root("declines", &[]);
+        let cargo = Cargo {
+            program: PathBuf::from("cargo"),
+            rustc_wrapper: None,
+            offline: false,
+            vendored: None,
+            retries: 0,
+            lock_timeout: None,
+            timeout: None,
+            crates_toml: crates_toml.clone(),
+            root: None,
+            env: vec![],
+            runner: Rc::new(runner::Fake::new(|args: &[String]| -> Exit {
+                panic!("ran cargo {:?}", args)
+            })),
+            installed_sources: vec![],
+            observer: None,
+        };
+        let requirement = Requirement::new("rustfmt", "1.4").unwrap();
+        match super::ensure_package(&cargo, &requirement, false, &mut Confirm::never()) {
+            Err(err @ Error::Declined { .. }) => {
+                assert_eq!(err.to_string(), "Installing rustfmt was declined");
+                // Declining isn't a failure, but leaves the package unsatisfied.
+                assert_eq!(exit_code(&err), EXIT_UNSATISFIED);
+            }
+            other => panic!("{:?}", other),
+        }
+        std::fs::remove_dir_all(crates_toml.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    pub fn unrecorded_installs() {
+        let crates_toml = install_root("unrecorded", &[]);
+        let cargo = Cargo {
+            program: PathBuf::from("cargo"),
+            rustc_wrapper: None,
+            offline: false,
+            vendored: None,
+            retries: 0,
+            lock_timeout: None,
+            timeout: None,
+            crates_toml: crates_toml.clone(),
+            root: None,
+            env: vec![],
+            // Succeeds without recording anything.
+            runner: Rc::new(runner::Fake::new(|_: &[String]| Exit {
+                code: Some(0),
+                stderr: String::new(),
+            })),
+            installed_sources: vec![],
+            observer: None,
+        };
+        let requirement = Requirement::new("rustfmt", "1.4").unwrap();
+        match super::ensure_package(&cargo, &requirement, false, &mut Confirm::always()) {
+            Err(Error::Unrecorded { package }) => assert_eq!(package, "rustfmt"),
+            other => panic!("{:?}", other),
+        }
+        std::fs::remove_dir_all(crates_toml.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    pub fn reports_events() {
+        let crates_toml = install_root("events", &[]);
+        let installs = runner::Fake::new({
+            let crates_toml = crates_toml.clone();
+            move |_: &[String]| {
+                std::fs::write(
+                    &crates_toml,
+                    "[v1]\n\"rustfmt 1.4.0 (registry+https://github.com/rust-lang/crates.io-index)\" \
+                     = [\"rustfmt\"]\n",
+                )
+                .unwrap();
+                std::fs::write(crates_toml.with_file_name("bin").join("rustfmt"), "").unwrap();
+                Exit {
+                    code: Some(0),
+                    stderr: "  Installing rustfmt v1.4.0\n".to_owned(),
+                }
+            }
+        });
+        let events = Arc::new(Mutex::new(vec![]));
+        let cargo = Cargo {
+            program: PathBuf::from("cargo"),
+            rustc_wrapper: None,
+            offline: false,
+            vendored: None,
+            retries: 0,
+            lock_timeout: None,
+            timeout: None,
+            crates_toml: crates_toml.clone(),
+            root: None,
+            env: vec![],
+            runner: Rc::new(installs),
+            installed_sources: vec![],
+            observer: Some(Arc::new({
+                let events = events.clone();
+                move |event| events.lock().unwrap().push(event)
+            })),
+        };
+        let requirement = Requirement::new("rustfmt", "=1.4.0").unwrap();
+        for _ in 0..2 {
+            super::ensure_package(&cargo, &requirement, false, &mut Confirm::always()).unwrap();
+        }
+        let package = || "rustfmt".to_owned();
+        let version = Version::parse("1.4.0").unwrap();
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                Event::CheckStarted { package: package() },
+                Event::Decided {
+                    package: package(),
+                    installed: None,
+                    reason: Some(InstallReason::NotInstalled)
+                },
+                Event::InstallStarted {
+                    package: package(),
+                    version_req: "=1.4.0".to_owned()
+                },
+                Event::StderrLine {
+                    package: package(),
+                    line: "  Installing rustfmt v1.4.0".to_owned()
+                },
+                Event::InstallFinished {
+                    package: package(),
+                    result: Ok(Some(version.clone()))
+                },
+                Event::CheckStarted { package: package() },
+                Event::Decided {
+                    package: package(),
+                    installed: Some(version),
+                    reason: None
+                },
+            ]
+        );
+        std::fs::remove_dir_all(crates_toml.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    pub fn no_contents() {
+        test(true, "");
+    }
+
+    #[test]
+    pub fn no_packages() {
+        test(true, "[v1]");
+    }
+
+    #[test]
+    pub fn absent_package() {
+        test(
+            true,
+            r###"[v1]
+"protobuf 1.4.2 (registry+https://github.com/rust-lang/crates.io-index)" = ["foo", "bar"]"###,
+        );
+    }
+
+    #[test]
+    pub fn exact_match() {
+        test(
+            false,
+            r###"[v1]
+"rustfmt 0.9.0 (registry+https://github.com/rust-lang/crates.io-index)" = ["rustfmt"]"###,
+        );
+    }
+
+    #[test]
+    pub fn have_newer_but_compatible() {
+        test(
+            false,
+            r###"[v1]
+"rustfmt 0.9.1 (registry+https://github.com/rust-lang/crates.io-index)" = ["rustfmt"]"###,
+        );
+    }
+
+    #[test]
+    pub fn have_newer_but_incompatible() {
+        test(
+            true,
+            r###"[v1]
+"rustfmt 0.10.0 (registry+https://github.com/rust-lang/crates.io-index)" = ["rustfmt"]"###,
+        );
+    }
+
+    #[test]
+    pub fn have_older() {
+        test(
+            true,
+            r###"[v1]
+"rustfmt 0.8.0 (registry+https://github.com/rust-lang/crates.io-index)" = ["rustfmt"]"###,
+        );
+    }
+
+    #[test]
+    pub fn carat_satisfied() {
+        let crates_toml_contents = r###"[v1]
+"rustfmt 0.0.9 (registry+https://github.com/rust-lang/crates.io-index)" = ["rustfmt"]"###;
+
+        assert_eq!(
+            should_install(
+                &some_path(),
+                crates_toml_contents,
+                "rustfmt",
+                &VersionReq::parse("^0.0.9").unwrap(),
+            )
+            .unwrap(),
+            None
+        )
+    }
+
+    #[test]
+    pub fn carat_unsatisfied() {
+        let crates_toml_contents = r###"[v1]
+"rustfmt 0.0.10 (registry+https://github.com/rust-lang/crates.io-index)" = ["rustfmt"]"###;
+
+        let reason = should_install(
+            &some_path(),
+            crates_toml_contents,
+            "rustfmt",
+            &VersionReq::parse("^0.0.9").unwrap(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            reason.to_string(),
+            "installed 0.0.10 does not satisfy ^0.0.9"
+        );
+    }
+
+    #[test]
+    pub fn lists_installed_packages() {
+        let crates_toml_contents = r###"[v1]
+"protobuf 1.4.2 (registry+https://github.com/rust-lang/crates.io-index)" = ["foo", "bar"]
+"rustfmt 0.9.0 (registry+https://github.com/rust-lang/crates.io-index)" = ["rustfmt"]"###;
+        assert_eq!(
+            installed_packages(&some_path(), crates_toml_contents).unwrap(),
+            vec!["protobuf".to_owned(), "rustfmt".to_owned()]
+        );
+        assert_eq!(
+            installed_packages(&some_path(), "").unwrap(),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    pub fn duplicate_entries() {
+        let crates_toml_contents = r###"[v1]
+"rustfmt 0.9.0 (registry+https://github.com/rust-lang/crates.io-index)" = ["rustfmt"]
+"rustfmt 1.0.0 (git+https://github.com/rust-lang/rustfmt#0123456789abcdef)" = ["rustfmt"]
+"rustfmt 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)" = ["rustfmt"]"###;
+        assert_eq!(
+            installed_version(&some_path(), crates_toml_contents, "rustfmt").unwrap(),
+            Some(Version::parse("1.0.0").unwrap())
+        );
+        let table = crates_toml::installed(&some_path(), crates_toml_contents).unwrap();
+        assert_eq!(
+            package_key(&table, "rustfmt"),
+            Some(
+                "rustfmt 1.0.0 (git+https://github.com/rust-lang/rustfmt#0123456789abcdef)"
+                    .to_owned()
+            )
+        );
+        assert_eq!(package_key(&table, "rust"), None);
+    }
+
+    #[test]
+    pub fn check_describes_problem() {
+        let crates_toml_contents = r###"[v1]
+"rustfmt 0.8.0 (registry+https://github.com/rust-lang/crates.io-index)" = ["rustfmt"]"###;
+        let crates_toml = install_root("check", &["rustfmt"]);
+        let check = |package: &str, version: &str| {
+            unsatisfied_reason(
+                &crates_toml,
+                crates_toml_contents,
+                &Requirement::new(package, version).unwrap(),
+            )
+            .map_err(|err| err.to_string())
+        };
+        assert_eq!(check("rustfmt", "^0.8"), Ok(None));
+        assert_eq!(
+            check("rustfmt", "^0.9"),
+            Ok(Some("installed 0.8.0 does not satisfy ^0.9".to_owned()))
+        );
+        assert_eq!(
+            check("cargo-deny", "0.14"),
+            Ok(Some("not installed (want 0.14)".to_owned()))
+        );
+        let bin_dir = crates_toml.with_file_name("bin");
+        std::fs::write(bin_dir.join("cargo-deny"), "").unwrap();
+        assert_eq!(
+            check("cargo-deny", "0.14"),
+            Ok(Some(format!(
+                "not recorded in .crates.toml (want 0.14), but cargo-deny is in {}; ensure \
+                 reinstalls it with --force to bring them back in sync",
+                bin_dir.display()
+            )))
+        );
+        std::fs::remove_dir_all(crates_toml.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    pub fn statuses() {
+        let crates_toml_contents = r###"[v1]
+"rustfmt 0.8.0 (registry+https://github.com/rust-lang/crates.io-index)" = ["rustfmt"]"###;
+        let crates_toml = install_root("statuses", &["rustfmt"]);
+        let status = |package: &str, version: &str| {
+            package_status(
+                &crates_toml,
+                crates_toml_contents,
+                &Requirement::new(package, version).unwrap(),
+            )
+            .unwrap()
+        };
+        let installed = Version::parse("0.8.0").unwrap();
+        assert_eq!(
+            status("rustfmt", "^0.8"),
+            PackageStatus::Satisfied(installed.clone())
+        );
+        assert_eq!(
+            status("rustfmt", "^0.9"),
+            PackageStatus::Mismatched(installed.clone())
+        );
+        assert_eq!(status("cargo-deny", "0.14"), PackageStatus::Missing);
+        let prunable = |requirement: &Requirement| {
+            prunable_version(&crates_toml, crates_toml_contents, requirement).unwrap()
+        };
+        assert_eq!(
+            prunable(&Requirement::new("rustfmt", "^0.9").unwrap()),
+            Some(installed.clone())
+        );
+        assert_eq!(
+            prunable(&Requirement::new("cargo-deny", "0.14").unwrap()),
+            None
+        );
+        // The right version from another source is reinstalled by ensure, not pruned.
+        let mut requirement = Requirement::new("rustfmt", "^0.8").unwrap();
+        requirement.source =
+            Source::git("https://github.com/rust-lang/rustfmt", None, None, None).unwrap();
+        assert_eq!(
+            package_status(&crates_toml, crates_toml_contents, &requirement).unwrap(),
+            PackageStatus::Mismatched(installed.clone())
+        );
+        assert_eq!(prunable(&requirement), None);
+        std::fs::remove_file(crates_toml.with_file_name("bin").join("rustfmt")).unwrap();
+        assert_eq!(
+            unsatisfied_reason(
+                &crates_toml,
+                crates_toml_contents,
+                &Requirement::new("rustfmt", "0.8").unwrap()
+            )
+            .unwrap(),
+            Some(format!(
+                "installed 0.8.0: binary rustfmt is missing from {}",
+                crates_toml.with_file_name("bin").display()
+            ))
+        );
+        std::fs::remove_dir_all(crates_toml.parent().unwrap()).unwrap();
+    }
+
+    /// Runs `git` in `dir`, returning its output.
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = std::process::Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+        String::from_utf8(output.stdout).unwrap().trim().to_owned()
+    }
+
+    #[test]
+    pub fn git_statuses() {
+        let repo =
+            std::env::temp_dir().join(format!("cargo-ensure-installed-git-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&repo);
+        std::fs::create_dir_all(&repo).unwrap();
+        git(&repo, &["init", "--quiet", "--initial-branch=main"]);
+        git(
+            &repo,
+            &["commit", "--quiet", "--allow-empty", "-m", "first"],
+        );
+        let commit = git(&repo, &["rev-parse", "HEAD"]);
+        let url = format!("file://{}", repo.display());
+        let crates_toml_contents = format!(
+            "[v1]\n\
+             \"grpcio-compiler 0.2.0 (git+{}?branch=main#{})\" = []\n\
+             \"rustfmt 0.8.0 (registry+https://github.com/rust-lang/crates.io-index)\" = \
+             [\"rustfmt\"]\n",
+            url, commit
+        );
+        let crates_toml = install_root("git-statuses", &["rustfmt"]);
+        let status = |package: &str, branch: &str| {
+            let mut requirement = Requirement::new(package, "*").unwrap();
+            requirement.source = Source::git(&url, Some(branch), None, None).unwrap();
+            package_status(&crates_toml, &crates_toml_contents, &requirement).unwrap()
+        };
+        let installed = Version::parse("0.2.0").unwrap();
+        assert_eq!(
+            status("grpcio-compiler", "main"),
+            PackageStatus::Satisfied(installed.clone())
+        );
+        assert_eq!(
+            status("grpcio-compiler", "dev"),
+            PackageStatus::Mismatched(installed.clone())
+        );
+        assert_eq!(
+            status("rustfmt", "main"),
+            PackageStatus::Mismatched(Version::parse("0.8.0").unwrap())
+        );
+        let mut requirement = Requirement::new("rustfmt", "*").unwrap();
+        requirement.source =
+            Source::git("https://github.com/rust-lang/rustfmt", None, None, None).unwrap();
+        requirement.any_source = true;
+        assert_eq!(
+            package_status(&crates_toml, &crates_toml_contents, &requirement).unwrap(),
+            PackageStatus::Satisfied(Version::parse("0.8.0").unwrap())
+        );
+
+        git(
+            &repo,
+            &["commit", "--quiet", "--allow-empty", "-m", "second"],
+        );
+        assert_eq!(
+            status("grpcio-compiler", "main"),
+            PackageStatus::Mismatched(installed)
+        );
+        std::fs::remove_dir_all(repo).unwrap();
+        std::fs::remove_dir_all(crates_toml.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    pub fn bin_statuses() {
+        let crates_toml_contents = r###"[v1]
+"cargo-binutils 0.3.6 (registry+https://github.com/rust-lang/crates.io-index)" = ["cargo-size"]"###;
+        let crates_toml = install_root("bin-statuses", &["cargo-size"]);
+        let mut requirement = Requirement::new("cargo-binutils", "0.3").unwrap();
+        requirement.bins = vec!["cargo-size".to_owned()];
+        let installed = Version::parse("0.3.6").unwrap();
+        assert_eq!(
+            package_status(&crates_toml, crates_toml_contents, &requirement).unwrap(),
+            PackageStatus::Satisfied(installed.clone())
+        );
+        requirement.bins.push("cargo-objdump".to_owned());
+        assert_eq!(
+            package_status(&crates_toml, crates_toml_contents, &requirement).unwrap(),
+            PackageStatus::Mismatched(installed)
+        );
+        assert_eq!(
+            unsatisfied_reason(&crates_toml, crates_toml_contents, &requirement).unwrap(),
+            Some("installed 0.3.6: binary cargo-objdump is not installed".to_owned())
+        );
+        std::fs::remove_dir_all(crates_toml.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    pub fn incomplete_installs() {
+        let key = "cargo-binutils 0.3.6 (registry+https://github.com/rust-lang/crates.io-index)";
+        let crates_toml = install_root("incomplete", &["cargo-nm"]);
+        let bin_dir = crates_toml.with_file_name("bin");
+        let requirement = Requirement::new("cargo-binutils", "0.3").unwrap();
+        let contents = format!(
+            "[v1]\n\"{}\" = [\"cargo-nm\", \"cargo-objdump\", \"cargo-size\"]\n",
+            key
+        );
+        assert_eq!(
+            unsatisfied_reason(&crates_toml, &contents, &requirement).unwrap(),
+            Some(format!(
+                "installed 0.3.6: 2 of its 3 binaries are missing from {}: cargo-objdump, \
+                 cargo-size",
+                bin_dir.display()
+            ))
+        );
+
+        // A binary only .crates2.json lists is checked too.
+        let contents = format!("[v1]\n\"{}\" = [\"cargo-nm\"]\n", key);
+        assert_eq!(
+            unsatisfied_reason(&crates_toml, &contents, &requirement).unwrap(),
+            None
+        );
+        std::fs::write(
+            crates_toml.with_file_name(".crates2.json"),
+            format!(
+                r#"{{"installs": {{"{}": {{"bins": ["cargo-nm", "rust-nm"]}}}}}}"#,
+                key
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            unsatisfied_reason(&crates_toml, &contents, &requirement).unwrap(),
+            Some(format!(
+                "installed 0.3.6: binary rust-nm is missing from {}",
+                bin_dir.display()
+            ))
+        );
+        std::fs::remove_dir_all(crates_toml.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    pub fn crates2_statuses() {
+        let dir = std::env::temp_dir().join(format!(
+            "cargo-ensure-installed-crates2-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let key = "cargo-nextest 0.9.67 (registry+https://github.com/rust-lang/crates.io-index)";
+        std::fs::write(
+            dir.join(".crates2.json"),
+            format!(
+                r#"{{"installs": {{"{}": {{"profile": "dev", "target": "x86_64-unknown-linux-musl"}}}}}}"#,
+                key
+            ),
+        )
+        .unwrap();
+        std::fs::create_dir_all(dir.join("bin")).unwrap();
+        std::fs::write(dir.join("bin/cargo-nextest"), "").unwrap();
+        let crates_toml = dir.join(".crates.toml");
+        let contents = format!("[v1]\n\"{}\" = [\"cargo-nextest\"]\n", key);
+        let reason = |profile: Option<&str>, target: Option<&str>| {
+            let mut requirement = Requirement::new("cargo-nextest", "0.9").unwrap();
+            requirement.profile = profile.map(|p| p.to_owned());
+            requirement.target = target.map(|t| t.to_owned());
+            unsatisfied_reason(&crates_toml, &contents, &requirement).unwrap()
+        };
+        assert_eq!(reason(Some("dev"), None), None);
+        assert_eq!(
+            reason(None, None),
+            Some("installed 0.9.67: built with profile dev, not release".to_owned())
+        );
+        assert_eq!(
+            reason(Some("dev"), Some("x86_64-unknown-linux-gnu")),
+            Some(
+                "installed 0.9.67: built for x86_64-unknown-linux-musl, not \
+                 x86_64-unknown-linux-gnu"
+                    .to_owned()
+            )
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    pub fn recorded_build_statuses() {
+        let crates_toml = install_root("recorded-build", &["cargo-nextest"]);
+        let key = "cargo-nextest 0.9.67 (registry+https://github.com/rust-lang/crates.io-index)";
+        let contents = format!("[v1]\n\"{}\" = [\"cargo-nextest\"]\n", key);
+        let mut state = State::load(&crates_toml).unwrap();
+        state.record_build(
+            "cargo-nextest",
+            &Build {
+                profile: Some("dev".to_owned()),
+                rustflags: Some("-C target-cpu=native".to_owned()),
+                ..Build::default()
+            },
+        );
+        state.save().unwrap();
+        let mut requirement = Requirement::new("cargo-nextest", "0.9").unwrap();
+        assert_eq!(
+            unsatisfied_reason(&crates_toml, &contents, &requirement).unwrap(),
+            Some("installed 0.9.67: built with profile dev, not release".to_owned())
+        );
+        requirement.profile = Some("dev".to_owned());
+        // Unless the tests happen to be run with exactly those RUSTFLAGS.
+        if rustflags(&[]) != "-C target-cpu=native" {
+            assert_eq!(
+                unsatisfied_reason(&crates_toml, &contents, &requirement).unwrap(),
+                Some(format!(
+                    "installed 0.9.67: built with RUSTFLAGS \"-C target-cpu=native\", not {:?}",
+                    rustflags(&[])
+                ))
+            );
+        }
+        std::fs::remove_dir_all(crates_toml.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    pub fn rustflags_from_env() {
+        let set = |name: &str, value: Option<&str>| (name.to_owned(), value.map(OsString::from));
+        assert_eq!(
+            rustflags(&[set(
+                "CARGO_ENCODED_RUSTFLAGS",
+                Some("-C\x1ftarget-cpu=native")
+            )]),
+            "-C target-cpu=native"
+        );
+        // As for build scripts, whose CARGO_ENCODED_RUSTFLAGS the nested cargo doesn't see.
+        assert_eq!(
+            rustflags(&[
+                set("CARGO_ENCODED_RUSTFLAGS", None),
+                set("RUSTFLAGS", Some("--cfg tokio_unstable")),
+            ]),
+            "--cfg tokio_unstable"
+        );
+        assert_eq!(
+            rustflags(&[set("CARGO_ENCODED_RUSTFLAGS", None), set("RUSTFLAGS", None)]),
+            ""
+        );
+    }
+
+    #[test]
+    pub fn checksum_statuses() {
+        let crates_toml = install_root("checksums", &[]);
+        let bin = crates_toml.with_file_name("bin").join("rustfmt");
+        std::fs::write(&bin, "binary").unwrap();
+        let contents = r###"[v1]
+"rustfmt 0.8.0 (registry+https://github.com/rust-lang/crates.io-index)" = ["rustfmt"]"###;
+        let mut state = State::load(&crates_toml).unwrap();
+        state.record_build("rustfmt", &Build::default());
+        state.record_checksums(
+            "rustfmt",
+            &[(
+                "rustfmt".to_owned(),
+                "9a3a45d01531a20e89ac6ae10b0b0beb0492acd7216a368aa062d1a5fecaf9cd".to_owned(),
+            )],
+        );
+        state.save().unwrap();
+        let mut requirement = Requirement::new("rustfmt", "0.8").unwrap();
+        assert_eq!(
+            unsatisfied_reason(&crates_toml, contents, &requirement).unwrap(),
+            None
+        );
+        std::fs::write(&bin, "tampered").unwrap();
+        let problem = "binary rustfmt has changed since it was installed: its SHA-256 is \
+                       d121be3103007b41edf96f8262925f8c7d61894afe9a041843b631f69445bc57, not \
+                       9a3a45d01531a20e89ac6ae10b0b0beb0492acd7216a368aa062d1a5fecaf9cd";
+        assert_eq!(
+            unsatisfied_reason(&crates_toml, contents, &requirement).unwrap(),
+            Some(format!("installed 0.8.0: {}", problem))
+        );
+        requirement.strict_checksums = true;
+        match unsatisfied_reason(&crates_toml, contents, &requirement) {
+            Err(Error::ChecksumMismatch {
+                package,
+                problem: actual,
+            }) => {
+                assert_eq!(package, "rustfmt");
+                assert_eq!(actual, problem);
+            }
+            other => panic!("{:?}", other),
+        }
+        std::fs::remove_dir_all(crates_toml.parent().unwrap()).unwrap();
+    }
+
+    fn test(want: bool, crates_toml_contents: &str) {
+        assert_eq!(
+            should_install(
+                &some_path(),
+                crates_toml_contents,
+                "rustfmt",
+                &VersionReq::parse("0.9.0").unwrap(),
+            )
+            .unwrap()
+            .is_some(),
+            want
+        )
+    }
+}
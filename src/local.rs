@@ -0,0 +1,115 @@
+//! Checking whether a crate installed from a local path is up to date with its source.
+
+use semver::Version;
+use std::path::Path;
+use std::time::SystemTime;
+use workspace::read_cargo_toml;
+
+/// The `package.version` of the crate in `dir`, or `None` if it isn't given directly (for
+/// example, if it is inherited from a workspace).
+pub fn package_version(dir: &Path) -> Result<Option<Version>, String> {
+    let path = dir.join("Cargo.toml");
+    let value = read_cargo_toml(&path)?;
+    let raw_version = match value.get("package").and_then(|p| p.get("version")) {
+        Some(version) => match version.as_str() {
+            Some(version) => version,
+            None => return Ok(None),
+        },
+        None => return Ok(None),
+    };
+    match Version::parse(raw_version) {
+        Ok(version) => Ok(Some(version)),
+        Err(err) => Err(format!(
+            "Invalid version {:?} in {:?}: {:?}",
+            raw_version, path, err
+        )),
+    }
+}
+
+/// The most recent modification time of any file under `dir`, ignoring `target` directories and
+/// hidden files (such as `.git`).
+pub fn newest_modification(dir: &Path) -> Result<Option<SystemTime>, String> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => return Err(format!("Error reading {:?}: {:?}", dir, err)),
+    };
+    let mut newest = None;
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => return Err(format!("Error reading {:?}: {:?}", dir, err)),
+        };
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with('.') || name == "target" {
+            continue;
+        }
+        let path = entry.path();
+        let modified = if path.is_dir() {
+            newest_modification(&path)?
+        } else {
+            modified(&path)
+        };
+        newest = newest.max(modified);
+    }
+    Ok(newest)
+}
+
+/// When `path` was last modified, if it exists and the platform records it.
+pub fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{modified, newest_modification, package_version};
+    use semver::Version;
+    use std::path::PathBuf;
+
+    fn write_crate(name: &str, cargo_toml: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "cargo-ensure-installed-local-{}-{}",
+            name,
+            std::process::id()
+        ));
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::create_dir_all(dir.join("target")).unwrap();
+        std::fs::write(dir.join("Cargo.toml"), cargo_toml).unwrap();
+        std::fs::write(dir.join("src").join("main.rs"), "fn main() {}").unwrap();
+        dir
+    }
+
+    #[test]
+    pub fn versions() {
+        let dir = write_crate(
+            "version",
+            "[package]\nname = \"my-tool\"\nversion = \"0.3.1\"\n",
+        );
+        assert_eq!(
+            package_version(&dir).unwrap(),
+            Some(Version::parse("0.3.1").unwrap())
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+        let dir = write_crate(
+            "inherited",
+            "[package]\nname = \"my-tool\"\nversion.workspace = true\n",
+        );
+        assert_eq!(package_version(&dir).unwrap(), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    pub fn ignores_target() {
+        let dir = write_crate(
+            "target",
+            "[package]\nname = \"my-tool\"\nversion = \"0.1.0\"\n",
+        );
+        let newest = newest_modification(&dir).unwrap();
+        std::fs::write(dir.join("target").join("output"), "").unwrap();
+        assert_eq!(newest_modification(&dir).unwrap(), newest);
+        assert!(newest >= modified(&dir.join("src").join("main.rs")));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
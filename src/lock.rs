@@ -0,0 +1,98 @@
+//! Advisory locks which stop several processes from installing the same package into the same
+//! install root at once.
+//!
+//! Each package has its own lock file, in `.cargo-ensure-installed-locks` in the install root, so
+//! installs of different packages still run in parallel. The state file, which they share, has one
+//! too. The files are left in place, since removing one while another process waits on it would
+//! let a third take a different lock.
+
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+
+/// A held lock, released when dropped.
+pub struct PackageLock {
+    _file: File,
+}
+
+/// The lock file for `package` in the install root whose `.crates.toml` is `crates_toml`.
+fn path_for(crates_toml: &Path, package: &str) -> PathBuf {
+    crates_toml
+        .with_file_name(".cargo-ensure-installed-locks")
+        .join(format!("{}.lock", package))
+}
+
+/// Takes the lock on installing `package` into the install root of `crates_toml`, waiting for
+/// any other process which holds it. `waiting` is called first if it has to wait.
+pub fn lock(crates_toml: &Path, package: &str, waiting: &dyn Fn()) -> Result<PackageLock, String> {
+    take(&path_for(crates_toml, package), waiting)
+}
+
+/// Takes the lock on the state file of the install root of `crates_toml`, which is held only for
+/// as long as it takes to load, change and save it. Its name can't clash with a package's, since
+/// package names can't start with a dot.
+pub fn lock_state(crates_toml: &Path) -> Result<PackageLock, String> {
+    take(&path_for(crates_toml, ".state"), &|| {})
+}
+
+fn take(path: &Path, waiting: &dyn Fn()) -> Result<PackageLock, String> {
+    if let Some(dir) = path.parent() {
+        if let Err(err) = fs::create_dir_all(dir) {
+            return Err(format!("Error creating {:?}: {:?}", dir, err));
+        }
+    }
+    let file = match OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+    {
+        Ok(file) => file,
+        Err(err) => return Err(format!("Error opening {:?}: {:?}", path, err)),
+    };
+    match file.try_lock() {
+        Ok(()) => return Ok(PackageLock { _file: file }),
+        Err(TryLockError::WouldBlock) => waiting(),
+        Err(TryLockError::Error(err)) => {
+            return Err(format!("Error locking {:?}: {:?}", path, err))
+        }
+    }
+    match file.lock() {
+        Ok(()) => Ok(PackageLock { _file: file }),
+        Err(err) => Err(format!("Error locking {:?}: {:?}", path, err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::lock;
+    use std::cell::Cell;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    pub fn waits_for_holder() {
+        let dir = std::env::temp_dir().join(format!(
+            "cargo-ensure-installed-lock-{}",
+            std::process::id()
+        ));
+        let crates_toml = dir.join(".crates.toml");
+        let waited = Cell::new(false);
+        let held = lock(&crates_toml, "rustfmt", &|| waited.set(true)).unwrap();
+        assert!(!waited.get());
+        // Another package's lock is independent.
+        lock(&crates_toml, "cargo-deny", &|| panic!("waited")).unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let other = {
+            let crates_toml = crates_toml.clone();
+            thread::spawn(move || {
+                let _lock = lock(&crates_toml, "rustfmt", &|| sender.send(()).unwrap()).unwrap();
+            })
+        };
+        receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+        drop(held);
+        other.join().unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,214 @@
+use requirement::Requirement;
+use semver::{Version, VersionReq};
+use std::path::Path;
+use toml::value::Table;
+use toml::Value;
+
+pub const LOCKFILE_NAME: &str = "ensure-installed.lock";
+
+/// The exact versions previously resolved for each requirement, so that later runs install the
+/// same versions rather than whatever currently satisfies the requirement.
+///
+/// ```toml
+/// [[package]]
+/// name = "rustfmt"
+/// requirement = "^0.9"
+/// version = "0.9.3"
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Lockfile {
+    pub packages: Vec<LockedPackage>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LockedPackage {
+    pub name: String,
+    /// The requirement the version was resolved for. If the requirement changes, the locked
+    /// version is ignored.
+    pub requirement: String,
+    pub version: Version,
+}
+
+impl Lockfile {
+    /// Reads the lockfile at `path`, treating a missing file as an empty lockfile.
+    pub fn load(path: &Path) -> Result<Lockfile, String> {
+        if !path.exists() {
+            return Ok(Lockfile::default());
+        }
+        match ::read_file_to_string(path) {
+            Ok(contents) => Lockfile::parse(path, &contents),
+            Err(err) => Err(format!("Error reading lockfile {:?}: {:?}", path, err)),
+        }
+    }
+
+    pub fn parse(path: &Path, contents: &str) -> Result<Lockfile, String> {
+        let invalid = |reason: &str| format!("Invalid lockfile {:?}: {}", path, reason);
+        let value = match contents.parse::<Value>() {
+            Ok(v) => v,
+            Err(err) => return Err(format!("Error parsing lockfile {:?}: {:?}", path, err)),
+        };
+        let packages = match value.get("package") {
+            Some(Value::Array(packages)) => packages,
+            Some(_) => return Err(invalid("package was not an array")),
+            None => return Ok(Lockfile::default()),
+        };
+        let mut lockfile = Lockfile::default();
+        for package in packages {
+            let field = |name: &str| match package.get(name).and_then(|v| v.as_str()) {
+                Some(s) => Ok(s.to_owned()),
+                None => Err(invalid(&format!("package entry missing string {}", name))),
+            };
+            let name = field("name")?;
+            let raw_version = field("version")?;
+            let version = match Version::parse(&raw_version) {
+                Ok(v) => v,
+                Err(err) => {
+                    return Err(invalid(&format!(
+                        "{} has invalid version {:?}: {:?}",
+                        name, raw_version, err
+                    )))
+                }
+            };
+            lockfile.packages.push(LockedPackage {
+                name,
+                requirement: field("requirement")?,
+                version,
+            });
+        }
+        Ok(lockfile)
+    }
+
+    /// The version locked for `requirement`, if it was resolved for the same requirement and still
+    /// satisfies it.
+    pub fn locked_version(&self, requirement: &Requirement) -> Option<&Version> {
+        self.packages
+            .iter()
+            .find(|p| p.name == requirement.package && p.requirement == requirement.raw_version)
+            .map(|p| &p.version)
+            .filter(|version| requirement.version.matches(version))
+    }
+
+    /// Returns `requirement` pinned to its locked version, if it has one.
+    pub fn pin(&self, requirement: &Requirement) -> Requirement {
+        let mut pinned = requirement.clone();
+        if let Some(version) = self.locked_version(requirement) {
+            pinned.raw_version = format!("={}", version);
+            pinned.version = VersionReq::exact(version);
+        }
+        pinned
+    }
+
+    /// Records `version` as the resolution of `requirement`, replacing any existing entry for the
+    /// package.
+    pub fn lock(&mut self, requirement: &Requirement, version: Version) {
+        self.packages.retain(|p| p.name != requirement.package);
+        self.packages.push(LockedPackage {
+            name: requirement.package.clone(),
+            requirement: requirement.raw_version.clone(),
+            version,
+        });
+        self.packages.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    pub fn to_toml_string(&self) -> String {
+        let packages = self
+            .packages
+            .iter()
+            .map(|p| {
+                let mut table = Table::new();
+                table.insert("name".to_owned(), Value::String(p.name.clone()));
+                table.insert(
+                    "requirement".to_owned(),
+                    Value::String(p.requirement.clone()),
+                );
+                table.insert("version".to_owned(), Value::String(p.version.to_string()));
+                Value::Table(table)
+            })
+            .collect();
+        let mut root = Table::new();
+        root.insert("package".to_owned(), Value::Array(packages));
+        format!(
+            "# This file is automatically generated by cargo-ensure-installed.\n\
+             # It is not intended for manual editing.\n\n{}",
+            ::toml::to_string(&Value::Table(root)).unwrap()
+        )
+    }
+
+    /// Writes the lockfile to `path`, unless it is unchanged from what was loaded.
+    pub fn save_if_changed(&self, path: &Path, loaded: &Lockfile) -> Result<(), String> {
+        if self == loaded {
+            return Ok(());
+        }
+        match ::std::fs::write(path, self.to_toml_string()) {
+            Ok(()) => Ok(()),
+            Err(err) => Err(format!("Error writing lockfile {:?}: {:?}", path, err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Lockfile;
+    use requirement::Requirement;
+    use semver::Version;
+    use std::path::PathBuf;
+
+    fn some_path() -> PathBuf {
+        PathBuf::from("/path/to/ensure-installed.lock")
+    }
+
+    #[test]
+    pub fn round_trip() {
+        let mut lockfile = Lockfile::default();
+        lockfile.lock(
+            &Requirement::new("rustfmt", "^0.9").unwrap(),
+            Version::parse("0.9.3").unwrap(),
+        );
+        lockfile.lock(
+            &Requirement::new("cargo-deny", "0.14").unwrap(),
+            Version::parse("0.14.2").unwrap(),
+        );
+        let contents = lockfile.to_toml_string();
+        assert_eq!(Lockfile::parse(&some_path(), &contents).unwrap(), lockfile);
+        assert_eq!(lockfile.packages[0].name, "cargo-deny");
+    }
+
+    #[test]
+    pub fn pins_locked_version() {
+        let lockfile = Lockfile::parse(
+            &some_path(),
+            r###"[[package]]
+name = "rustfmt"
+requirement = "^0.9"
+version = "0.9.3""###,
+        )
+        .unwrap();
+        let pinned = lockfile.pin(&Requirement::new("rustfmt", "^0.9").unwrap());
+        assert_eq!(pinned.raw_version, "=0.9.3");
+        assert!(pinned.version.matches(&Version::parse("0.9.3").unwrap()));
+        assert!(!pinned.version.matches(&Version::parse("0.9.4").unwrap()));
+    }
+
+    #[test]
+    pub fn ignores_lock_for_changed_requirement() {
+        let lockfile = Lockfile::parse(
+            &some_path(),
+            r###"[[package]]
+name = "rustfmt"
+requirement = "^0.9"
+version = "0.9.3""###,
+        )
+        .unwrap();
+        let pinned = lockfile.pin(&Requirement::new("rustfmt", "^1.4").unwrap());
+        assert_eq!(pinned.raw_version, "^1.4");
+    }
+
+    #[test]
+    pub fn missing_field() {
+        assert!(Lockfile::parse(
+            &some_path(),
+            "[[package]]\nname = \"rustfmt\"\nversion = \"0.9.3\""
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,528 @@
+//! Structured logs of what we do, through `tracing`, so that our behaviour can be correlated with
+//! the rest of a build's telemetry.
+//!
+//! Checks and installs are spans, carrying the package and backend, and what happens in them is
+//! logged as events. Nothing is printed unless the command line asks for it, with `--log-format`
+//! or `RUST_LOG`, but the command line appends everything, along with all of cargo's output, to a
+//! log file.
+
+use cli::LogFormat;
+use progress;
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::subscriber::{self, Subscriber};
+use tracing::{Event, Level, Metadata};
+
+/// A log file grows until it is this big, and is then moved aside to start a new one.
+const MAX_FILE_LEN: u64 = 10 * 1024 * 1024;
+
+/// Somewhere log events are written.
+pub struct Sink {
+    format: LogFormat,
+    filter: Filter,
+    write: Box<dyn Fn(String) + Send + Sync>,
+}
+
+impl Sink {
+    /// Prints the events `filter` allows to stderr, in `format`.
+    pub fn stderr(format: LogFormat, filter: Filter) -> Sink {
+        Sink {
+            format,
+            filter,
+            write: Box::new(|line| progress::suspend(|| eprintln!("{}", line))),
+        }
+    }
+
+    /// Appends every debug event, in the human format, to the file at `path`, after a line saying
+    /// that a run with `args` started. If it has grown too large, it is first moved aside to
+    /// `path` with `.old` appended, replacing what was there.
+    pub fn file(path: &Path, args: &[String]) -> io::Result<Sink> {
+        if fs::metadata(path).is_ok_and(|metadata| metadata.len() > MAX_FILE_LEN) {
+            let mut old = path.as_os_str().to_owned();
+            old.push(".old");
+            fs::rename(path, old)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(
+            file,
+            "{} started: {}",
+            timestamp(SystemTime::now()),
+            args.join(" ")
+        )?;
+        let file = Mutex::new(file);
+        Ok(Sink {
+            format: LogFormat::Human,
+            filter: Filter::level(Level::DEBUG),
+            write: Box::new(move |line| {
+                let _ = writeln!(file.lock().unwrap(), "{}", line);
+            }),
+        })
+    }
+}
+
+/// Writes events to `sinks` from now on.
+pub fn init(sinks: Vec<Sink>) {
+    if !sinks.is_empty() {
+        let _ = subscriber::set_global_default(Logger::new(sinks));
+    }
+}
+
+/// Which events to log, as `RUST_LOG` chooses them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filter {
+    /// The most verbose level to log for targets no directive names, or `None` for nothing.
+    default: Option<Level>,
+    /// Targets, like `cargo_ensure_installed::installer`, and the most verbose level to log for
+    /// them and their submodules.
+    targets: Vec<(String, Option<Level>)>,
+}
+
+impl Filter {
+    /// Logs everything at `level` and above.
+    pub fn level(level: Level) -> Filter {
+        Filter {
+            default: Some(level),
+            targets: vec![],
+        }
+    }
+
+    /// Parses `RUST_LOG`'s comma-separated directives, as `env_logger` does: each a level like
+    /// `debug` for every target, or a target optionally followed by `=level`, which logs
+    /// everything for that target if the level is left out. Targets no directive names are logged
+    /// at `error` unless a directive says otherwise.
+    pub fn parse(directives: &str) -> Result<Filter, String> {
+        let mut filter = Filter::level(Level::ERROR);
+        for directive in directives.split(',').map(str::trim) {
+            if directive.is_empty() {
+                continue;
+            }
+            let (target, level) = match directive.split_once('=') {
+                Some((target, level)) => (Some(target), parse_level(level)?),
+                None => match parse_level(directive) {
+                    Ok(level) => (None, level),
+                    Err(_) => (Some(directive), Some(Level::TRACE)),
+                },
+            };
+            match target {
+                // Crate names are written with hyphens, but targets have underscores.
+                Some(target) => filter.targets.push((target.replace('-', "_"), level)),
+                None => filter.default = level,
+            }
+        }
+        Ok(filter)
+    }
+
+    /// Whether to log something at `level` for `target`.
+    fn enabled(&self, target: &str, level: &Level) -> bool {
+        let named = self
+            .targets
+            .iter()
+            .filter(|(prefix, _)| {
+                target == prefix
+                    || (target.starts_with(prefix.as_str())
+                        && target[prefix.len()..].starts_with("::"))
+            })
+            .max_by_key(|(prefix, _)| prefix.len());
+        match named.map_or(self.default, |(_, level)| *level) {
+            Some(max_level) => *level <= max_level,
+            None => false,
+        }
+    }
+}
+
+/// Parses a level name, like `debug`, or `off` for `None`.
+fn parse_level(name: &str) -> Result<Option<Level>, String> {
+    match name.trim().to_lowercase().as_str() {
+        "off" => Ok(None),
+        "error" => Ok(Some(Level::ERROR)),
+        "warn" => Ok(Some(Level::WARN)),
+        "info" => Ok(Some(Level::INFO)),
+        "debug" => Ok(Some(Level::DEBUG)),
+        "trace" => Ok(Some(Level::TRACE)),
+        _ => Err(format!(
+            "unknown log level '{}' (expected one of off, error, warn, info, debug, trace)",
+            name
+        )),
+    }
+}
+
+/// A span which has been created, and not yet closed.
+struct SpanData {
+    name: &'static str,
+    fields: Map<String, Value>,
+    /// How many handles to it there are.
+    refs: usize,
+}
+
+thread_local! {
+    /// The spans this thread is in, innermost last.
+    static ENTERED: RefCell<Vec<u64>> = const { RefCell::new(vec![]) };
+}
+
+/// Formats each event, along with the spans it happened in, as a line for each sink which wants it.
+struct Logger {
+    sinks: Vec<Sink>,
+    next_id: AtomicU64,
+    spans: Mutex<HashMap<u64, SpanData>>,
+}
+
+impl Logger {
+    fn new(sinks: Vec<Sink>) -> Logger {
+        Logger {
+            sinks,
+            next_id: AtomicU64::new(1),
+            spans: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Sink {
+    fn format(&self, event: &Event, spans: &[(&'static str, Map<String, Value>)]) -> String {
+        let metadata = event.metadata();
+        let mut fields = Map::new();
+        event.record(&mut Fields(&mut fields));
+        match self.format {
+            LogFormat::Human => {
+                let mut line = format!("{} {:>5} ", timestamp(SystemTime::now()), metadata.level());
+                for (name, fields) in spans {
+                    line.push_str(name);
+                    if !fields.is_empty() {
+                        line.push_str(&format!("{{{}}}", human_fields(fields)));
+                    }
+                    line.push_str(": ");
+                }
+                if let Some(Value::String(message)) = fields.remove("message") {
+                    line.push_str(&message);
+                    if !fields.is_empty() {
+                        line.push(' ');
+                    }
+                }
+                line.push_str(&human_fields(&fields));
+                line
+            }
+            LogFormat::Json => {
+                let mut object = Map::new();
+                object.insert(
+                    "timestamp".to_owned(),
+                    Value::String(timestamp(SystemTime::now())),
+                );
+                object.insert(
+                    "level".to_owned(),
+                    Value::String(metadata.level().to_string()),
+                );
+                object.insert(
+                    "target".to_owned(),
+                    Value::String(metadata.target().to_owned()),
+                );
+                object.insert("fields".to_owned(), Value::Object(fields));
+                let spans = spans
+                    .iter()
+                    .map(|(name, fields)| {
+                        let mut span = fields.clone();
+                        span.insert("name".to_owned(), Value::String((*name).to_owned()));
+                        Value::Object(span)
+                    })
+                    .collect();
+                object.insert("spans".to_owned(), Value::Array(spans));
+                Value::Object(object).to_string()
+            }
+        }
+    }
+}
+
+impl Subscriber for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.sinks
+            .iter()
+            .any(|sink| sink.filter.enabled(metadata.target(), metadata.level()))
+    }
+
+    fn new_span(&self, attributes: &Attributes) -> Id {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut fields = Map::new();
+        attributes.record(&mut Fields(&mut fields));
+        let span = SpanData {
+            name: attributes.metadata().name(),
+            fields,
+            refs: 1,
+        };
+        self.spans.lock().unwrap().insert(id, span);
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record) {
+        if let Some(span) = self.spans.lock().unwrap().get_mut(&span.into_u64()) {
+            values.record(&mut Fields(&mut span.fields));
+        }
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event) {
+        let spans: Vec<_> = ENTERED.with(|entered| {
+            let spans = self.spans.lock().unwrap();
+            entered
+                .borrow()
+                .iter()
+                .filter_map(|id| spans.get(id))
+                .map(|span| (span.name, span.fields.clone()))
+                .collect()
+        });
+        let metadata = event.metadata();
+        for sink in &self.sinks {
+            if sink.filter.enabled(metadata.target(), metadata.level()) {
+                (sink.write)(sink.format(event, &spans));
+            }
+        }
+    }
+
+    fn enter(&self, span: &Id) {
+        ENTERED.with(|entered| entered.borrow_mut().push(span.into_u64()));
+    }
+
+    fn exit(&self, span: &Id) {
+        ENTERED.with(|entered| {
+            let mut entered = entered.borrow_mut();
+            if let Some(index) = entered.iter().rposition(|id| *id == span.into_u64()) {
+                entered.remove(index);
+            }
+        });
+    }
+
+    fn clone_span(&self, id: &Id) -> Id {
+        if let Some(span) = self.spans.lock().unwrap().get_mut(&id.into_u64()) {
+            span.refs += 1;
+        }
+        id.clone()
+    }
+
+    fn try_close(&self, id: Id) -> bool {
+        let mut spans = self.spans.lock().unwrap();
+        let closed = match spans.get_mut(&id.into_u64()) {
+            Some(span) => {
+                span.refs -= 1;
+                span.refs == 0
+            }
+            None => false,
+        };
+        if closed {
+            spans.remove(&id.into_u64());
+        }
+        closed
+    }
+}
+
+/// Collects the fields of a span or event, as JSON.
+struct Fields<'a>(&'a mut Map<String, Value>);
+
+impl<'a> Visit for Fields<'a> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(
+            field.name().to_owned(),
+            Value::String(format!("{:?}", value)),
+        );
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0
+            .insert(field.name().to_owned(), Value::String(value.to_owned()));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_owned(), Value::Bool(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_owned(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_owned(), Value::from(value));
+    }
+}
+
+/// `fields` as `key=value` pairs, with strings unquoted.
+fn human_fields(fields: &Map<String, Value>) -> String {
+    let pairs: Vec<_> = fields
+        .iter()
+        .map(|(key, value)| match *value {
+            Value::String(ref value) => format!("{}={}", key, value),
+            ref value => format!("{}={}", key, value),
+        })
+        .collect();
+    pairs.join(" ")
+}
+
+/// `time` in RFC 3339 format, in UTC, to the millisecond.
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = ((secs / 86_400) as i64, secs % 86_400);
+    // Converts days since the epoch to a date in the proleptic Gregorian calendar, as described at
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{timestamp, Filter, Logger, Sink};
+    use cli::LogFormat;
+    use serde_json::Value;
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, UNIX_EPOCH};
+    use tracing::{subscriber, Level};
+
+    fn log(format: LogFormat) -> Vec<String> {
+        let lines = Arc::new(Mutex::new(vec![]));
+        let written = lines.clone();
+        let logger = Logger::new(vec![Sink {
+            format,
+            filter: Filter::level(Level::INFO),
+            write: Box::new(move |line| written.lock().unwrap().push(line)),
+        }]);
+        subscriber::with_default(logger, || {
+            let span = info_span!("ensure", package = "rustfmt");
+            let _entered = span.enter();
+            info!(installed = "1.4.2", install = false, "decided");
+            debug!("hidden");
+        });
+        let lines = lines.lock().unwrap().clone();
+        lines
+    }
+
+    #[test]
+    pub fn formats_human() {
+        let lines = log(LogFormat::Human);
+        assert_eq!(lines.len(), 1);
+        assert!(
+            lines[0]
+                .ends_with("  INFO ensure{package=rustfmt}: decided install=false installed=1.4.2"),
+            "{}",
+            lines[0]
+        );
+    }
+
+    #[test]
+    pub fn formats_json() {
+        let lines = log(LogFormat::Json);
+        assert_eq!(lines.len(), 1);
+        let mut line: Value = ::serde_json::from_str(&lines[0]).unwrap();
+        line.as_object_mut().unwrap().remove("timestamp");
+        assert_eq!(
+            line.to_string(),
+            r#"{"fields":{"install":false,"installed":"1.4.2","message":"decided"},"level":"INFO","spans":[{"name":"ensure","package":"rustfmt"}],"target":"cargo_ensure_installed::log::tests"}"#
+        );
+    }
+
+    #[test]
+    pub fn filters() {
+        let enabled = |directives: &str, target: &str, level: Level| {
+            Filter::parse(directives).unwrap().enabled(target, &level)
+        };
+        let installer = "cargo_ensure_installed::installer";
+        assert!(enabled("debug", installer, Level::DEBUG));
+        assert!(!enabled("debug", installer, Level::TRACE));
+        assert!(!enabled("", installer, Level::WARN));
+        assert!(enabled("", installer, Level::ERROR));
+        assert!(enabled("cargo-ensure-installed", installer, Level::TRACE));
+        assert!(!enabled("cargo_ensure", installer, Level::INFO));
+        assert!(enabled(
+            "info,cargo_ensure_installed::installer=debug",
+            installer,
+            Level::DEBUG
+        ));
+        assert!(!enabled(
+            "info,cargo_ensure_installed::installer=debug",
+            "cargo_ensure_installed::log",
+            Level::DEBUG
+        ));
+        assert!(!enabled(
+            "cargo_ensure_installed=debug,cargo_ensure_installed::installer=off",
+            installer,
+            Level::ERROR
+        ));
+        assert!(!enabled("off", installer, Level::ERROR));
+        assert!(Filter::parse("cargo_ensure_installed=loud").is_err());
+    }
+
+    #[test]
+    pub fn appends_to_files() {
+        let path = std::env::temp_dir().join(format!(
+            "cargo-ensure-installed-log-{}.log",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        for run in ["first", "second"] {
+            let sink = Sink::file(
+                &path,
+                &["cargo-ensure-installed".to_owned(), run.to_owned()],
+            );
+            subscriber::with_default(Logger::new(vec![sink.unwrap()]), || {
+                debug!(line = "   Compiling rustfmt", "cargo stderr");
+                trace!("hidden");
+            });
+        }
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(lines.len(), 4, "{}", contents);
+        assert!(lines[0].ends_with(" started: cargo-ensure-installed first"));
+        assert!(lines[1].ends_with("DEBUG cargo stderr line=   Compiling rustfmt"));
+        assert!(lines[2].ends_with(" started: cargo-ensure-installed second"));
+
+        // Once it's too big, it's moved aside.
+        fs::write(&path, vec![b'\n'; super::MAX_FILE_LEN as usize + 1]).unwrap();
+        Sink::file(&path, &[]).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+        let old = path.with_extension("log.old");
+        assert!(fs::metadata(&old).unwrap().len() > super::MAX_FILE_LEN);
+        fs::remove_file(path).unwrap();
+        fs::remove_file(old).unwrap();
+    }
+
+    #[test]
+    pub fn timestamps() {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        assert_eq!(
+            timestamp(UNIX_EPOCH + Duration::from_millis(1_791_986_405_250)),
+            "2026-10-14T14:00:05.250Z"
+        );
+        assert_eq!(
+            timestamp(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "2000-02-29T00:00:00.000Z"
+        );
+    }
+}
@@ -0,0 +1,906 @@
+use backend::{Backend, GithubRelease};
+use cfg::Cfg;
+use detect;
+use requirement::{merge, Requirement};
+use source::Source;
+use std::path::{Path, PathBuf};
+use toml::Value;
+
+/// The file formats a manifest may be written in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Toml,
+    Json,
+    Yaml,
+}
+
+impl Format {
+    /// Detects the format of a manifest from its extension, defaulting to TOML.
+    pub fn from_path(path: &Path) -> Format {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Format::Json,
+            Some("yaml") | Some("yml") => Format::Yaml,
+            _ => Format::Toml,
+        }
+    }
+
+    fn parse(self, contents: &str) -> Result<Value, String> {
+        match self {
+            Format::Toml => contents
+                .parse::<Value>()
+                .map_err(|err| format!("{:?}", err)),
+            Format::Json => serde_json::from_str(contents).map_err(|err| err.to_string()),
+            Format::Yaml => serde_yaml::from_str(contents).map_err(|err| err.to_string()),
+        }
+    }
+}
+
+/// Reads the tools manifest at `path`, in the format indicated by its extension.
+///
+/// A manifest lists tools under a `[tools]` table, either as a bare version requirement or as a
+/// table with a `version` key:
+///
+/// ```toml
+/// [tools]
+/// rustfmt = "0.9"
+/// cargo-deny = { version = "0.14" }
+/// cargo-xwin = { version = "0.16", cfg = 'target_os = "windows"' }
+/// cargo-release = { version = "0.25", groups = ["release"] }
+/// ```
+///
+/// Table entries may carry a `cfg` condition, in which case the tool is only required on matching
+/// platforms, and a list of `groups` which can be selected with `--group`.
+///
+/// A manifest may `include = ["../shared-tools.toml"]` other manifests, resolved relative to the
+/// including manifest. Entries in the including manifest override included entries for the same
+/// tool, and later includes override earlier ones.
+///
+/// JSON and YAML manifests have the same structure.
+pub fn load_manifest(path: &Path) -> Result<Vec<Requirement>, String> {
+    load_manifest_included_from(path, &mut vec![])
+}
+
+/// Loads the manifest at `path`, where `stack` holds the chain of manifests which included it.
+fn load_manifest_included_from(
+    path: &Path,
+    stack: &mut Vec<PathBuf>,
+) -> Result<Vec<Requirement>, String> {
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+    if stack.contains(&canonical) {
+        let cycle: Vec<_> = stack
+            .iter()
+            .chain(Some(&canonical))
+            .map(|p| format!("{:?}", p))
+            .collect();
+        return Err(format!("Manifest include cycle: {}", cycle.join(" -> ")));
+    }
+
+    let contents = match ::read_file_to_string(path) {
+        Ok(s) => s,
+        Err(err) => return Err(format!("Error reading manifest {:?}: {:?}", path, err)),
+    };
+    let value = parse_manifest_value(path, Format::from_path(path), &contents)?;
+
+    let mut requirements = vec![];
+    stack.push(canonical);
+    for include in parse_includes(path, &value)? {
+        let include_path = path.parent().unwrap_or_else(|| Path::new("")).join(include);
+        match load_manifest_included_from(&include_path, stack) {
+            Ok(included) => merge(&mut requirements, included),
+            Err(err) => {
+                return Err(format!("{}\n  included from {:?}", err, path));
+            }
+        }
+    }
+    stack.pop();
+
+    merge(&mut requirements, parse_tools(path, &value)?);
+    Ok(requirements)
+}
+
+fn parse_manifest_value(path: &Path, format: Format, contents: &str) -> Result<Value, String> {
+    match format.parse(contents) {
+        Ok(v) => Ok(v),
+        Err(err) => Err(format!("Error parsing manifest {:?}: {}", path, err)),
+    }
+}
+
+fn parse_tools(path: &Path, value: &Value) -> Result<Vec<Requirement>, String> {
+    match value.get("tools") {
+        Some(tools) => parse_tools_table(path, "tools", tools),
+        None => Ok(vec![]),
+    }
+}
+
+fn parse_includes(path: &Path, value: &Value) -> Result<Vec<String>, String> {
+    let includes = match value.get("include") {
+        Some(includes) => includes,
+        None => return Ok(vec![]),
+    };
+    let invalid = || {
+        format!(
+            "Invalid manifest {:?}: include was not an array of strings.",
+            path
+        )
+    };
+    let includes = match includes.as_array() {
+        Some(includes) => includes,
+        None => return Err(invalid()),
+    };
+    let mut paths = Vec::with_capacity(includes.len());
+    for include in includes {
+        match include.as_str() {
+            Some(include) => paths.push(include.to_owned()),
+            None => return Err(invalid()),
+        }
+    }
+    Ok(paths)
+}
+
+/// Parses a table of tool entries, such as the `[tools]` table of a manifest or a
+/// `metadata.ensure-installed` table of a `Cargo.toml`, reporting every invalid entry at once.
+pub fn parse_tools_table(
+    path: &Path,
+    name: &str,
+    tools: &Value,
+) -> Result<Vec<Requirement>, String> {
+    let tools = match tools.as_table() {
+        Some(t) => t,
+        None => {
+            return Err(format!(
+                "Invalid manifest {:?}: {} was not a table.",
+                path, name
+            ))
+        }
+    };
+
+    let mut requirements = Vec::with_capacity(tools.len());
+    let mut errors = vec![];
+    for (package, entry) in tools {
+        match parse_entry(path, package, entry) {
+            Ok(requirement) => requirements.push(requirement),
+            Err(err) => errors.push(format!("  {}: {}", package, err)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(format!(
+            "Invalid manifest {:?}:\n{}",
+            path,
+            errors.join("\n")
+        ));
+    }
+    Ok(requirements)
+}
+
+/// Parses the entry for `package` in the manifest at `path`.
+fn parse_entry(path: &Path, package: &str, entry: &Value) -> Result<Requirement, String> {
+    let entry = expand_env_vars(entry, &|name| std::env::var(name).ok())?;
+    let table = match entry {
+        Value::String(ref version) => return Ok(Requirement::new(package, version)?),
+        Value::Table(ref table) => table,
+        _ => return Err("expected a version string or a table".to_owned()),
+    };
+    let raw_version = match table.get("version") {
+        Some(Value::String(version)) => version.as_str(),
+        Some(_) => return Err("version was not a string".to_owned()),
+        None => "*",
+    };
+    let mut requirement = Requirement::new(package, raw_version)?;
+    requirement.platform = match table.get("cfg") {
+        Some(Value::String(cfg)) => Some(Cfg::parse(cfg)?),
+        Some(_) => return Err("cfg was not a string".to_owned()),
+        None => None,
+    };
+    requirement.groups = string_array(table, "groups")?;
+    for feature in string_array(table, "features")? {
+        requirement.add_feature(&feature);
+    }
+    requirement.all_features = boolean(table, "all-features")?.unwrap_or(false);
+    requirement.no_default_features = !boolean(table, "default-features")?.unwrap_or(true);
+    requirement.locked = boolean(table, "locked")?.unwrap_or(false);
+    requirement.rebuild_on_rustc_change =
+        boolean(table, "rebuild-on-rustc-change")?.unwrap_or(false);
+    requirement.bins = string_array(table, "bins")?;
+    requirement.profile = string(table, "profile")?.map(str::to_owned);
+    requirement.target = string(table, "target")?.map(str::to_owned);
+    requirement.toolchain = string(table, "toolchain")?.map(str::to_owned);
+    requirement.version_regex = string(table, "version-regex")?.map(str::to_owned);
+    if let Some(ref regex) = requirement.version_regex {
+        detect::validate_regex(regex)?;
+    }
+    requirement.github = github_release(table)?;
+    if requirement.github.is_some() {
+        requirement.backends = vec![Backend::GithubRelease];
+    }
+    let backends = match table.get("backend") {
+        Some(Value::String(backend)) => vec![backend.clone()],
+        Some(_) => string_array(table, "backend")?,
+        None => vec![],
+    };
+    if !backends.is_empty() {
+        requirement.backends = backends
+            .iter()
+            .map(|backend| Backend::parse(backend))
+            .collect::<Result<_, _>>()?;
+    }
+    let branch = string(table, "branch")?;
+    let tag = string(table, "tag")?;
+    let rev = string(table, "rev")?;
+    let git = string(table, "git")?;
+    let local_path = string(table, "path")?;
+    let registry = string(table, "registry")?;
+    let index = string(table, "index")?;
+    let sources = [git, local_path, registry, index];
+    if sources.iter().flatten().count() > 1 {
+        return Err("only one of git, path, registry and index may be specified".to_owned());
+    }
+    if git.is_none() && (branch.is_some() || tag.is_some() || rev.is_some()) {
+        return Err("branch, tag and rev may only be used with git".to_owned());
+    }
+    if let Some(url) = git {
+        requirement.source = Source::git(url, branch, tag, rev)?;
+    } else if let Some(dir) = local_path {
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        requirement.source = Source::Path(base.join(dir));
+    } else if let Some(name) = registry {
+        requirement.source = Source::NamedRegistry(name.to_owned());
+    } else if let Some(url) = index {
+        requirement.source = Source::Index(url.to_owned());
+    }
+    Ok(requirement)
+}
+
+/// Reads the optional `github` table, which says where the package's release binaries are.
+fn github_release(table: &toml::value::Table) -> Result<Option<GithubRelease>, String> {
+    let github = match table.get("github") {
+        Some(Value::Table(github)) => github,
+        Some(_) => return Err("github was not a table".to_owned()),
+        None => return Ok(None),
+    };
+    let required = |key: &str| match string(github, key)? {
+        Some(value) => Ok(value.to_owned()),
+        None => Err(format!("github.{} must be specified", key)),
+    };
+    Ok(Some(GithubRelease {
+        repo: required("repo")?,
+        tag: string(github, "tag")?.unwrap_or("v{version}").to_owned(),
+        asset: required("asset")?,
+        checksum: string(github, "checksum")?.map(str::to_owned),
+    }))
+}
+
+/// Reads an optional string from `table`.
+fn string<'a>(table: &'a toml::value::Table, key: &str) -> Result<Option<&'a str>, String> {
+    match table.get(key) {
+        Some(Value::String(value)) => Ok(Some(value)),
+        Some(_) => Err(format!("{} was not a string", key)),
+        None => Ok(None),
+    }
+}
+
+/// Reads an optional boolean from `table`.
+fn boolean(table: &toml::value::Table, key: &str) -> Result<Option<bool>, String> {
+    match table.get(key) {
+        Some(Value::Boolean(value)) => Ok(Some(*value)),
+        Some(_) => Err(format!("{} was not a boolean", key)),
+        None => Ok(None),
+    }
+}
+
+/// Reads an optional array of strings from `table`.
+fn string_array(table: &toml::value::Table, key: &str) -> Result<Vec<String>, String> {
+    let values = match table.get(key) {
+        Some(Value::Array(values)) => values,
+        Some(_) => return Err(format!("{} was not an array", key)),
+        None => return Ok(vec![]),
+    };
+    let mut strings = Vec::with_capacity(values.len());
+    for value in values {
+        match value.as_str() {
+            Some(s) => strings.push(s.to_owned()),
+            None => return Err(format!("{} contained a non-string", key)),
+        }
+    }
+    Ok(strings)
+}
+
+/// Replaces `${VAR}` in every string within `value` with the value of the environment variable
+/// `VAR`, as returned by `lookup`. `$$` produces a literal `$`.
+fn expand_env_vars(
+    value: &Value,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<Value, String> {
+    match *value {
+        Value::String(ref s) => Ok(Value::String(expand_env_vars_in_str(s, lookup)?)),
+        Value::Array(ref values) => {
+            let mut expanded = Vec::with_capacity(values.len());
+            for value in values {
+                expanded.push(expand_env_vars(value, lookup)?);
+            }
+            Ok(Value::Array(expanded))
+        }
+        Value::Table(ref table) => {
+            let mut expanded = table.clone();
+            for (key, value) in table {
+                expanded.insert(key.clone(), expand_env_vars(value, lookup)?);
+            }
+            Ok(Value::Table(expanded))
+        }
+        ref other => Ok(other.clone()),
+    }
+}
+
+fn expand_env_vars_in_str(
+    s: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<String, String> {
+    let mut expanded = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(index) = rest.find('$') {
+        expanded.push_str(&rest[..index]);
+        rest = &rest[index + 1..];
+        if rest.starts_with('$') {
+            expanded.push('$');
+            rest = &rest[1..];
+        } else if rest.starts_with('{') {
+            let end = match rest.find('}') {
+                Some(end) => end,
+                None => return Err(format!("unterminated ${{ in {:?}", s)),
+            };
+            let name = &rest[1..end];
+            match lookup(name) {
+                Some(value) => expanded.push_str(&value),
+                None => {
+                    return Err(format!(
+                        "environment variable {} referenced in {:?} is not set",
+                        name, s
+                    ))
+                }
+            }
+            rest = &rest[end + 1..];
+        } else {
+            expanded.push('$');
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expand_env_vars_in_str, load_manifest, parse_manifest_value, parse_tools, Format};
+    use backend::{Backend, GithubRelease};
+    use cfg::Cfg;
+    use requirement::Requirement;
+    use semver::VersionReq;
+    use source::Source;
+    use std::path::{Path, PathBuf};
+
+    fn some_path() -> PathBuf {
+        PathBuf::from("/path/to/tools.toml")
+    }
+
+    fn parse_manifest(
+        path: &Path,
+        format: Format,
+        contents: &str,
+    ) -> Result<Vec<Requirement>, String> {
+        parse_tools(path, &parse_manifest_value(path, format, contents)?)
+    }
+
+    #[test]
+    pub fn empty() {
+        assert_eq!(
+            parse_manifest(&some_path(), Format::Toml, "")
+                .unwrap()
+                .len(),
+            0
+        );
+    }
+
+    #[test]
+    pub fn string_and_table_entries() {
+        let requirements = parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+rustfmt = "0.9"
+cargo-deny = { version = "0.14" }
+cargo-audit = {}"###,
+        )
+        .unwrap();
+        assert_eq!(requirements.len(), 3);
+        assert_eq!(requirements[0].package, "cargo-audit");
+        assert_eq!(requirements[0].raw_version, "*");
+        assert_eq!(requirements[1].package, "cargo-deny");
+        assert_eq!(requirements[1].version, VersionReq::parse("0.14").unwrap());
+        assert_eq!(requirements[2].package, "rustfmt");
+        assert_eq!(requirements[2].version, VersionReq::parse("0.9").unwrap());
+    }
+
+    #[test]
+    pub fn reports_every_invalid_entry() {
+        let err = parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+rustfmt = "not-a-version"
+cargo-deny = 14
+cargo-audit = "0.18""###,
+        )
+        .err()
+        .unwrap();
+        assert!(err.contains("rustfmt"), "{}", err);
+        assert!(err.contains("cargo-deny"), "{}", err);
+        assert!(!err.contains("cargo-audit"), "{}", err);
+    }
+
+    #[test]
+    pub fn tools_not_a_table() {
+        assert!(parse_manifest(&some_path(), Format::Toml, "tools = 1").is_err());
+    }
+
+    #[test]
+    pub fn format_from_path() {
+        assert_eq!(
+            Format::from_path(&PathBuf::from("tools.toml")),
+            Format::Toml
+        );
+        assert_eq!(
+            Format::from_path(&PathBuf::from("tools.json")),
+            Format::Json
+        );
+        assert_eq!(
+            Format::from_path(&PathBuf::from("tools.yaml")),
+            Format::Yaml
+        );
+        assert_eq!(Format::from_path(&PathBuf::from("tools.yml")), Format::Yaml);
+        assert_eq!(Format::from_path(&PathBuf::from("tools")), Format::Toml);
+    }
+
+    #[test]
+    pub fn json() {
+        let requirements = parse_manifest(
+            &some_path(),
+            Format::Json,
+            r###"{"tools": {"rustfmt": "0.9", "cargo-deny": {"version": "0.14"}}}"###,
+        )
+        .unwrap();
+        assert_eq!(requirements.len(), 2);
+        assert_eq!(requirements[0].package, "cargo-deny");
+        assert_eq!(requirements[0].raw_version, "0.14");
+        assert_eq!(requirements[1].package, "rustfmt");
+        assert_eq!(requirements[1].raw_version, "0.9");
+    }
+
+    #[test]
+    pub fn yaml() {
+        let requirements = parse_manifest(
+            &some_path(),
+            Format::Yaml,
+            r###"tools:
+  rustfmt: "0.9"
+  cargo-deny:
+    version: "0.14""###,
+        )
+        .unwrap();
+        assert_eq!(requirements.len(), 2);
+        assert_eq!(requirements[0].package, "cargo-deny");
+        assert_eq!(requirements[0].raw_version, "0.14");
+        assert_eq!(requirements[1].package, "rustfmt");
+        assert_eq!(requirements[1].raw_version, "0.9");
+    }
+
+    #[test]
+    pub fn cfg() {
+        let requirements = parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+rustfmt = "0.9"
+cargo-xwin = { version = "0.16", cfg = 'target_os = "windows"' }"###,
+        )
+        .unwrap();
+        assert_eq!(
+            requirements[0].platform,
+            Some(Cfg::KeyValue("target_os".to_owned(), "windows".to_owned()))
+        );
+        assert_eq!(requirements[1].platform, None);
+    }
+
+    #[test]
+    pub fn invalid_cfg() {
+        let err = parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+cargo-xwin = { version = "0.16", cfg = "target_os = " }"###,
+        )
+        .err()
+        .unwrap();
+        assert!(err.contains("cargo-xwin"), "{}", err);
+    }
+
+    #[test]
+    pub fn groups() {
+        let requirements = parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+rustfmt = { version = "0.9", groups = ["ci", "dev"] }
+cargo-release = "0.25""###,
+        )
+        .unwrap();
+        assert_eq!(requirements[0].package, "cargo-release");
+        assert_eq!(requirements[0].groups, Vec::<String>::new());
+        assert_eq!(requirements[1].package, "rustfmt");
+        assert_eq!(requirements[1].groups, vec!["ci", "dev"]);
+    }
+
+    #[test]
+    pub fn invalid_groups() {
+        assert!(parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+rustfmt = { version = "0.9", groups = "ci" }"###,
+        )
+        .is_err());
+    }
+
+    #[test]
+    pub fn features() {
+        let requirements = parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+sqlx-cli = { version = "0.7", features = ["postgres", "rustls"] }"###,
+        )
+        .unwrap();
+        assert_eq!(requirements[0].features, vec!["postgres", "rustls"]);
+        assert!(!requirements[0].all_features);
+        assert!(!requirements[0].no_default_features);
+
+        let requirements = parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+cargo-udeps = { version = "0.1", all-features = true, default-features = false }"###,
+        )
+        .unwrap();
+        assert!(requirements[0].all_features);
+        assert!(requirements[0].no_default_features);
+        assert!(!requirements[0].locked);
+
+        assert!(parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+cargo-udeps = { version = "0.1", all-features = "yes" }"###,
+        )
+        .is_err());
+    }
+
+    #[test]
+    pub fn locked() {
+        let requirements = parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+cargo-deny = { version = "0.14", locked = true }"###,
+        )
+        .unwrap();
+        assert!(requirements[0].locked);
+    }
+
+    #[test]
+    pub fn bins() {
+        let requirements = parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+cargo-binutils = { version = "0.3", bins = ["cargo-size", "cargo-objdump"] }"###,
+        )
+        .unwrap();
+        assert_eq!(requirements[0].bins, vec!["cargo-size", "cargo-objdump"]);
+        assert_eq!(requirements[0].profile, None);
+    }
+
+    #[test]
+    pub fn profile() {
+        let requirements = parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+cargo-nextest = { version = "0.9", profile = "dev", target = "x86_64-unknown-linux-musl" }"###,
+        )
+        .unwrap();
+        assert_eq!(requirements[0].profile, Some("dev".to_owned()));
+        assert_eq!(requirements[0].toolchain, None);
+        assert_eq!(
+            requirements[0].target,
+            Some("x86_64-unknown-linux-musl".to_owned())
+        );
+    }
+
+    #[test]
+    pub fn git() {
+        let requirements = parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+grpcio-compiler = { git = "https://github.com/pingcap/grpc-rs", tag = "v0.2.0" }"###,
+        )
+        .unwrap();
+        assert_eq!(requirements[0].raw_version, "*");
+        assert_eq!(
+            requirements[0].source,
+            Source::git(
+                "https://github.com/pingcap/grpc-rs",
+                None,
+                Some("v0.2.0"),
+                None
+            )
+            .unwrap()
+        );
+
+        assert!(parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+grpcio-compiler = { version = "0.2", rev = "ccc979" }"###,
+        )
+        .is_err());
+    }
+
+    #[test]
+    pub fn path() {
+        let requirements = parse_manifest(
+            Path::new("/src/repo/tools.toml"),
+            Format::Toml,
+            r###"[tools]
+my-tool = { path = "tools/my-tool" }"###,
+        )
+        .unwrap();
+        assert_eq!(
+            requirements[0].source,
+            Source::Path(PathBuf::from("/src/repo/tools/my-tool"))
+        );
+    }
+
+    #[test]
+    pub fn registries() {
+        let requirements = parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+internal-tool = { version = "1", registry = "internal" }
+other-tool = { version = "1", index = "sparse+https://registry.example.com/" }"###,
+        )
+        .unwrap();
+        assert_eq!(
+            requirements[0].source,
+            Source::NamedRegistry("internal".to_owned())
+        );
+        assert_eq!(
+            requirements[1].source,
+            Source::Index("sparse+https://registry.example.com/".to_owned())
+        );
+
+        assert!(parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+internal-tool = { registry = "internal", git = "https://example.com/tool" }"###,
+        )
+        .is_err());
+    }
+
+    #[test]
+    pub fn backend() {
+        let requirements = parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+cargo-nextest = { version = "0.9", backend = "binstall" }
+ripgrep = { version = "14", backend = ["quickinstall", "source"] }
+rustfmt = "0.9""###,
+        )
+        .unwrap();
+        assert_eq!(requirements[0].backends, vec![Backend::Binstall]);
+        assert_eq!(
+            requirements[1].backends,
+            vec![Backend::Quickinstall, Backend::Source]
+        );
+        assert_eq!(requirements[2].backends, vec![Backend::Source]);
+
+        let err = parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+ripgrep = { version = "14", backend = "apt" }"###,
+        )
+        .err()
+        .unwrap();
+        assert!(err.contains("ripgrep: unknown backend 'apt'"), "{}", err);
+    }
+
+    #[test]
+    pub fn github() {
+        let requirements = parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+cargo-nextest = { version = "0.9", github = { repo = "nextest-rs/nextest", tag = "cargo-nextest-{version}", asset = "cargo-nextest-{version}-{target}.tar.gz" } }"###,
+        )
+        .unwrap();
+        assert_eq!(requirements[0].backends, vec![Backend::GithubRelease]);
+        assert_eq!(
+            requirements[0].github,
+            Some(GithubRelease {
+                repo: "nextest-rs/nextest".to_owned(),
+                tag: "cargo-nextest-{version}".to_owned(),
+                asset: "cargo-nextest-{version}-{target}.tar.gz".to_owned(),
+                checksum: None,
+            })
+        );
+        assert!(parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+cargo-nextest = { version = "0.9", github = { repo = "nextest-rs/nextest" } }"###,
+        )
+        .is_err());
+    }
+
+    #[test]
+    pub fn toolchain() {
+        let requirements = parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+cargo-udeps = { version = "0.1", toolchain = "nightly-2024-01-01" }"###,
+        )
+        .unwrap();
+        assert_eq!(
+            requirements[0].toolchain,
+            Some("nightly-2024-01-01".to_owned())
+        );
+    }
+
+    #[test]
+    pub fn version_regex() {
+        let requirements = parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+protoc = { version = "3", version-regex = "libprotoc (\\S+)" }"###,
+        )
+        .unwrap();
+        assert_eq!(
+            requirements[0].version_regex,
+            Some(r"libprotoc (\S+)".to_owned())
+        );
+        assert!(parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+protoc = { version = "3", version-regex = "libprotoc (" }"###,
+        )
+        .is_err());
+    }
+
+    fn write_manifests(name: &str, manifests: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "cargo-ensure-installed-{}-{}",
+            name,
+            std::process::id()
+        ));
+        for &(file, contents) in manifests {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    pub fn include() {
+        let dir = write_manifests(
+            "include",
+            &[
+                (
+                    "shared/tools.toml",
+                    "[tools]\nrustfmt = \"0.9\"\ncargo-deny = \"0.14\"",
+                ),
+                (
+                    "project/tools.toml",
+                    "include = [\"../shared/tools.toml\"]\n[tools]\nrustfmt = \"1.4\"",
+                ),
+            ],
+        );
+        let requirements = load_manifest(&dir.join("project/tools.toml")).unwrap();
+        let summary: Vec<_> = requirements
+            .iter()
+            .map(|r| format!("{}@{}", r.package, r.raw_version))
+            .collect();
+        assert_eq!(summary, vec!["cargo-deny@0.14", "rustfmt@1.4"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    pub fn include_cycle() {
+        let dir = write_manifests(
+            "include-cycle",
+            &[
+                ("a.toml", "include = [\"b.toml\"]"),
+                ("b.toml", "include = [\"a.toml\"]"),
+            ],
+        );
+        let err = load_manifest(&dir.join("a.toml")).err().unwrap();
+        assert!(err.starts_with("Manifest include cycle"), "{}", err);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    pub fn include_error_provenance() {
+        let dir = write_manifests(
+            "include-provenance",
+            &[
+                ("shared.toml", "[tools]\nrustfmt = \"nope\""),
+                ("tools.toml", "include = [\"shared.toml\"]"),
+            ],
+        );
+        let err = load_manifest(&dir.join("tools.toml")).err().unwrap();
+        assert!(err.contains("shared.toml"), "{}", err);
+        assert!(err.contains("included from"), "{}", err);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "MIRROR" => Some("https://mirror.example.com".to_owned()),
+            "RUSTFMT_VERSION" => Some("1.4".to_owned()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    pub fn expand_env_vars() {
+        assert_eq!(
+            expand_env_vars_in_str("${MIRROR}/git/tool.git", &lookup),
+            Ok("https://mirror.example.com/git/tool.git".to_owned())
+        );
+        assert_eq!(
+            expand_env_vars_in_str("^${RUSTFMT_VERSION}", &lookup),
+            Ok("^1.4".to_owned())
+        );
+        assert_eq!(
+            expand_env_vars_in_str("$$5 and $HOME", &lookup),
+            Ok("$5 and $HOME".to_owned())
+        );
+        assert!(expand_env_vars_in_str("${UNSET}", &lookup)
+            .err()
+            .unwrap()
+            .contains("UNSET"));
+        assert!(expand_env_vars_in_str("${MIRROR", &lookup).is_err());
+    }
+
+    #[test]
+    pub fn unset_env_var_names_entry() {
+        let err = parse_manifest(
+            &some_path(),
+            Format::Toml,
+            r###"[tools]
+rustfmt = "${CARGO_ENSURE_INSTALLED_TEST_UNSET_VAR}""###,
+        )
+        .err()
+        .unwrap();
+        assert!(err.contains("rustfmt"), "{}", err);
+        assert!(
+            err.contains("CARGO_ENSURE_INSTALLED_TEST_UNSET_VAR"),
+            "{}",
+            err
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! Diagnostic output, filtered by how verbose we were asked to be.
+
+use cli::MessageFormat;
+use std::env;
+use std::ffi::OsString;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, AtomicIsize, AtomicU8, Ordering};
+
+/// Only print errors.
+pub const QUIET: isize = -1;
+/// Explain why each package is or isn't installed, and which commands are run.
+pub const VERBOSE: isize = 1;
+/// Additionally print the parsed `.crates.toml` state, and pass `--verbose` to cargo.
+pub const VERY_VERBOSE: isize = 2;
+
+static VERBOSITY: AtomicIsize = AtomicIsize::new(0);
+static MESSAGE_FORMAT: AtomicU8 = AtomicU8::new(MessageFormat::Human as u8);
+static COLOR_STDOUT: AtomicBool = AtomicBool::new(false);
+static COLOR_STDERR: AtomicBool = AtomicBool::new(false);
+
+pub fn set_verbosity(verbosity: isize) {
+    VERBOSITY.store(verbosity, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> isize {
+    VERBOSITY.load(Ordering::Relaxed)
+}
+
+pub fn set_message_format(format: MessageFormat) {
+    MESSAGE_FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+pub fn message_format() -> MessageFormat {
+    match MESSAGE_FORMAT.load(Ordering::Relaxed) {
+        format if format == MessageFormat::Json as u8 => MessageFormat::Json,
+        format if format == MessageFormat::JsonEvents as u8 => MessageFormat::JsonEvents,
+        _ => MessageFormat::Human,
+    }
+}
+
+/// Whether stdout is kept for output for tools to read, as with `--message-format json`, so that
+/// what we print for people goes to stderr instead.
+pub fn machine_readable() -> bool {
+    message_format() != MessageFormat::Human
+}
+
+/// A color to print something in, as an ANSI SGR code.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Color {
+    /// For things which were fine already.
+    Green = 32,
+    /// For changes.
+    Yellow = 33,
+    /// For failures.
+    Red = 31,
+}
+
+/// Whether to color what is printed to a stream, given `NO_COLOR` and `CLICOLOR_FORCE` as
+/// https://no-color.org and https://bixense.com/clicolors describe them, and whether the stream is
+/// a terminal.
+pub fn use_color(
+    no_color: Option<OsString>,
+    clicolor_force: Option<OsString>,
+    terminal: bool,
+) -> bool {
+    if no_color.is_some_and(|value| !value.is_empty()) {
+        return false;
+    }
+    if clicolor_force.is_some_and(|value| !value.is_empty() && value != "0") {
+        return true;
+    }
+    terminal
+}
+
+/// Colors what is printed to stdout and stderr from now on, if they are terminals and the
+/// environment doesn't say otherwise. Nothing is colored until this is called.
+pub fn init_color() {
+    let color = |terminal| {
+        use_color(
+            env::var_os("NO_COLOR"),
+            env::var_os("CLICOLOR_FORCE"),
+            terminal,
+        )
+    };
+    COLOR_STDOUT.store(color(io::stdout().is_terminal()), Ordering::Relaxed);
+    COLOR_STDERR.store(color(io::stderr().is_terminal()), Ordering::Relaxed);
+}
+
+/// `text` in `color`, if `colored`.
+fn paint(colored: bool, color: Color, text: &str) -> String {
+    if colored {
+        format!("\x1b[{}m{}\x1b[0m", color as u8, text)
+    } else {
+        text.to_owned()
+    }
+}
+
+/// `text` in `color`, if what `human!` prints to is colored.
+pub fn human_color(color: Color, text: &str) -> String {
+    let colored = if machine_readable() {
+        &COLOR_STDERR
+    } else {
+        &COLOR_STDOUT
+    };
+    paint(colored.load(Ordering::Relaxed), color, text)
+}
+
+/// `text` in `color`, if stderr is colored.
+pub fn stderr_color(color: Color, text: &str) -> String {
+    paint(COLOR_STDERR.load(Ordering::Relaxed), color, text)
+}
+
+/// Prints to stderr if the verbosity is at least `$level`.
+macro_rules! verbose {
+    ($level:expr, $($arg:tt)*) => {
+        if $crate::output::verbosity() >= $level {
+            let line = format!($($arg)*);
+            $crate::progress::suspend(|| eprintln!("{}", line));
+        }
+    };
+}
+
+/// Prints output for people to stdout, or to stderr if stdout is machine readable.
+macro_rules! human {
+    ($($arg:tt)*) => {{
+        let line = format!($($arg)*);
+        if $crate::output::machine_readable() {
+            $crate::progress::suspend(|| eprintln!("{}", line));
+        } else {
+            $crate::progress::suspend(|| println!("{}", line));
+        }
+    }};
+}
+
+/// Prints a status message, as `human!` does, unless `--quiet` was passed.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if $crate::output::verbosity() > $crate::output::QUIET {
+            human!($($arg)*);
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{paint, use_color, Color};
+    use std::ffi::OsString;
+
+    #[test]
+    pub fn colors() {
+        let set = |value: &str| Some(OsString::from(value));
+        assert!(use_color(None, None, true));
+        assert!(!use_color(None, None, false));
+        assert!(!use_color(set("1"), None, true));
+        assert!(!use_color(set("1"), set("1"), true));
+        assert!(use_color(set(""), None, true));
+        assert!(use_color(None, set("1"), false));
+        assert!(!use_color(None, set("0"), false));
+        assert_eq!(paint(true, Color::Red, "failed"), "\x1b[31mfailed\x1b[0m");
+        assert_eq!(paint(false, Color::Green, "satisfied"), "satisfied");
+    }
+}
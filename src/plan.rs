@@ -0,0 +1,161 @@
+//! Describing what an ensure would change, without changing anything.
+
+use semver::Version;
+use table::format_table;
+
+/// What would be done to a package.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Install,
+    Upgrade,
+    Downgrade,
+    /// Install the same version again, such as from a different source.
+    Reinstall,
+    Skip,
+    Uninstall,
+}
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Action::Install => "install",
+            Action::Upgrade => "upgrade",
+            Action::Downgrade => "downgrade",
+            Action::Reinstall => "reinstall",
+            Action::Skip => "skip",
+            Action::Uninstall => "uninstall",
+        }
+    }
+}
+
+/// One line of a plan.
+#[derive(Debug, PartialEq)]
+pub struct Step {
+    pub package: String,
+    pub installed: Option<Version>,
+    /// The requested requirement, or `None` for packages which aren't required.
+    pub requirement: Option<String>,
+    /// The version which would be installed, if it is known.
+    pub target: Option<Version>,
+    pub action: Action,
+}
+
+impl Step {
+    /// The step for a required package, given whether what's installed satisfies it.
+    pub fn ensure(
+        package: &str,
+        installed: Option<Version>,
+        requirement: &str,
+        target: Option<Version>,
+        satisfied: bool,
+    ) -> Step {
+        let action = match (&installed, &target) {
+            _ if satisfied => Action::Skip,
+            (None, _) => Action::Install,
+            (Some(installed), Some(target)) if target < installed => Action::Downgrade,
+            (Some(installed), Some(target)) if target == installed => Action::Reinstall,
+            (Some(_), _) => Action::Upgrade,
+        };
+        Step {
+            package: package.to_owned(),
+            installed,
+            requirement: Some(requirement.to_owned()),
+            target,
+            action,
+        }
+    }
+
+    /// The step for an installed package which would be removed.
+    pub fn uninstall(package: &str, installed: Option<Version>) -> Step {
+        Step {
+            package: package.to_owned(),
+            installed,
+            requirement: None,
+            target: None,
+            action: Action::Uninstall,
+        }
+    }
+
+    fn columns(&self) -> Vec<String> {
+        vec![
+            self.package.clone(),
+            show(self.installed.as_ref()),
+            self.requirement.clone().unwrap_or_else(|| "-".to_owned()),
+            show(self.target.as_ref()),
+            self.action.name().to_owned(),
+        ]
+    }
+}
+
+fn show(version: Option<&Version>) -> String {
+    match version {
+        Some(version) => version.to_string(),
+        None => "-".to_owned(),
+    }
+}
+
+/// Formats `steps` as an aligned table for people to read.
+pub fn format_human(steps: &[Step]) -> String {
+    let rows: Vec<_> = steps.iter().map(Step::columns).collect();
+    format_table(
+        &["PACKAGE", "INSTALLED", "REQUIREMENT", "TARGET", "ACTION"],
+        &rows,
+    )
+}
+
+/// Formats `steps` for scripts: one tab-separated line per step, with the columns of
+/// `format_human` in the same order, no header, and `-` for missing values. This format is stable.
+pub fn format_porcelain(steps: &[Step]) -> String {
+    let mut out = String::new();
+    for step in steps {
+        out.push_str(&step.columns().join("\t"));
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_human, format_porcelain, Action, Step};
+    use semver::Version;
+
+    fn v(s: &str) -> Option<Version> {
+        Some(Version::parse(s).unwrap())
+    }
+
+    #[test]
+    pub fn actions() {
+        let action = |installed, target, satisfied| {
+            Step::ensure("rustfmt", installed, "^1", target, satisfied).action
+        };
+        assert_eq!(action(v("1.4.0"), v("1.4.0"), true), Action::Skip);
+        assert_eq!(action(None, v("1.6.0"), false), Action::Install);
+        assert_eq!(action(None, None, false), Action::Install);
+        assert_eq!(action(v("0.9.0"), v("1.6.0"), false), Action::Upgrade);
+        assert_eq!(action(v("2.0.0"), v("1.6.0"), false), Action::Downgrade);
+        assert_eq!(action(v("0.9.0"), None, false), Action::Upgrade);
+        assert_eq!(action(v("0.9.0"), v("0.9.0"), false), Action::Reinstall);
+    }
+
+    #[test]
+    pub fn formats() {
+        let steps = vec![
+            Step::ensure("cargo-deny", None, "^0.14", v("0.14.3"), false),
+            Step::ensure("rustfmt", v("0.9.0"), "0.9", v("0.9.0"), true),
+            Step::uninstall("ripgrep", v("13.0.0")),
+        ];
+        assert_eq!(
+            format_human(&steps),
+            "PACKAGE     INSTALLED  REQUIREMENT  TARGET  ACTION\n\
+             cargo-deny  -          ^0.14        0.14.3  install\n\
+             rustfmt     0.9.0      0.9          0.9.0   skip\n\
+             ripgrep     13.0.0     -            -       uninstall\n"
+        );
+        assert_eq!(
+            format_porcelain(&steps),
+            "cargo-deny\t-\t^0.14\t0.14.3\tinstall\n\
+             rustfmt\t0.9.0\t0.9\t0.9.0\tskip\n\
+             ripgrep\t13.0.0\t-\t-\tuninstall\n"
+        );
+    }
+}
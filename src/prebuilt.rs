@@ -0,0 +1,297 @@
+//! Downloading and unpacking prebuilt binaries.
+//!
+//! Like the registry queries, downloads shell out to `curl`, and archives are unpacked with `tar`.
+
+use semver::Version;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A prebuilt archive of one version of a package.
+#[derive(Debug, PartialEq)]
+pub struct Release {
+    pub version: Version,
+    pub url: String,
+    /// A file containing the SHA-256 of the archive, to check the download against.
+    pub checksum_url: Option<String>,
+    /// Where the release came from, as recorded in the source part of its state file key.
+    pub source: String,
+}
+
+/// Downloads and unpacks `release`, and copies its binaries into `bin_dir`, returning their
+/// names. Only `bins` are copied if any are given, and otherwise every executable in the archive.
+/// Returns `None` if the archive doesn't exist.
+pub fn fetch(
+    release: &Release,
+    package: &str,
+    bins: &[String],
+    bin_dir: &Path,
+) -> Result<Option<Vec<String>>, String> {
+    let dir = scratch_dir(package)?;
+    let result = fetch_in(release, bins, bin_dir, &dir);
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+fn fetch_in(
+    release: &Release,
+    bins: &[String],
+    bin_dir: &Path,
+    dir: &Path,
+) -> Result<Option<Vec<String>>, String> {
+    let archive = dir.join("archive");
+    if !download(&release.url, &archive)? {
+        return Ok(None);
+    }
+    if let Some(ref checksum_url) = release.checksum_url {
+        let checksum_file = dir.join("checksum");
+        if !download(checksum_url, &checksum_file)? {
+            return Err(format!("Checksum {} was not found", checksum_url));
+        }
+        let expected = match ::read_file_to_string(&checksum_file) {
+            Ok(contents) => contents
+                .split_whitespace()
+                .next()
+                .unwrap_or("")
+                .to_lowercase(),
+            Err(err) => return Err(format!("Error reading {:?}: {:?}", checksum_file, err)),
+        };
+        let actual = sha256(&archive)?;
+        if actual != expected {
+            return Err(format!(
+                "Checksum mismatch for {}: expected {}, got {}",
+                release.url, expected, actual
+            ));
+        }
+    }
+    let unpacked = dir.join("unpacked");
+    extract(&archive, &unpacked)?;
+    install_binaries(&unpacked, bin_dir, bins).map(Some)
+}
+
+/// Downloads `url` to `dest`. Returns `false`, rather than an error, if there is nothing there.
+fn download(url: &str, dest: &Path) -> Result<bool, String> {
+    let output = match Command::new("curl")
+        .args(["--silent", "--show-error", "--location"])
+        .args(["--write-out", "%{http_code}", "--output"])
+        .arg(dest)
+        .arg(url)
+        .output()
+    {
+        Ok(output) => output,
+        Err(err) => return Err(format!("Error running curl to fetch {}: {:?}", url, err)),
+    };
+    if !output.status.success() {
+        return Err(format!(
+            "Error fetching {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    match String::from_utf8_lossy(&output.stdout).trim() {
+        "200" => Ok(true),
+        "404" => Ok(false),
+        code => Err(format!("Error fetching {}: HTTP status {}", url, code)),
+    }
+}
+
+/// Unpacks the tarball at `archive`, compressed in any way `tar` detects, into `dir`.
+fn extract(archive: &Path, dir: &Path) -> Result<(), String> {
+    let output = match Command::new("tar")
+        .arg("-xf")
+        .arg(archive)
+        .arg("-C")
+        .arg(dir)
+        .output()
+    {
+        Ok(output) => output,
+        Err(err) => {
+            return Err(format!(
+                "Error running tar to unpack {:?}: {:?}",
+                archive, err
+            ))
+        }
+    };
+    if !output.status.success() {
+        return Err(format!(
+            "Error unpacking {:?}: {}",
+            archive,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Copies `bins` from anywhere under `from`, or every executable file if `bins` is empty, into
+/// `bin_dir`, returning their names.
+fn install_binaries(from: &Path, bin_dir: &Path, bins: &[String]) -> Result<Vec<String>, String> {
+    let mut found = vec![];
+    find_files(from, &mut found)?;
+    let found: Vec<_> = found
+        .into_iter()
+        .filter(
+            |path| match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) if !bins.is_empty() => bins.iter().any(|bin| bin == name),
+                Some(_) => is_executable(path),
+                None => false,
+            },
+        )
+        .collect();
+    for bin in bins {
+        if !found
+            .iter()
+            .any(|path| path.file_name() == Some(bin.as_ref()))
+        {
+            return Err(format!(
+                "The archive doesn't contain a binary named {}",
+                bin
+            ));
+        }
+    }
+    if found.is_empty() {
+        return Err("The archive doesn't contain any executables".to_owned());
+    }
+    if let Err(err) = fs::create_dir_all(bin_dir) {
+        return Err(format!("Error creating {:?}: {:?}", bin_dir, err));
+    }
+    let mut names = vec![];
+    for path in found {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let dest = bin_dir.join(&name);
+        if let Err(err) = fs::copy(&path, &dest) {
+            return Err(format!("Error copying {:?} to {:?}: {:?}", path, dest, err));
+        }
+        names.push(name);
+    }
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+fn find_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let error = |err: ::std::io::Error| format!("Error reading {:?}: {:?}", dir, err);
+    for entry in fs::read_dir(dir).map_err(error)? {
+        let path = entry.map_err(error)?.path();
+        if path.is_dir() {
+            find_files(&path, files)?;
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "exe")
+}
+
+/// The hex SHA-256 of the file at `path`, from `sha256sum` or `shasum`.
+pub fn sha256(path: &Path) -> Result<String, String> {
+    let output = Command::new("sha256sum").arg(path).output().or_else(|_| {
+        Command::new("shasum")
+            .args(["-a", "256"])
+            .arg(path)
+            .output()
+    });
+    match output {
+        Ok(ref output) if output.status.success() => Ok(String::from_utf8_lossy(&output.stdout)
+            .split_whitespace()
+            .next()
+            .unwrap_or("")
+            .to_owned()),
+        Ok(output) => Err(format!(
+            "Error hashing {:?}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+        Err(err) => Err(format!(
+            "Error running sha256sum or shasum to hash {:?}: {:?}",
+            path, err
+        )),
+    }
+}
+
+/// A fresh directory to download and unpack `package` in.
+fn scratch_dir(package: &str) -> Result<PathBuf, String> {
+    let dir = ::std::env::temp_dir().join(format!(
+        "cargo-ensure-installed-{}-{}",
+        package,
+        ::std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    match fs::create_dir_all(dir.join("unpacked")) {
+        Ok(()) => Ok(dir),
+        Err(err) => Err(format!("Error creating {:?}: {:?}", dir, err)),
+    }
+}
+
+/// The host's target triple, according to `rustc -vV`.
+pub fn host_target() -> Result<String, String> {
+    let output = match Command::new("rustc").arg("-vV").output() {
+        Ok(output) => output,
+        Err(err) => return Err(format!("Error running rustc -vV: {:?}", err)),
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match stdout.lines().find_map(|line| line.strip_prefix("host: ")) {
+        Some(host) => Ok(host.trim().to_owned()),
+        None => Err("Error finding the host target triple in rustc -vV".to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{install_binaries, scratch_dir, sha256};
+    use std::fs;
+
+    #[test]
+    pub fn installs_binaries() {
+        let dir = scratch_dir("prebuilt-test").unwrap();
+        let unpacked = dir.join("unpacked");
+        fs::create_dir_all(unpacked.join("ripgrep-14.1.0/doc")).unwrap();
+        fs::write(unpacked.join("ripgrep-14.1.0/rg"), "binary").unwrap();
+        fs::write(unpacked.join("ripgrep-14.1.0/doc/rg.1"), "manual").unwrap();
+        let bin_dir = dir.join("bin");
+        assert_eq!(
+            install_binaries(&unpacked, &bin_dir, &["rg".to_owned()]).unwrap(),
+            vec!["rg"]
+        );
+        assert!(bin_dir.join("rg").is_file());
+        assert!(!bin_dir.join("rg.1").exists());
+        assert_eq!(
+            install_binaries(&unpacked, &bin_dir, &["ripgrep".to_owned()]),
+            Err("The archive doesn't contain a binary named ripgrep".to_owned())
+        );
+        assert_eq!(
+            sha256(&bin_dir.join("rg")).unwrap(),
+            "9a3a45d01531a20e89ac6ae10b0b0beb0492acd7216a368aa062d1a5fecaf9cd"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    pub fn installs_executables() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = scratch_dir("prebuilt-test-executables").unwrap();
+        let unpacked = dir.join("unpacked");
+        fs::write(unpacked.join("cargo-nextest"), "binary").unwrap();
+        fs::set_permissions(
+            unpacked.join("cargo-nextest"),
+            fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+        fs::write(unpacked.join("README.md"), "readme").unwrap();
+        assert_eq!(
+            install_binaries(&unpacked, &dir.join("bin"), &[]).unwrap(),
+            vec!["cargo-nextest"]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
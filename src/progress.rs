@@ -0,0 +1,195 @@
+//! Progress bars for ensuring several packages on a terminal: one for each package, saying whether
+//! it is waiting, being checked, being built or done. Elsewhere, the status lines say as much.
+
+use event::Event;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use output::{self, Color};
+use std::io::{self, IsTerminal};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The bars being shown, if any.
+static ACTIVE: Mutex<Option<Bars>> = Mutex::new(None);
+
+/// A bar for each package, in the order they are ensured.
+struct Bars {
+    multi: MultiProgress,
+    bars: Vec<(String, ProgressBar)>,
+}
+
+impl Bars {
+    fn new(target: ProgressDrawTarget, packages: &[String]) -> Bars {
+        let multi = MultiProgress::with_draw_target(target);
+        let width = packages.iter().map(String::len).max().unwrap_or(0);
+        let style = ProgressStyle::with_template("{spinner} {prefix} {wide_msg}")
+            .unwrap()
+            .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏ ");
+        let bars = packages
+            .iter()
+            .map(|package| {
+                let bar = multi.add(ProgressBar::new_spinner());
+                bar.set_style(style.clone());
+                bar.set_prefix(format!("{:width$}", package, width = width));
+                bar.set_message("waiting");
+                (package.clone(), bar)
+            })
+            .collect();
+        Bars { multi, bars }
+    }
+
+    fn bar(&self, package: &str) -> Option<&ProgressBar> {
+        self.bars
+            .iter()
+            .find(|(name, bar)| name == package && !bar.is_finished())
+            .map(|(_, bar)| bar)
+    }
+
+    fn event(&self, event: &Event) {
+        let (package, message) = match *event {
+            Event::CheckStarted { ref package } => (package, "checking".to_owned()),
+            Event::Decided {
+                ref package,
+                reason: Some(ref reason),
+                ..
+            } => (package, reason.to_string()),
+            Event::InstallStarted { ref package, .. } => (package, "building".to_owned()),
+            // The last thing cargo said, like the crate it is compiling.
+            Event::StderrLine {
+                ref package,
+                ref line,
+            } if !line.trim().is_empty() => (package, format!("building: {}", line.trim())),
+            _ => return,
+        };
+        if let Some(bar) = self.bar(package) {
+            if let Event::CheckStarted { .. } = *event {
+                bar.enable_steady_tick(Duration::from_millis(100));
+            }
+            bar.set_message(message);
+        }
+    }
+
+    fn finish(&self, package: &str, message: String) -> bool {
+        match self.bar(package) {
+            Some(bar) => {
+                bar.finish_with_message(message);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Shows a bar for each of `packages` until the returned `Shown` is dropped, if there are several
+/// of them, stderr is a terminal, and nothing else is to be printed as it happens.
+pub fn start(packages: &[String]) -> Shown {
+    let show = packages.len() > 1
+        && io::stderr().is_terminal()
+        && output::verbosity() == 0
+        && !output::machine_readable();
+    if show {
+        *ACTIVE.lock().unwrap() = Some(Bars::new(ProgressDrawTarget::stderr(), packages));
+    }
+    Shown(())
+}
+
+/// Moves the bar for the package `event` is about along. Without bars, says why each package is
+/// to be installed as a status line instead.
+pub fn event(event: &Event) {
+    if let Some(ref bars) = *ACTIVE.lock().unwrap() {
+        bars.event(event);
+        return;
+    }
+    if let Event::Decided {
+        ref package,
+        reason: Some(ref reason),
+        ..
+    } = *event
+    {
+        status!("{}: {}", package, reason);
+    }
+}
+
+/// Says that `package` is done, with `message` in `color`, on its bar, returning whether there was
+/// one to say it on.
+pub fn finish(package: &str, color: Color, message: &str) -> bool {
+    match *ACTIVE.lock().unwrap() {
+        Some(ref bars) => bars.finish(package, output::stderr_color(color, message)),
+        None => false,
+    }
+}
+
+/// While this is alive, the bars `start` showed are shown. Once it is dropped, those for packages
+/// which were never finished, such as after an interruption, are removed.
+pub struct Shown(());
+
+impl Drop for Shown {
+    fn drop(&mut self) {
+        if let Some(bars) = ACTIVE.lock().unwrap().take() {
+            for (_, bar) in bars.bars {
+                if !bar.is_finished() {
+                    bar.finish_and_clear();
+                }
+            }
+        }
+    }
+}
+
+/// Runs `print` with the bars out of the way, so that what it prints isn't drawn over.
+pub fn suspend<R, F: FnOnce() -> R>(print: F) -> R {
+    // Not holding the lock while printing, in case printing logs something.
+    let multi = match ACTIVE.try_lock() {
+        Ok(active) => active.as_ref().map(|bars| bars.multi.clone()),
+        Err(_) => None,
+    };
+    match multi {
+        Some(multi) => multi.suspend(print),
+        None => print(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bars;
+    use event::{Event, InstallReason};
+    use indicatif::ProgressDrawTarget;
+
+    #[test]
+    pub fn follows_events() {
+        let bars = Bars::new(
+            ProgressDrawTarget::hidden(),
+            &["cargo-deny".to_owned(), "rustfmt".to_owned()],
+        );
+        let message = |index: usize| bars.bars[index].1.message();
+        assert_eq!(bars.bars[1].1.prefix(), "rustfmt   ");
+        assert_eq!(message(0), "waiting");
+
+        let package = "rustfmt".to_owned();
+        bars.event(&Event::CheckStarted {
+            package: package.clone(),
+        });
+        assert_eq!(message(1), "checking");
+        bars.event(&Event::Decided {
+            package: package.clone(),
+            installed: None,
+            reason: Some(InstallReason::NotInstalled),
+        });
+        assert_eq!(message(1), "not present");
+        bars.event(&Event::InstallStarted {
+            package: package.clone(),
+            version_req: "^1".to_owned(),
+        });
+        assert_eq!(message(1), "building");
+        bars.event(&Event::StderrLine {
+            package: package.clone(),
+            line: "   Compiling rustfmt v1.4.2".to_owned(),
+        });
+        assert_eq!(message(1), "building: Compiling rustfmt v1.4.2");
+
+        assert!(bars.finish("rustfmt", "installed 1.4.2".to_owned()));
+        assert!(bars.bars[1].1.is_finished());
+        assert_eq!(message(1), "installed 1.4.2");
+        // Finished bars are left alone.
+        assert!(!bars.finish("rustfmt", "failed".to_owned()));
+        assert_eq!(message(0), "waiting");
+    }
+}
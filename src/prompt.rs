@@ -0,0 +1,169 @@
+//! Asking before doing anything slow.
+
+use progress;
+use std::io::{BufRead, IsTerminal, Write};
+
+/// Asks the user to confirm installs, remembering a "yes to all" answer.
+pub struct Confirm {
+    interactive: bool,
+    all: bool,
+    /// Whether everything is declined without asking.
+    none: bool,
+}
+
+impl Confirm {
+    /// Confirms everything without asking.
+    pub fn always() -> Confirm {
+        Confirm {
+            interactive: false,
+            all: true,
+            none: false,
+        }
+    }
+
+    /// Declines everything without asking.
+    #[cfg(test)]
+    pub fn never() -> Confirm {
+        Confirm {
+            interactive: false,
+            all: false,
+            none: true,
+        }
+    }
+
+    /// Asks only if both stdin and stdout are terminals and this isn't a CI build, so automation
+    /// is never prompted.
+    pub fn for_terminal() -> Confirm {
+        let terminal = std::io::stdin().is_terminal() && std::io::stdout().is_terminal();
+        Confirm {
+            interactive: terminal && !in_ci(&|name| std::env::var(name).ok()),
+            all: false,
+            none: false,
+        }
+    }
+
+    pub fn confirm(&mut self, question: &str) -> Result<bool, String> {
+        if self.none {
+            return Ok(false);
+        }
+        if !self.interactive || self.all {
+            return Ok(true);
+        }
+        let stdin = std::io::stdin();
+        progress::suspend(|| self.ask(question, &mut stdin.lock(), &mut std::io::stdout()))
+    }
+
+    fn ask(
+        &mut self,
+        question: &str,
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+    ) -> Result<bool, String> {
+        loop {
+            let written = write!(output, "{} [y/N/a] ", question).and_then(|()| output.flush());
+            if let Err(err) = written {
+                return Err(format!("Error writing prompt: {:?}", err));
+            }
+            let mut answer = String::new();
+            match input.read_line(&mut answer) {
+                // Treat a closed stdin as "no" rather than asking forever.
+                Ok(0) => return Ok(false),
+                Ok(_) => {}
+                Err(err) => return Err(format!("Error reading answer: {:?}", err)),
+            }
+            match answer.trim().to_lowercase().as_str() {
+                "y" | "yes" => return Ok(true),
+                "" | "n" | "no" => return Ok(false),
+                "a" | "all" => {
+                    self.all = true;
+                    return Ok(true);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Variables set by CI systems which don't all set `CI`.
+const CI_VARIABLES: &[&str] = &[
+    "BUILDKITE",
+    "CIRCLECI",
+    "GITHUB_ACTIONS",
+    "GITLAB_CI",
+    "JENKINS_URL",
+    "TEAMCITY_VERSION",
+    "TF_BUILD",
+    "TRAVIS",
+];
+
+/// Whether the environment looks like a CI build.
+fn in_ci(lookup: &dyn Fn(&str) -> Option<String>) -> bool {
+    let set = |name: &str| match lookup(name) {
+        Some(value) => !value.is_empty() && value != "0" && value.to_lowercase() != "false",
+        None => false,
+    };
+    set("CI") || CI_VARIABLES.iter().any(|name| set(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{in_ci, Confirm};
+
+    fn interactive() -> Confirm {
+        Confirm {
+            interactive: true,
+            all: false,
+            none: false,
+        }
+    }
+
+    fn ask(confirm: &mut Confirm, input: &str) -> (bool, String) {
+        let mut output = vec![];
+        let answer = confirm
+            .ask("Install rustfmt 0.9.0?", &mut input.as_bytes(), &mut output)
+            .unwrap();
+        (answer, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    pub fn answers() {
+        assert_eq!(
+            ask(&mut interactive(), "y\n"),
+            (true, "Install rustfmt 0.9.0? [y/N/a] ".to_owned())
+        );
+        assert!(!ask(&mut interactive(), "\n").0);
+        assert!(!ask(&mut interactive(), "N\n").0);
+        assert!(!ask(&mut interactive(), "").0);
+    }
+
+    #[test]
+    pub fn asks_again_after_unknown_answer() {
+        let (answer, output) = ask(&mut interactive(), "maybe\nyes\n");
+        assert!(answer);
+        assert_eq!(output.matches("[y/N/a]").count(), 2);
+    }
+
+    #[test]
+    pub fn yes_to_all() {
+        let mut confirm = interactive();
+        assert!(ask(&mut confirm, "a\n").0);
+        assert!(confirm.confirm("Install cargo-deny 0.14?").unwrap());
+    }
+
+    #[test]
+    pub fn detects_ci() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|&&(var, _)| var == name)
+                    .map(|&(_, value)| value.to_owned())
+            }
+        };
+        assert!(!in_ci(&env(&[])));
+        assert!(in_ci(&env(&[("CI", "true")])));
+        assert!(in_ci(&env(&[("GITHUB_ACTIONS", "true")])));
+        assert!(in_ci(&env(&[("JENKINS_URL", "https://ci.example.com/")])));
+        assert!(!in_ci(&env(&[("CI", "false")])));
+        assert!(!in_ci(&env(&[("CI", "")])));
+    }
+}
@@ -0,0 +1,137 @@
+//! Queries the crates.io sparse index for the versions of a package.
+//!
+//! Requests are made by shelling out to `curl`, in the same way installs shell out to `cargo`.
+
+use semver::{Version, VersionReq};
+use std::process::Command;
+
+pub const CRATES_IO_INDEX: &str = "https://index.crates.io/";
+
+/// The git index of crates.io, which identifies it in `.crates.toml` keys.
+pub const CRATES_IO_GIT_INDEX: &str = "https://github.com/rust-lang/crates.io-index";
+
+/// Fetches every non-yanked version of `package` published to the sparse index at `index_url`.
+pub fn fetch_versions(index_url: &str, package: &str) -> Result<Vec<Version>, String> {
+    let url = format!(
+        "{}/{}",
+        index_url.trim_end_matches('/'),
+        index_path(package)
+    );
+    let output = match Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--location", &url])
+        .output()
+    {
+        Ok(output) => output,
+        Err(err) => return Err(format!("Error running curl to fetch {}: {:?}", url, err)),
+    };
+    if !output.status.success() {
+        return Err(format!(
+            "Error fetching {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    parse_index_file(package, &String::from_utf8_lossy(&output.stdout))
+}
+
+/// The path of a package's file within a sparse index, e.g. `ru/st/rustfmt`.
+pub fn index_path(package: &str) -> String {
+    let name = package.to_lowercase();
+    match name.len() {
+        1 => format!("1/{}", name),
+        2 => format!("2/{}", name),
+        3 => format!("3/{}/{}", &name[..1], name),
+        _ => format!("{}/{}/{}", &name[..2], &name[2..4], name),
+    }
+}
+
+/// Parses an index file, which holds one JSON object per published version.
+pub fn parse_index_file(package: &str, contents: &str) -> Result<Vec<Version>, String> {
+    let mut versions = vec![];
+    for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+        let entry: serde_json::Value = match serde_json::from_str(line) {
+            Ok(entry) => entry,
+            Err(err) => return Err(format!("Invalid index entry for {}: {}", package, err)),
+        };
+        if entry.get("yanked").and_then(|y| y.as_bool()) == Some(true) {
+            continue;
+        }
+        let raw_version = match entry.get("vers").and_then(|v| v.as_str()) {
+            Some(v) => v,
+            None => return Err(format!("Invalid index entry for {}: missing vers", package)),
+        };
+        match Version::parse(raw_version) {
+            Ok(version) => versions.push(version),
+            Err(err) => {
+                return Err(format!(
+                    "Invalid index entry for {}: {:?} could not be parsed as a version: {:?}",
+                    package, raw_version, err
+                ))
+            }
+        }
+    }
+    Ok(versions)
+}
+
+/// The newest of `versions` satisfying `requirement`, ignoring pre-releases unless the requirement
+/// asks for them (as `cargo install` does).
+pub fn newest_matching<'a>(
+    versions: &'a [Version],
+    requirement: &VersionReq,
+) -> Option<&'a Version> {
+    versions.iter().filter(|v| requirement.matches(v)).max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{index_path, newest_matching, parse_index_file};
+    use semver::{Version, VersionReq};
+
+    #[test]
+    pub fn paths() {
+        assert_eq!(index_path("a"), "1/a");
+        assert_eq!(index_path("ab"), "2/ab");
+        assert_eq!(index_path("abc"), "3/a/abc");
+        assert_eq!(index_path("rustfmt"), "ru/st/rustfmt");
+        assert_eq!(index_path("Cargo-Deny"), "ca/rg/cargo-deny");
+    }
+
+    #[test]
+    pub fn index_file() {
+        let versions = parse_index_file(
+            "rustfmt",
+            r#"{"name":"rustfmt","vers":"0.9.0","yanked":false}
+{"name":"rustfmt","vers":"0.9.1","yanked":true}
+{"name":"rustfmt","vers":"0.10.0","yanked":false}
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            versions,
+            vec![
+                Version::parse("0.9.0").unwrap(),
+                Version::parse("0.10.0").unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    pub fn newest() {
+        let versions: Vec<_> = ["0.9.0", "0.9.3", "0.10.0", "0.9.4-beta.1"]
+            .iter()
+            .map(|v| Version::parse(v).unwrap())
+            .collect();
+        assert_eq!(
+            newest_matching(&versions, &VersionReq::parse("^0.9").unwrap()),
+            Some(&Version::parse("0.9.3").unwrap())
+        );
+        assert_eq!(
+            newest_matching(&versions, &VersionReq::parse("*").unwrap()),
+            Some(&Version::parse("0.10.0").unwrap())
+        );
+        assert_eq!(
+            newest_matching(&versions, &VersionReq::parse("^1").unwrap()),
+            None
+        );
+    }
+}
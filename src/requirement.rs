@@ -0,0 +1,341 @@
+use backend::{Backend, GithubRelease};
+use cfg::Cfg;
+use error::Error;
+use semver::VersionReq;
+use source::Source;
+
+/// A single package which should be installed at a version matching `version`.
+#[derive(Clone)]
+pub struct Requirement {
+    pub package: String,
+    pub raw_version: String,
+    pub version: VersionReq,
+    /// If set, the package is only required on platforms matching this condition.
+    pub platform: Option<Cfg>,
+    /// Manifest groups (e.g. `ci`, `dev`) this package belongs to.
+    pub groups: Vec<String>,
+    /// Features to pass to `cargo install --features`.
+    pub features: Vec<String>,
+    pub all_features: bool,
+    pub no_default_features: bool,
+    /// Whether to build with the package's own `Cargo.lock` (`cargo install --locked`).
+    pub locked: bool,
+    pub source: Source,
+    /// Whether an install from any source satisfies the requirement, not just from `source`.
+    pub any_source: bool,
+    /// Whether to check that the installed binaries run and report the installed version.
+    pub verify: bool,
+    /// If set, a copy of the tool which cargo didn't install satisfies the requirement when its
+    /// `--version` output matches this regex with a satisfying version.
+    pub version_regex: Option<String>,
+    /// Whether a satisfying copy of the tool anywhere on `$PATH` satisfies the requirement.
+    pub accept_path: bool,
+    /// Whether to reinstall the package when the rustc it would be built with changes.
+    pub rebuild_on_rustc_change: bool,
+    /// Whether a binary whose checksum has changed since it was installed is an error, rather than
+    /// a reason to reinstall.
+    pub strict_checksums: bool,
+    /// Whether to pass `--force` to `cargo install`, which replaces binaries of the same name even
+    /// if another package installed them. Needed when replacing an existing install.
+    pub force: bool,
+    /// The binaries to install (`cargo install --bin`), or every binary if empty.
+    pub bins: Vec<String>,
+    /// The cargo profile to build with, if not `release`.
+    pub profile: Option<String>,
+    /// The target triple to build for, if not the host.
+    pub target: Option<String>,
+    /// How many jobs cargo builds with, if not one per CPU.
+    pub jobs: Option<u32>,
+    /// Arguments to pass to `cargo install` verbatim.
+    pub extra_args: Vec<String>,
+    /// The rustup toolchain to build with (`cargo +toolchain install`).
+    pub toolchain: Option<String>,
+    /// What installs the package, tried in order until one succeeds.
+    pub backends: Vec<Backend>,
+    /// Where the `github-release` backend downloads the package from.
+    pub github: Option<GithubRelease>,
+}
+
+impl Requirement {
+    pub fn new(package: &str, raw_version: &str) -> Result<Requirement, Error> {
+        if package.is_empty() {
+            return Err(Error::Other(format!(
+                "Invalid package name specified with version '{:?}': package name was empty",
+                raw_version
+            )));
+        }
+        let version = match VersionReq::parse(raw_version) {
+            Ok(v) => v,
+            Err(err) => {
+                return Err(Error::InvalidVersionReq {
+                    package: package.to_owned(),
+                    version_req: raw_version.to_owned(),
+                    message: format!("{:?}", err),
+                })
+            }
+        };
+        Ok(Requirement {
+            package: package.to_owned(),
+            raw_version: raw_version.to_owned(),
+            version,
+            platform: None,
+            groups: vec![],
+            features: vec![],
+            all_features: false,
+            no_default_features: false,
+            locked: false,
+            source: Source::Registry,
+            any_source: false,
+            verify: false,
+            version_regex: None,
+            accept_path: false,
+            rebuild_on_rustc_change: false,
+            strict_checksums: false,
+            force: false,
+            bins: vec![],
+            profile: None,
+            target: None,
+            jobs: None,
+            extra_args: vec![],
+            toolchain: None,
+            backends: vec![Backend::Source],
+            github: None,
+        })
+    }
+
+    /// Adds `feature` to the features to install with, unless it's already there.
+    pub fn add_feature(&mut self, feature: &str) {
+        if !self.features.iter().any(|f| f == feature) {
+            self.features.push(feature.to_owned());
+        }
+    }
+
+    /// Whether this package should be ensured when only `selected` groups were asked for.
+    ///
+    /// Selecting no groups selects every package.
+    pub fn in_groups(&self, selected: &[String]) -> bool {
+        selected.is_empty() || self.groups.iter().any(|group| selected.contains(group))
+    }
+
+    /// Parses a `name@req` spec, where a missing `@req` means any version (`*`).
+    pub fn parse_spec(spec: &str) -> Result<Requirement, Error> {
+        match spec.find('@') {
+            Some(index) => Requirement::new(&spec[..index], &spec[index + 1..]),
+            None => Requirement::new(spec, "*"),
+        }
+    }
+}
+
+/// Parses one requirement per line, each either `name version-req` or `name@req`.
+///
+/// Blank lines and lines starting with `#` are ignored.
+pub fn parse_lines(contents: &str) -> Result<Vec<Requirement>, String> {
+    let mut requirements = vec![];
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let requirement = match line.find(char::is_whitespace) {
+            Some(split) => Requirement::new(&line[..split], line[split..].trim()),
+            None => Requirement::parse_spec(line),
+        };
+        match requirement {
+            Ok(requirement) => requirements.push(requirement),
+            Err(err) => return Err(format!("Invalid line {}: {}", index + 1, err)),
+        }
+    }
+    Ok(requirements)
+}
+
+/// Adds `overrides` to `requirements`, replacing any existing entries for the same package.
+pub fn merge(requirements: &mut Vec<Requirement>, overrides: Vec<Requirement>) {
+    for requirement in overrides {
+        match requirements
+            .iter()
+            .position(|r| r.package == requirement.package)
+        {
+            Some(index) => requirements[index] = requirement,
+            None => requirements.push(requirement),
+        }
+    }
+}
+
+/// Combines `--package`/`--version` flag pairs with positional `name@req` specs.
+pub fn parse_requirements(
+    packages: &[String],
+    versions: &[String],
+    specs: &[String],
+) -> Result<Vec<Requirement>, String> {
+    if packages.len() != versions.len() {
+        return Err(format!(
+            "Each --package must be paired with a --version: got {} packages and {} versions",
+            packages.len(),
+            versions.len()
+        ));
+    }
+    let mut requirements = Vec::with_capacity(packages.len() + specs.len());
+    for (package, raw_version) in packages.iter().zip(versions) {
+        requirements.push(Requirement::new(package, raw_version)?);
+    }
+    for spec in specs {
+        requirements.push(Requirement::parse_spec(spec)?);
+    }
+    Ok(requirements)
+}
+
+/// Splits `--features` values, which may be comma or space separated, into features for every
+/// package named on the command line and `package/feature` pairs for a single package.
+pub fn parse_features(values: &[String]) -> (Vec<String>, Vec<(String, String)>) {
+    let mut features = vec![];
+    let mut package_features = vec![];
+    for value in values {
+        for feature in value.split(|c: char| c == ',' || c.is_whitespace()) {
+            if feature.is_empty() {
+                continue;
+            }
+            match feature.find('/') {
+                Some(index) => package_features
+                    .push((feature[..index].to_owned(), feature[index + 1..].to_owned())),
+                None => features.push(feature.to_owned()),
+            }
+        }
+    }
+    (features, package_features)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_features, parse_lines, parse_requirements, Requirement};
+    use semver::VersionReq;
+
+    #[test]
+    pub fn multiple_requirements() {
+        let requirements = parse_requirements(
+            &["rustfmt".to_owned(), "cargo-deny".to_owned()],
+            &["0.9".to_owned(), "0.14".to_owned()],
+            &[],
+        )
+        .unwrap();
+        assert_eq!(requirements.len(), 2);
+        assert_eq!(requirements[0].package, "rustfmt");
+        assert_eq!(requirements[0].version, VersionReq::parse("0.9").unwrap());
+        assert_eq!(requirements[1].package, "cargo-deny");
+        assert_eq!(requirements[1].version, VersionReq::parse("0.14").unwrap());
+    }
+
+    #[test]
+    pub fn unpaired_requirements() {
+        assert!(parse_requirements(
+            &["rustfmt".to_owned(), "cargo-deny".to_owned()],
+            &["0.9".to_owned()],
+            &[],
+        )
+        .is_err());
+    }
+
+    #[test]
+    pub fn no_requirements() {
+        assert_eq!(parse_requirements(&[], &[], &[]).unwrap().len(), 0);
+    }
+
+    #[test]
+    pub fn flags_and_specs() {
+        let requirements = parse_requirements(
+            &["rustfmt".to_owned()],
+            &["0.9".to_owned()],
+            &["cargo-audit@0.18".to_owned()],
+        )
+        .unwrap();
+        assert_eq!(requirements.len(), 2);
+        assert_eq!(requirements[0].package, "rustfmt");
+        assert_eq!(requirements[1].package, "cargo-audit");
+        assert_eq!(requirements[1].raw_version, "0.18");
+    }
+
+    #[test]
+    pub fn spec_with_version() {
+        let requirement = Requirement::parse_spec("rustfmt@^1.4").unwrap();
+        assert_eq!(requirement.package, "rustfmt");
+        assert_eq!(requirement.version, VersionReq::parse("^1.4").unwrap());
+    }
+
+    #[test]
+    pub fn spec_without_version() {
+        let requirement = Requirement::parse_spec("rustfmt").unwrap();
+        assert_eq!(requirement.package, "rustfmt");
+        assert_eq!(requirement.raw_version, "*");
+    }
+
+    #[test]
+    pub fn spec_invalid_version() {
+        assert!(Requirement::parse_spec("rustfmt@not-a-version").is_err());
+    }
+
+    #[test]
+    pub fn spec_empty_name() {
+        assert!(Requirement::parse_spec("@1.0").is_err());
+    }
+
+    #[test]
+    pub fn lines() {
+        let requirements = parse_lines(
+            "# Tools for CI\nrustfmt 0.9\n\ncargo-audit@0.18\ncargo-deny >= 0.14, < 0.15\ncargo-udeps\n",
+        )
+        .unwrap();
+        let summary: Vec<_> = requirements
+            .iter()
+            .map(|r| format!("{}@{}", r.package, r.raw_version))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                "rustfmt@0.9",
+                "cargo-audit@0.18",
+                "cargo-deny@>= 0.14, < 0.15",
+                "cargo-udeps@*",
+            ]
+        );
+    }
+
+    #[test]
+    pub fn lines_invalid() {
+        let err = parse_lines("rustfmt 0.9\ncargo-audit nope\n")
+            .err()
+            .unwrap();
+        assert!(err.starts_with("Invalid line 2:"), "{}", err);
+    }
+
+    #[test]
+    pub fn in_groups() {
+        let mut requirement = Requirement::new("rustfmt", "0.9").unwrap();
+        assert!(requirement.in_groups(&[]));
+        assert!(!requirement.in_groups(&["ci".to_owned()]));
+        requirement.groups = vec!["ci".to_owned(), "dev".to_owned()];
+        assert!(requirement.in_groups(&[]));
+        assert!(requirement.in_groups(&["ci".to_owned()]));
+        assert!(requirement.in_groups(&["release".to_owned(), "dev".to_owned()]));
+        assert!(!requirement.in_groups(&["release".to_owned()]));
+    }
+
+    #[test]
+    pub fn features() {
+        let (features, package_features) = parse_features(&[
+            "postgres,sqlite".to_owned(),
+            "sqlx-cli/rustls cargo-udeps/vendored-openssl".to_owned(),
+        ]);
+        assert_eq!(features, vec!["postgres", "sqlite"]);
+        assert_eq!(
+            package_features,
+            vec![
+                ("sqlx-cli".to_owned(), "rustls".to_owned()),
+                ("cargo-udeps".to_owned(), "vendored-openssl".to_owned()),
+            ]
+        );
+
+        let mut requirement = Requirement::new("sqlx-cli", "0.7").unwrap();
+        requirement.add_feature("postgres");
+        requirement.add_feature("postgres");
+        assert_eq!(requirement.features, vec!["postgres"]);
+    }
+}
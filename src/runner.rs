@@ -0,0 +1,395 @@
+//! Running cargo, behind a trait so that tests can stand in for it.
+
+use error::Error;
+use github;
+use output;
+use progress;
+use signals;
+use std::env;
+use std::io::{self, BufRead, BufReader, IsTerminal, Read};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use teamcity;
+
+/// How a command exited.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Exit {
+    /// Its exit code, or `None` if it was killed by a signal.
+    pub code: Option<i32>,
+    /// Everything it wrote to stderr.
+    pub stderr: String,
+}
+
+impl Exit {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+/// Something which runs cargo commands.
+pub trait Runner {
+    /// Runs `command` for `package`, passing each line it writes to stderr to `on_line`, and
+    /// giving up if it waits longer than `lock_timeout` for another cargo process's lock, or runs
+    /// for longer than `timeout` altogether.
+    fn run(
+        &self,
+        command: &mut Command,
+        package: &str,
+        lock_timeout: Option<Duration>,
+        timeout: Option<Duration>,
+        on_line: &dyn Fn(&str),
+    ) -> Result<Exit, Error>;
+}
+
+/// Runs commands as processes.
+pub struct System;
+
+impl Runner for System {
+    fn run(
+        &self,
+        command: &mut Command,
+        package: &str,
+        lock_timeout: Option<Duration>,
+        timeout: Option<Duration>,
+        on_line: &dyn Fn(&str),
+    ) -> Result<Exit, Error> {
+        debug!(command = ?command, "running");
+        run_capturing(command, package, lock_timeout, timeout, on_line)
+    }
+}
+
+/// Runs `command` for `package`, passing each line of its stderr to `on_line` as it goes, and
+/// returns how it exited along with everything it wrote to stderr. Its output is copied to our
+/// stderr as it goes when verbose, and otherwise only if it fails. If cargo blocks on a lock
+/// another cargo process holds, says so, and kills it if it is still waiting after `lock_timeout`.
+/// Whatever it is doing, it is killed once it has run for `timeout`.
+fn run_capturing(
+    command: &mut Command,
+    package: &str,
+    lock_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    on_line: &dyn Fn(&str),
+) -> Result<Exit, Error> {
+    // Cargo only colours its output for terminals, which its stderr no longer is.
+    let color = output::use_color(
+        env::var_os("NO_COLOR"),
+        env::var_os("CLICOLOR_FORCE"),
+        io::stderr().is_terminal(),
+    );
+    if color && env::var_os("CARGO_TERM_COLOR").is_none() {
+        command.env("CARGO_TERM_COLOR", "always");
+    }
+    let (mut child, running) =
+        match signals::spawn(command.stdout(Stdio::piped()).stderr(Stdio::piped())) {
+            Ok(spawned) => spawned,
+            Err(err) => {
+                return Err(Error::Other(format!(
+                    "Error running {:?}: {:?}",
+                    command.get_program(),
+                    err
+                )))
+            }
+        };
+    // Lines are read on other threads, so that we can stop waiting for them.
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let (sender, lines) = mpsc::channel();
+    if let Some(stdout) = child.stdout.take() {
+        read_lines(stdout, false, sender.clone());
+    }
+    if let Some(stderr) = child.stderr.take() {
+        read_lines(stderr, true, sender);
+    }
+    // Unless asked to stream it, cargo's output is only shown if it fails. GitHub Actions and
+    // TeamCity fold it away in a group.
+    let streaming =
+        output::verbosity() >= output::VERBOSE || github::active() || teamcity::active();
+    let mut output = String::new();
+    let mut captured = String::new();
+    let mut blocked: Option<(Instant, String)> = None;
+    let mut timed_out = false;
+    loop {
+        let lock_deadline = match (&blocked, lock_timeout) {
+            (Some((since, _)), Some(timeout)) => Some(*since + timeout),
+            _ => None,
+        };
+        let until = match (lock_deadline, deadline) {
+            (Some(lock_deadline), Some(deadline)) => Some(lock_deadline.min(deadline)),
+            (lock_deadline, deadline) => lock_deadline.or(deadline),
+        };
+        let (is_stderr, line) = match until {
+            Some(until) => {
+                match lines.recv_timeout(until.saturating_duration_since(Instant::now())) {
+                    Ok(line) => line,
+                    Err(RecvTimeoutError::Timeout) => {
+                        timed_out = deadline.is_some_and(|deadline| Instant::now() >= deadline);
+                        break;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => {
+                        blocked = None;
+                        break;
+                    }
+                }
+            }
+            None => match lines.recv() {
+                Ok(line) => line,
+                Err(_) => {
+                    blocked = None;
+                    break;
+                }
+            },
+        };
+        if streaming {
+            eprintln!("{}", line);
+        }
+        output.push_str(&line);
+        output.push('\n');
+        if !is_stderr {
+            debug!(line = %line, "cargo stdout");
+            continue;
+        }
+        on_line(&line);
+        captured.push_str(&line);
+        captured.push('\n');
+        // Cargo carries on with its other output once it has the lock.
+        blocked = match (blocked, lock_wait(&line)) {
+            (Some(blocked), Some(_)) => Some(blocked),
+            (None, Some(lock)) => {
+                verbose!(
+                    0,
+                    "{}: waiting for another cargo process to release its lock on {}{}",
+                    package,
+                    lock,
+                    match lock_timeout {
+                        Some(timeout) => format!(" (for up to {}s)", timeout.as_secs()),
+                        None => String::new(),
+                    }
+                );
+                Some((Instant::now(), lock.to_owned()))
+            }
+            (_, None) => None,
+        };
+    }
+    if timed_out {
+        running.kill(&mut child);
+        let _ = child.wait();
+        show_failed_output(streaming, package, &output);
+        return Err(Error::Timeout {
+            package: package.to_owned(),
+            timeout: timeout.unwrap_or_default(),
+        });
+    }
+    if let Some((_, lock)) = blocked {
+        running.kill(&mut child);
+        let _ = child.wait();
+        show_failed_output(streaming, package, &output);
+        return Err(Error::LockTimeout {
+            lock,
+            timeout: lock_timeout.unwrap_or_default(),
+        });
+    }
+    match child.wait() {
+        Ok(_) if signals::interrupted().is_some() => Err(Error::Interrupted {
+            signal: signals::interrupted().unwrap_or_default(),
+        }),
+        Ok(status) => {
+            if !status.success() {
+                show_failed_output(streaming, package, &output);
+            }
+            Ok(Exit {
+                code: status.code(),
+                stderr: captured,
+            })
+        }
+        Err(err) => Err(Error::Other(format!(
+            "Error waiting for {:?}: {:?}",
+            command.get_program(),
+            err
+        ))),
+    }
+}
+
+/// Sends each line `reader` reads to `sender` from another thread, along with `is_stderr`.
+fn read_lines<R: Read + Send + 'static>(
+    reader: R,
+    is_stderr: bool,
+    sender: Sender<(bool, String)>,
+) {
+    thread::spawn(move || {
+        for line in BufReader::new(reader).split(b'\n') {
+            let line = match line {
+                Ok(line) => String::from_utf8_lossy(&line).into_owned(),
+                Err(_) => break,
+            };
+            if sender.send((is_stderr, line)).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Prints `output`, everything a failed command for `package` printed, unless it was streamed.
+fn show_failed_output(streaming: bool, package: &str, output: &str) {
+    if !streaming && !output.is_empty() {
+        progress::suspend(|| {
+            eprintln!("{}: cargo failed, after printing:", package);
+            eprint!("{}", output);
+        });
+    }
+}
+
+/// What cargo is waiting to lock, like `package cache`, if `line` of its stderr says it's blocked
+/// on a lock held by another cargo process.
+fn lock_wait(line: &str) -> Option<&str> {
+    let (_, lock) = line.split_once("waiting for file lock on ")?;
+    Some(lock.trim())
+}
+
+/// Stands in for cargo in tests: records the arguments of each command, and runs `respond` with
+/// them instead of the command.
+#[cfg(test)]
+pub struct Fake<F: Fn(&[String]) -> Exit> {
+    pub respond: F,
+    pub ran: ::std::cell::RefCell<Vec<Vec<String>>>,
+}
+
+#[cfg(test)]
+impl<F: Fn(&[String]) -> Exit> Fake<F> {
+    pub fn new(respond: F) -> Fake<F> {
+        Fake {
+            respond,
+            ran: Default::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl<F: Fn(&[String]) -> Exit> Runner for Fake<F> {
+    fn run(
+        &self,
+        command: &mut Command,
+        _: &str,
+        _: Option<Duration>,
+        _: Option<Duration>,
+        on_line: &dyn Fn(&str),
+    ) -> Result<Exit, Error> {
+        let args: Vec<String> = command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        let exit = (self.respond)(&args);
+        for line in exit.stderr.lines() {
+            on_line(line);
+        }
+        self.ran.borrow_mut().push(args);
+        Ok(exit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lock_wait, run_capturing};
+    use error::Error;
+    use std::process::Command;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    pub fn lock_waits() {
+        assert_eq!(
+            lock_wait("    Blocking waiting for file lock on package cache"),
+            Some("package cache")
+        );
+        assert_eq!(
+            lock_wait(
+                "\u{1b}[1m\u{1b}[36m    Blocking\u{1b}[0m waiting for file lock on build directory"
+            ),
+            Some("build directory")
+        );
+        assert_eq!(lock_wait("    Updating crates.io index"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    pub fn gives_up_waiting_for_locks() {
+        let mut blocked = Command::new("sh");
+        blocked.args([
+            "-c",
+            "echo '    Blocking waiting for file lock on package cache' >&2; exec sleep 30",
+        ]);
+        let start = Instant::now();
+        match run_capturing(
+            &mut blocked,
+            "rustfmt",
+            Some(Duration::from_secs(1)),
+            None,
+            &|_| {},
+        ) {
+            Err(Error::LockTimeout { lock, timeout }) => {
+                assert_eq!(lock, "package cache");
+                assert_eq!(timeout, Duration::from_secs(1));
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(start.elapsed() < Duration::from_secs(20));
+
+        let mut unblocked = Command::new("sh");
+        unblocked.args([
+            "-c",
+            "echo '    Blocking waiting for file lock on package cache' >&2; sleep 1; \
+             echo '    Updating crates.io index' >&2",
+        ]);
+        let exit = run_capturing(
+            &mut unblocked,
+            "rustfmt",
+            Some(Duration::from_secs(10)),
+            None,
+            &|_| {},
+        )
+        .unwrap();
+        assert!(exit.success());
+        assert!(exit.stderr.ends_with("Updating crates.io index\n"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    pub fn gives_up_after_timeouts() {
+        let mut hung = Command::new("sh");
+        hung.args([
+            "-c",
+            "echo '    Updating crates.io index' >&2; exec sleep 30",
+        ]);
+        let start = Instant::now();
+        match run_capturing(
+            &mut hung,
+            "rustfmt",
+            None,
+            Some(Duration::from_secs(1)),
+            &|_| {},
+        ) {
+            Err(Error::Timeout { package, timeout }) => {
+                assert_eq!(package, "rustfmt");
+                assert_eq!(timeout, Duration::from_secs(1));
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(start.elapsed() < Duration::from_secs(20));
+
+        let mut quick = Command::new("sh");
+        quick.args([
+            "-c",
+            "echo '    Updating crates.io index' >&2; echo 'Installed package'",
+        ]);
+        let exit = run_capturing(
+            &mut quick,
+            "rustfmt",
+            None,
+            Some(Duration::from_secs(10)),
+            &|_| {},
+        )
+        .unwrap();
+        assert!(exit.success());
+        // Its stdout is captured, but isn't stderr.
+        assert_eq!(exit.stderr, "    Updating crates.io index\n");
+    }
+}
@@ -0,0 +1,168 @@
+//! Stopping cleanly on Ctrl-C or SIGTERM: cargo, and everything it started, is stopped, and any
+//! binaries we backed up are restored before we exit with the conventional `128 + signal` code.
+//!
+//! Only the command line handles signals. Library users keep their own handlers.
+
+use std::io;
+use std::process::{Child, Command};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
+
+/// Whether `install` has been called.
+static HANDLING: AtomicBool = AtomicBool::new(false);
+/// The signal we were interrupted by, or 0.
+static INTERRUPTED: AtomicI32 = AtomicI32::new(0);
+/// The process group of the cargo we are running, or 0.
+static CHILD: AtomicI32 = AtomicI32::new(0);
+/// How many `Critical` sections we are in, during which exiting would leave things half done.
+static CRITICAL: AtomicUsize = AtomicUsize::new(0);
+
+/// Handles SIGINT and SIGTERM from now on. If nothing needs cleaning up, we exit straight away;
+/// otherwise cargo is stopped and we exit once we have cleaned up after it. A second signal exits regardless.
+#[cfg(unix)]
+pub fn install() -> io::Result<()> {
+    use signal_hook::consts::{SIGINT, SIGTERM};
+    use signal_hook::iterator::Signals;
+
+    let mut signals = Signals::new([SIGINT, SIGTERM])?;
+    HANDLING.store(true, Ordering::SeqCst);
+    ::std::thread::spawn(move || {
+        for signal in signals.forever() {
+            let again = INTERRUPTED.swap(signal, Ordering::SeqCst) != 0;
+            let child = CHILD.load(Ordering::SeqCst);
+            if again || (child == 0 && CRITICAL.load(Ordering::SeqCst) == 0) {
+                ::std::process::exit(exit_code(signal));
+            }
+            if child != 0 {
+                stop_group(child, signal);
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn install() -> io::Result<()> {
+    Ok(())
+}
+
+/// The signal we were interrupted by, if we were.
+pub fn interrupted() -> Option<i32> {
+    match INTERRUPTED.load(Ordering::SeqCst) {
+        0 => None,
+        signal => Some(signal),
+    }
+}
+
+/// What to exit with after being interrupted by `signal`.
+pub fn exit_code(signal: i32) -> i32 {
+    128 + signal
+}
+
+/// The name of `signal`, for messages.
+pub fn name(signal: i32) -> String {
+    match signal {
+        2 => "SIGINT".to_owned(),
+        15 => "SIGTERM".to_owned(),
+        _ => format!("signal {}", signal),
+    }
+}
+
+/// While one of these is alive, a signal stops cargo and lets us clean up rather than exiting.
+pub struct Critical(());
+
+pub fn critical() -> Critical {
+    CRITICAL.fetch_add(1, Ordering::SeqCst);
+    Critical(())
+}
+
+impl Drop for Critical {
+    fn drop(&mut self) {
+        CRITICAL.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A running child, which a signal stops along with everything it started until this is dropped.
+pub struct Running {
+    /// Its process group, if it has one of its own.
+    group: Option<i32>,
+}
+
+impl Running {
+    /// Kills `child`, and everything it started if it has a process group of its own.
+    pub fn kill(&self, child: &mut Child) {
+        match self.group {
+            #[cfg(unix)]
+            Some(group) => stop_group(group, libc::SIGKILL),
+            _ => {
+                let _ = child.kill();
+            }
+        }
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        if self.group.is_some() {
+            CHILD.store(0, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Spawns `command`, in a process group of its own if we are handling signals, so that the
+/// compilers and build scripts it starts can be stopped with it.
+pub fn spawn(command: &mut Command) -> io::Result<(Child, Running)> {
+    if !HANDLING.load(Ordering::SeqCst) {
+        return Ok((command.spawn()?, Running { group: None }));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    let child = command.spawn()?;
+    let group = child.id() as i32;
+    CHILD.store(group, Ordering::SeqCst);
+    // A signal which came while it was starting didn't know to stop it.
+    if let Some(signal) = interrupted() {
+        stop_group(group, signal);
+    }
+    Ok((child, Running { group: Some(group) }))
+}
+
+/// Sends `signal` to every process in the process group `group`.
+#[cfg(unix)]
+fn stop_group(group: i32, signal: i32) {
+    unsafe {
+        libc::kill(-group, signal);
+    }
+}
+
+#[cfg(not(unix))]
+fn stop_group(_group: i32, _signal: i32) {}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::{exit_code, name, stop_group};
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    pub fn stops_process_groups() {
+        // The shell waits for its own child, which must be stopped too for it to exit.
+        let mut child = Command::new("sh")
+            .args(["-c", "sleep 30 & wait"])
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        let started = Instant::now();
+        stop_group(child.id() as i32, libc::SIGTERM);
+        let status = child.wait().unwrap();
+        assert!(!status.success());
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        assert_eq!(exit_code(libc::SIGINT), 130);
+        assert_eq!(name(libc::SIGTERM), "SIGTERM");
+    }
+}
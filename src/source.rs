@@ -0,0 +1,387 @@
+//! Where packages are installed from.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Where a package is installed from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    /// The default registry (crates.io).
+    Registry,
+    /// A registry configured in cargo's `[registries]` config, installed with `--registry`.
+    NamedRegistry(String),
+    /// A registry identified by its index URL, installed with `--index`.
+    Index(String),
+    Git {
+        url: String,
+        reference: GitReference,
+    },
+    /// A crate in a local directory.
+    Path(PathBuf),
+}
+
+/// Which commit of a git repository to install.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GitReference {
+    DefaultBranch,
+    Branch(String),
+    Tag(String),
+    Rev(String),
+}
+
+impl Source {
+    /// Builds a git source from optional `branch`, `tag` and `rev`, at most one of which may be set.
+    pub fn git(
+        url: &str,
+        branch: Option<&str>,
+        tag: Option<&str>,
+        rev: Option<&str>,
+    ) -> Result<Source, String> {
+        let reference = match (branch, tag, rev) {
+            (None, None, None) => GitReference::DefaultBranch,
+            (Some(branch), None, None) => GitReference::Branch(branch.to_owned()),
+            (None, Some(tag), None) => GitReference::Tag(tag.to_owned()),
+            (None, None, Some(rev)) => GitReference::Rev(rev.to_owned()),
+            _ => return Err("Only one of branch, tag and rev may be specified".to_owned()),
+        };
+        Ok(Source::Git {
+            url: url.to_owned(),
+            reference,
+        })
+    }
+
+    /// The arguments selecting this source for `cargo install`.
+    pub fn install_args(&self) -> Vec<String> {
+        match *self {
+            Source::Registry => vec![],
+            Source::NamedRegistry(ref name) => vec!["--registry".to_owned(), name.clone()],
+            Source::Index(ref url) => vec!["--index".to_owned(), url.clone()],
+            Source::Git {
+                ref url,
+                ref reference,
+            } => {
+                let mut args = vec!["--git".to_owned(), url.clone()];
+                match *reference {
+                    GitReference::DefaultBranch => {}
+                    GitReference::Branch(ref branch) => {
+                        args.push("--branch".to_owned());
+                        args.push(branch.clone());
+                    }
+                    GitReference::Tag(ref tag) => {
+                        args.push("--tag".to_owned());
+                        args.push(tag.clone());
+                    }
+                    GitReference::Rev(ref rev) => {
+                        args.push("--rev".to_owned());
+                        args.push(rev.clone());
+                    }
+                }
+                args
+            }
+            Source::Path(ref path) => vec!["--path".to_owned(), path.display().to_string()],
+        }
+    }
+
+    /// Whether a package recorded in `.crates.toml` with `installed` as the source part of its key
+    /// (e.g. `git+https://github.com/a/b?branch=main#0123abc`) came from this source.
+    ///
+    /// Requirements on the default registry accept any source. A named registry whose index isn't
+    /// known accepts any registry other than crates.io; see `resolve` to look the index up.
+    /// Branches are matched by name, so a branch which has moved on since it was installed still
+    /// matches.
+    pub fn matches_installed(&self, installed: &str) -> bool {
+        let (url, reference) = match *self {
+            Source::Registry => return true,
+            Source::NamedRegistry(_) => {
+                return registry_index(installed).is_some_and(|index| !is_crates_io(index))
+            }
+            Source::Index(ref url) => {
+                return match registry_index(installed) {
+                    Some(index) => normalize_index_url(index) == normalize_index_url(url),
+                    None => false,
+                }
+            }
+            Source::Git {
+                ref url,
+                ref reference,
+            } => (url, reference),
+            Source::Path(ref path) => {
+                return match installed.strip_prefix("path+file://") {
+                    Some(installed) => same_path(Path::new(installed), path),
+                    None => false,
+                }
+            }
+        };
+        let installed = match installed.strip_prefix("git+") {
+            Some(installed) => installed,
+            None => return false,
+        };
+        let (installed, commit) = match installed.find('#') {
+            Some(index) => (&installed[..index], &installed[index + 1..]),
+            None => (installed, ""),
+        };
+        let (installed_url, query) = match installed.find('?') {
+            Some(index) => (&installed[..index], &installed[index + 1..]),
+            None => (installed, ""),
+        };
+        if normalize_git_url(installed_url) != normalize_git_url(url) {
+            return false;
+        }
+        match *reference {
+            GitReference::DefaultBranch => query.is_empty(),
+            GitReference::Branch(ref branch) => query == format!("branch={}", branch),
+            GitReference::Tag(ref tag) => query == format!("tag={}", tag),
+            GitReference::Rev(ref rev) => {
+                query == format!("rev={}", rev) || (!rev.is_empty() && commit.starts_with(&**rev))
+            }
+        }
+    }
+}
+
+impl GitReference {
+    /// The refs `git ls-remote` should list to find the commit this refers to, or `None` for a rev,
+    /// which names the commit itself.
+    fn remote_refs(&self) -> Option<Vec<String>> {
+        match *self {
+            GitReference::DefaultBranch => Some(vec!["HEAD".to_owned()]),
+            GitReference::Branch(ref branch) => Some(vec![format!("refs/heads/{}", branch)]),
+            // An annotated tag is listed twice: as itself, and peeled to the commit it tags.
+            GitReference::Tag(ref tag) => Some(vec![
+                format!("refs/tags/{}", tag),
+                format!("refs/tags/{}^{{}}", tag),
+            ]),
+            GitReference::Rev(_) => None,
+        }
+    }
+}
+
+/// The commit which the branch, tag or default branch of the git repository at `url` now points to,
+/// from `git ls-remote`. Returns `None` for a rev, or if the reference doesn't exist.
+pub fn remote_commit(url: &str, reference: &GitReference) -> Result<Option<String>, String> {
+    let refs = match reference.remote_refs() {
+        Some(refs) => refs,
+        None => return Ok(None),
+    };
+    let output = match Command::new("git")
+        .arg("ls-remote")
+        .arg(url)
+        .args(&refs)
+        .output()
+    {
+        Ok(output) => output,
+        Err(err) => return Err(format!("Error running git ls-remote {}: {:?}", url, err)),
+    };
+    if !output.status.success() {
+        return Err(format!(
+            "Error running git ls-remote {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(parse_ls_remote(
+        &String::from_utf8_lossy(&output.stdout),
+        &refs,
+    ))
+}
+
+/// The commit in `git ls-remote` output listing `refs`, preferring the last of them, so that a
+/// peeled tag wins.
+fn parse_ls_remote(output: &str, refs: &[String]) -> Option<String> {
+    let listed: Vec<(&str, &str)> = output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split('\t');
+            Some((parts.next()?, parts.next()?))
+        })
+        .collect();
+    refs.iter().rev().find_map(|wanted| {
+        listed
+            .iter()
+            .find(|&&(_, name)| name == wanted)
+            .map(|&(commit, _)| commit.to_owned())
+    })
+}
+
+/// The commit recorded after the `#` of a git source in `.crates.toml`, if there is one.
+pub fn installed_commit(installed: &str) -> Option<&str> {
+    if !installed.starts_with("git+") {
+        return None;
+    }
+    installed
+        .rfind('#')
+        .map(|index| &installed[index + 1..])
+        .filter(|commit| !commit.is_empty())
+}
+
+/// The index URL of a `registry+` or `sparse+` source recorded in `.crates.toml`.
+fn registry_index(installed: &str) -> Option<&str> {
+    installed
+        .strip_prefix("registry+")
+        .or_else(|| installed.strip_prefix("sparse+"))
+}
+
+fn is_crates_io(index: &str) -> bool {
+    let index = normalize_index_url(index);
+    index == "https://github.com/rust-lang/crates.io-index" || index == "https://index.crates.io"
+}
+
+/// Ignores the `sparse+` prefix, which may or may not be given, and trailing slashes.
+fn normalize_index_url(url: &str) -> &str {
+    url.strip_prefix("sparse+")
+        .unwrap_or(url)
+        .trim_end_matches('/')
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Source::Registry => write!(f, "crates.io"),
+            Source::NamedRegistry(ref name) => write!(f, "registry {}", name),
+            Source::Index(ref url) => write!(f, "{}", url),
+            Source::Git {
+                ref url,
+                ref reference,
+            } => match *reference {
+                GitReference::DefaultBranch => write!(f, "{}", url),
+                GitReference::Branch(ref branch) => write!(f, "{} (branch {})", url, branch),
+                GitReference::Tag(ref tag) => write!(f, "{} (tag {})", url, tag),
+                GitReference::Rev(ref rev) => write!(f, "{} (rev {})", url, rev),
+            },
+            Source::Path(ref path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Ignores differences between URLs for the same repository which cargo may record.
+fn normalize_git_url(url: &str) -> String {
+    let url = url.trim_end_matches('/');
+    url.strip_suffix(".git").unwrap_or(url).to_lowercase()
+}
+
+fn same_path(a: &Path, b: &Path) -> bool {
+    let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_owned());
+    canonical(a) == canonical(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{installed_commit, parse_ls_remote, GitReference, Source};
+    use std::path::PathBuf;
+
+    const URL: &str = "https://github.com/pingcap/grpc-rs";
+
+    fn git(reference: GitReference) -> Source {
+        Source::Git {
+            url: URL.to_owned(),
+            reference,
+        }
+    }
+
+    #[test]
+    pub fn install_args() {
+        assert!(Source::Registry.install_args().is_empty());
+        assert_eq!(
+            git(GitReference::Tag("v0.2.0".to_owned())).install_args(),
+            vec!["--git", URL, "--tag", "v0.2.0"]
+        );
+        assert_eq!(
+            git(GitReference::DefaultBranch).install_args(),
+            vec!["--git", URL]
+        );
+    }
+
+    #[test]
+    pub fn only_one_reference() {
+        assert_eq!(
+            Source::git(URL, Some("main"), None, None).unwrap(),
+            git(GitReference::Branch("main".to_owned()))
+        );
+        assert!(Source::git(URL, Some("main"), Some("v1"), None).is_err());
+    }
+
+    #[test]
+    pub fn matches_installed() {
+        let registry = "registry+https://github.com/rust-lang/crates.io-index";
+        assert!(Source::Registry.matches_installed(registry));
+
+        let default = git(GitReference::DefaultBranch);
+        assert!(default.matches_installed("git+https://github.com/pingcap/grpc-rs.git#ccc979"));
+        assert!(!default.matches_installed("git+https://github.com/pingcap/grpc-rs?tag=v1#ccc979"));
+        assert!(!default.matches_installed("git+https://github.com/other/grpc-rs#ccc979"));
+        assert!(!default.matches_installed(registry));
+
+        let branch = git(GitReference::Branch("main".to_owned()));
+        assert!(
+            branch.matches_installed("git+https://github.com/pingcap/grpc-rs?branch=main#ccc979")
+        );
+        assert!(
+            !branch.matches_installed("git+https://github.com/pingcap/grpc-rs?branch=dev#ccc979")
+        );
+
+        let tag = git(GitReference::Tag("v1".to_owned()));
+        assert!(tag.matches_installed("git+https://github.com/pingcap/grpc-rs?tag=v1#ccc979"));
+
+        let rev = git(GitReference::Rev("ccc979".to_owned()));
+        assert!(
+            rev.matches_installed("git+https://github.com/pingcap/grpc-rs?rev=ccc979#ccc979370c")
+        );
+        assert!(rev.matches_installed("git+https://github.com/pingcap/grpc-rs#ccc979370c"));
+        assert!(!rev.matches_installed("git+https://github.com/pingcap/grpc-rs#ddd979370c"));
+    }
+
+    #[test]
+    pub fn commits() {
+        assert_eq!(
+            installed_commit("git+https://github.com/pingcap/grpc-rs?branch=main#ccc979370c"),
+            Some("ccc979370c")
+        );
+        assert_eq!(
+            installed_commit("registry+https://github.com/rust-lang/crates.io-index"),
+            None
+        );
+        let output = "1111111111111111111111111111111111111111\trefs/tags/v1\n\
+                      2222222222222222222222222222222222222222\trefs/tags/v1^{}\n";
+        let refs = GitReference::Tag("v1".to_owned()).remote_refs().unwrap();
+        assert_eq!(
+            parse_ls_remote(output, &refs),
+            Some("2222222222222222222222222222222222222222".to_owned())
+        );
+        assert_eq!(
+            parse_ls_remote(output.lines().next().unwrap(), &refs),
+            Some("1".repeat(40))
+        );
+        assert_eq!(parse_ls_remote("", &refs), None);
+        assert_eq!(GitReference::Rev("ccc979".to_owned()).remote_refs(), None);
+    }
+
+    #[test]
+    pub fn paths() {
+        let source = Source::Path(PathBuf::from("/src/tools/my-tool"));
+        assert_eq!(source.install_args(), vec!["--path", "/src/tools/my-tool"]);
+        assert!(source.matches_installed("path+file:///src/tools/my-tool"));
+        assert!(!source.matches_installed("path+file:///src/tools/other-tool"));
+        assert!(!source.matches_installed("registry+https://github.com/rust-lang/crates.io-index"));
+    }
+
+    #[test]
+    pub fn registries() {
+        let crates_io = "registry+https://github.com/rust-lang/crates.io-index";
+        let sparse_crates_io = "sparse+https://index.crates.io/";
+        let private = "sparse+https://registry.example.com/index/";
+
+        let named = Source::NamedRegistry("internal".to_owned());
+        assert_eq!(named.install_args(), vec!["--registry", "internal"]);
+        assert!(named.matches_installed(private));
+        assert!(!named.matches_installed(crates_io));
+        assert!(!named.matches_installed(sparse_crates_io));
+
+        let index = Source::Index("sparse+https://registry.example.com/index".to_owned());
+        assert!(index.matches_installed(private));
+        assert!(!index.matches_installed(crates_io));
+        let index = Source::Index("https://registry.example.com/index".to_owned());
+        assert!(index.matches_installed(private));
+        assert!(index.matches_installed("registry+https://registry.example.com/index"));
+        assert!(!index.matches_installed("git+https://registry.example.com/index#ccc979"));
+    }
+}
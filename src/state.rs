@@ -0,0 +1,448 @@
+//! Our own record of packages installed without cargo, such as from prebuilt binaries, and of how
+//! the packages cargo installed were built.
+//!
+//! Cargo doesn't know about the former, so they're kept in `.cargo-ensure-installed.json` next to
+//! `.crates.toml`, keyed in the same `"name version (source)"` form. Builds are keyed by package
+//! name.
+
+use backend::Backend;
+use crates2::InstallInfo;
+use lock;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The packages recorded in the state file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct State {
+    path: PathBuf,
+    installs: Map<String, Value>,
+    builds: Map<String, Value>,
+}
+
+/// What a package was built with when cargo installed it.
+#[derive(Debug, Default, PartialEq)]
+pub struct Build {
+    /// The version cargo resolved the requirement to.
+    pub version: Option<String>,
+    /// Where it came from, as in the source part of its `.crates.toml` key.
+    pub source: Option<String>,
+    pub backend: Option<Backend>,
+    /// When it was installed, in seconds since the Unix epoch.
+    pub installed_at: Option<u64>,
+    /// The output of `rustc -vV` for the compiler it was built with.
+    pub rustc: Option<String>,
+    /// The `RUSTFLAGS` it was built with, which is empty if there were none, and `None` if it was
+    /// recorded before we tracked them.
+    pub rustflags: Option<String>,
+    pub profile: Option<String>,
+    /// The target triple it was built for, if not the host.
+    pub target: Option<String>,
+    pub features: Vec<String>,
+    pub all_features: bool,
+    pub no_default_features: bool,
+}
+
+impl Build {
+    /// The build, in the form `.crates2.json` records it, for when cargo didn't.
+    pub fn install_info(&self, bins: Vec<String>) -> InstallInfo {
+        InstallInfo {
+            bins,
+            features: self.features.clone(),
+            all_features: self.all_features,
+            no_default_features: self.no_default_features,
+            profile: self.profile.clone(),
+            target: self.target.clone(),
+            rustc: self.rustc.clone(),
+        }
+    }
+}
+
+/// The current time, in seconds since the Unix epoch, as install times are recorded.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+/// The path of the state file, which sits next to `.crates.toml`.
+fn path_for(crates_toml: &Path) -> PathBuf {
+    crates_toml.with_file_name(".cargo-ensure-installed.json")
+}
+
+impl State {
+    /// Loads the state file next to `crates_toml`, which is empty if it doesn't exist.
+    pub fn load(crates_toml: &Path) -> Result<State, String> {
+        let path = path_for(crates_toml);
+        if !path.exists() {
+            return Ok(State {
+                path,
+                installs: Map::new(),
+                builds: Map::new(),
+            });
+        }
+        let contents = match ::read_file_to_string(&path) {
+            Ok(s) => s,
+            Err(err) => return Err(format!("Error reading {:?}: {:?}", path, err)),
+        };
+        State::parse(path, &contents)
+    }
+
+    /// Loads the state file next to `crates_toml`, lets `change` change it, and saves it if it
+    /// did, holding the install root's state lock throughout so that concurrent runs don't lose
+    /// each other's records.
+    pub fn update<R, F: FnOnce(&mut State) -> R>(
+        crates_toml: &Path,
+        change: F,
+    ) -> Result<R, String> {
+        let _lock = lock::lock_state(crates_toml)?;
+        let loaded = State::load(crates_toml)?;
+        let mut state = loaded.clone();
+        let result = change(&mut state);
+        if state != loaded {
+            state.save()?;
+        }
+        Ok(result)
+    }
+
+    pub fn parse(path: PathBuf, contents: &str) -> Result<State, String> {
+        let value: Value = match serde_json::from_str(contents) {
+            Ok(v) => v,
+            Err(err) => return Err(format!("Error parsing {:?}: {}", path, err)),
+        };
+        let installs = match value.get("installs").and_then(Value::as_object) {
+            Some(installs) => installs.clone(),
+            None => {
+                return Err(format!(
+                    "Invalid state file at {:?}: installs was not an object.",
+                    path
+                ))
+            }
+        };
+        // Older state files don't record builds.
+        let builds = match value.get("builds") {
+            Some(Value::Object(builds)) => builds.clone(),
+            Some(_) => {
+                return Err(format!(
+                    "Invalid state file at {:?}: builds was not an object.",
+                    path
+                ))
+            }
+            None => Map::new(),
+        };
+        Ok(State {
+            path,
+            installs,
+            builds,
+        })
+    }
+
+    /// Each recorded `"name version (source)"` key, with the binaries it installed.
+    pub fn installed(&self) -> Vec<(String, Vec<String>)> {
+        self.installs
+            .iter()
+            .map(|(key, install)| {
+                let bins = install
+                    .get("bins")
+                    .and_then(Value::as_array)
+                    .map(|bins| {
+                        bins.iter()
+                            .filter_map(|bin| bin.as_str().map(str::to_owned))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                (key.clone(), bins)
+            })
+            .collect()
+    }
+
+    /// Records that `key` was installed by `backend`, replacing any other version of its package
+    /// and any record of how cargo built it.
+    pub fn record(&mut self, key: &str, bins: &[String], backend: Backend) {
+        let package = key.split(' ').next().unwrap_or(key);
+        self.forget(package);
+        self.forget_build(package);
+        let mut install = Map::new();
+        install.insert(
+            "bins".to_owned(),
+            Value::Array(bins.iter().cloned().map(Value::String).collect()),
+        );
+        install.insert(
+            "backend".to_owned(),
+            Value::String(backend.name().to_owned()),
+        );
+        install.insert("installed_at".to_owned(), Value::from(now()));
+        self.installs.insert(key.to_owned(), Value::Object(install));
+    }
+
+    /// The backend which installed `package`, if we recorded it.
+    pub fn backend(&self, package: &str) -> Option<Backend> {
+        if let Some(backend) = self.build(package).and_then(|build| build.backend) {
+            return Some(backend);
+        }
+        let prefix = format!("{} ", package);
+        let (_, install) = self
+            .installs
+            .iter()
+            .find(|(key, _)| key.starts_with(&prefix))?;
+        Backend::parse(install.get("backend")?.as_str()?).ok()
+    }
+
+    /// Removes the record of `package`, returning the binaries it installed, or `None` if it
+    /// wasn't recorded.
+    pub fn forget(&mut self, package: &str) -> Option<Vec<String>> {
+        let prefix = format!("{} ", package);
+        let (key, bins) = self
+            .installed()
+            .into_iter()
+            .find(|(key, _)| key.starts_with(&prefix))?;
+        self.installs.remove(&key);
+        Some(bins)
+    }
+
+    /// How cargo built `package`, if we recorded it.
+    pub fn build(&self, package: &str) -> Option<Build> {
+        let build = self.builds.get(package)?;
+        let string = |key: &str| build.get(key).and_then(Value::as_str).map(str::to_owned);
+        let boolean = |key: &str| build.get(key).and_then(Value::as_bool).unwrap_or(false);
+        Some(Build {
+            version: string("version"),
+            source: string("source"),
+            backend: string("backend").and_then(|backend| Backend::parse(&backend).ok()),
+            installed_at: build.get("installed_at").and_then(Value::as_u64),
+            rustc: string("rustc"),
+            rustflags: string("rustflags"),
+            profile: string("profile"),
+            target: string("target"),
+            features: build
+                .get("features")
+                .and_then(Value::as_array)
+                .map(|features| {
+                    features
+                        .iter()
+                        .filter_map(|feature| feature.as_str().map(str::to_owned))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            all_features: boolean("all_features"),
+            no_default_features: boolean("no_default_features"),
+        })
+    }
+
+    /// Records how cargo built `package`, replacing any earlier record.
+    pub fn record_build(&mut self, package: &str, build: &Build) {
+        let mut record = Map::new();
+        let strings = [
+            ("version", &build.version),
+            ("source", &build.source),
+            ("rustc", &build.rustc),
+            ("rustflags", &build.rustflags),
+            ("profile", &build.profile),
+            ("target", &build.target),
+        ];
+        for (key, value) in strings.iter() {
+            if let Some(ref value) = **value {
+                record.insert(key.to_string(), Value::String(value.clone()));
+            }
+        }
+        if let Some(backend) = build.backend {
+            record.insert(
+                "backend".to_owned(),
+                Value::String(backend.name().to_owned()),
+            );
+        }
+        if let Some(installed_at) = build.installed_at {
+            record.insert("installed_at".to_owned(), Value::from(installed_at));
+        }
+        record.insert(
+            "features".to_owned(),
+            Value::Array(build.features.iter().cloned().map(Value::String).collect()),
+        );
+        record.insert("all_features".to_owned(), Value::Bool(build.all_features));
+        record.insert(
+            "no_default_features".to_owned(),
+            Value::Bool(build.no_default_features),
+        );
+        self.builds
+            .insert(package.to_owned(), Value::Object(record));
+    }
+
+    /// The SHA-256 of each of `package`'s binaries when it was installed, if we recorded them.
+    pub fn checksums(&self, package: &str) -> Vec<(String, String)> {
+        let record = match self.record_of(package) {
+            Some(record) => record,
+            None => return vec![],
+        };
+        match record.get("sha256").and_then(Value::as_object) {
+            Some(checksums) => checksums
+                .iter()
+                .filter_map(|(bin, hash)| hash.as_str().map(|hash| (bin.clone(), hash.to_owned())))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Records `checksums`, the SHA-256 of each of `package`'s binaries, alongside its install
+    /// or build record. Does nothing if neither is recorded.
+    pub fn record_checksums(&mut self, package: &str, checksums: &[(String, String)]) {
+        let checksums = checksums
+            .iter()
+            .map(|(bin, hash)| (bin.clone(), Value::String(hash.clone())))
+            .collect();
+        let prefix = format!("{} ", package);
+        let record = match self.builds.get_mut(package) {
+            Some(record) => Some(record),
+            None => self
+                .installs
+                .iter_mut()
+                .find(|(key, _)| key.starts_with(&prefix))
+                .map(|(_, install)| install),
+        };
+        if let Some(Value::Object(record)) = record {
+            record.insert("sha256".to_owned(), Value::Object(checksums));
+        }
+    }
+
+    /// The install or build record of `package`.
+    fn record_of(&self, package: &str) -> Option<&Value> {
+        let prefix = format!("{} ", package);
+        self.builds.get(package).or_else(|| {
+            self.installs
+                .iter()
+                .find(|(key, _)| key.starts_with(&prefix))
+                .map(|(_, install)| install)
+        })
+    }
+
+    /// Removes the record of how `package` was built, returning whether there was one.
+    pub fn forget_build(&mut self, package: &str) -> bool {
+        self.builds.remove(package).is_some()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let mut root = Map::new();
+        root.insert("installs".to_owned(), Value::Object(self.installs.clone()));
+        if !self.builds.is_empty() {
+            root.insert("builds".to_owned(), Value::Object(self.builds.clone()));
+        }
+        let contents = serde_json::to_string_pretty(&Value::Object(root)).unwrap();
+        // Written beside it and renamed over it, so that nothing ever reads half of it.
+        let temp = self.path.with_file_name(format!(
+            ".cargo-ensure-installed.json.{}.tmp",
+            process::id()
+        ));
+        if let Err(err) = fs::write(&temp, contents + "\n") {
+            return Err(format!("Error writing {:?}: {:?}", temp, err));
+        }
+        match fs::rename(&temp, &self.path) {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                let _ = fs::remove_file(&temp);
+                Err(format!("Error writing {:?}: {:?}", self.path, err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Build, State};
+    use backend::Backend;
+    use std::path::PathBuf;
+    use std::thread;
+
+    const KEY: &str =
+        "cargo-nextest 0.9.67 (registry+https://github.com/rust-lang/crates.io-index)";
+
+    #[test]
+    pub fn records() {
+        let path = PathBuf::from("/path/to/.cargo-ensure-installed.json");
+        let mut state = State::parse(path.clone(), r#"{"installs": {}}"#).unwrap();
+        state.record(
+            "cargo-nextest 0.9.66 (registry+https://github.com/rust-lang/crates.io-index)",
+            &["old".to_owned()],
+            Backend::Quickinstall,
+        );
+        state.record(KEY, &["cargo-nextest".to_owned()], Backend::Quickinstall);
+        assert_eq!(
+            state.installed(),
+            vec![(KEY.to_owned(), vec!["cargo-nextest".to_owned()])]
+        );
+        assert_eq!(state.backend("cargo-nextest"), Some(Backend::Quickinstall));
+        assert_eq!(state.backend("cargo"), None);
+        assert_eq!(state.forget("cargo"), None);
+        assert_eq!(
+            state.forget("cargo-nextest"),
+            Some(vec!["cargo-nextest".to_owned()])
+        );
+        assert!(state.installed().is_empty());
+        assert!(State::parse(path, "{}").is_err());
+    }
+
+    #[test]
+    pub fn records_builds() {
+        let path = PathBuf::from("/path/to/.cargo-ensure-installed.json");
+        let mut state = State::parse(path.clone(), r#"{"installs": {}}"#).unwrap();
+        assert_eq!(state.build("rustfmt"), None);
+        let build = Build {
+            rustc: Some("rustc 1.80.0 (051478957 2024-07-21)\n".to_owned()),
+            rustflags: Some("-C target-cpu=native".to_owned()),
+            version: Some("1.7.0".to_owned()),
+            source: Some("registry+https://github.com/rust-lang/crates.io-index".to_owned()),
+            backend: Some(Backend::Source),
+            installed_at: Some(1721606400),
+            profile: Some("dev".to_owned()),
+            features: vec!["nightly".to_owned()],
+            no_default_features: true,
+            ..Build::default()
+        };
+        state.record_build("rustfmt", &build);
+        assert_eq!(state.build("rustfmt"), Some(build));
+        let checksums = vec![(
+            "rustfmt".to_owned(),
+            "9a3a45d01531a20e89ac6ae10b0b0beb0492acd7216a368aa062d1a5fecaf9cd".to_owned(),
+        )];
+        state.record_checksums("rustfmt", &checksums);
+        assert_eq!(state.checksums("rustfmt"), checksums);
+        assert!(state.forget_build("rustfmt"));
+        assert_eq!(state.checksums("rustfmt"), vec![]);
+        assert!(!state.forget_build("rustfmt"));
+        assert!(State::parse(path, r#"{"installs": {}, "builds": []}"#).is_err());
+    }
+
+    #[test]
+    pub fn updates_concurrently() {
+        let dir = std::env::temp_dir().join(format!(
+            "cargo-ensure-installed-state-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let crates_toml = dir.join(".crates.toml");
+        let threads: Vec<_> = (0..8)
+            .map(|index| {
+                let crates_toml = crates_toml.clone();
+                thread::spawn(move || {
+                    let package = format!("tool-{}", index);
+                    let bins = vec![package.clone()];
+                    State::update(&crates_toml, |state| {
+                        state.record(
+                            &format!("{} 1.0.0 (registry+https://example.com)", package),
+                            &bins,
+                            Backend::Quickinstall,
+                        )
+                    })
+                    .unwrap();
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        // None of them lost another's record.
+        assert_eq!(State::load(&crates_toml).unwrap().installed().len(), 8);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
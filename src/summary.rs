@@ -0,0 +1,348 @@
+//! Describing what an ensure did to each package, for tools to read.
+
+use backend::Backend;
+use event::{Event, InstallReason};
+use semver::Version;
+use serde_json::{self, Map, Value};
+use std::sync::Mutex;
+use std::time::Duration;
+use table::format_table;
+
+/// Why each package which was decided to be installed was, until `take_reason` asks.
+static REASONS: Mutex<Vec<(String, InstallReason)>> = Mutex::new(vec![]);
+
+/// What was done to a package.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Decision {
+    /// A satisfying version was already installed.
+    Satisfied,
+    Installed,
+    Upgraded,
+    Downgraded,
+    /// The same version was installed again, such as from a different source.
+    Reinstalled,
+    Uninstalled,
+    /// It would have been installed, but this was a dry run.
+    WouldInstall,
+    /// It would have been uninstalled, but this was a dry run.
+    WouldUninstall,
+    /// It isn't satisfied, and wasn't to be installed, because of `--frozen`.
+    Unsatisfied,
+    /// It isn't satisfied, because installing it was declined when we asked.
+    Declined,
+    Failed,
+}
+
+impl Decision {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Decision::Satisfied => "satisfied",
+            Decision::Installed => "installed",
+            Decision::Upgraded => "upgraded",
+            Decision::Downgraded => "downgraded",
+            Decision::Reinstalled => "reinstalled",
+            Decision::Uninstalled => "uninstalled",
+            Decision::WouldInstall => "would-install",
+            Decision::WouldUninstall => "would-uninstall",
+            Decision::Unsatisfied => "unsatisfied",
+            Decision::Declined => "declined",
+            Decision::Failed => "failed",
+        }
+    }
+
+    /// The decision for a package which was installed at `before`, and is now at `after`.
+    pub fn between(before: Option<&Version>, after: &Version) -> Decision {
+        match before {
+            None => Decision::Installed,
+            Some(before) if before < after => Decision::Upgraded,
+            Some(before) if before > after => Decision::Downgraded,
+            Some(_) => Decision::Reinstalled,
+        }
+    }
+}
+
+/// What happened to one package.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackageSummary {
+    pub package: String,
+    /// The requested requirement, or `None` for packages which aren't required.
+    pub requirement: Option<String>,
+    pub decision: Decision,
+    /// Why it was, or would have been, installed, if it was.
+    pub reason: Option<InstallReason>,
+    /// The version installed beforehand, if any.
+    pub before: Option<Version>,
+    /// The version installed afterwards, if any.
+    pub after: Option<Version>,
+    /// How long checking and installing it took.
+    pub duration: Duration,
+    /// How long of that was spent installing it, if it was installed.
+    pub install_duration: Option<Duration>,
+    /// The backend which installed it, if we know.
+    pub backend: Option<Backend>,
+    /// Why it failed or isn't satisfied.
+    pub error: Option<String>,
+}
+
+impl PackageSummary {
+    fn to_json(&self) -> Value {
+        let version = |version: &Option<Version>| match *version {
+            Some(ref version) => Value::String(version.to_string()),
+            None => Value::Null,
+        };
+        let mut object = Map::new();
+        object.insert("name".to_owned(), Value::String(self.package.clone()));
+        object.insert(
+            "requirement".to_owned(),
+            self.requirement.clone().map_or(Value::Null, Value::String),
+        );
+        object.insert(
+            "decision".to_owned(),
+            Value::String(self.decision.name().to_owned()),
+        );
+        object.insert(
+            "reason".to_owned(),
+            self.reason
+                .as_ref()
+                .map_or(Value::Null, |reason| Value::String(reason.to_string())),
+        );
+        object.insert("before".to_owned(), version(&self.before));
+        object.insert("after".to_owned(), version(&self.after));
+        object.insert(
+            "duration_secs".to_owned(),
+            Value::from(self.duration.as_secs_f64()),
+        );
+        object.insert(
+            "install_duration_secs".to_owned(),
+            self.install_duration
+                .map_or(Value::Null, |duration| Value::from(duration.as_secs_f64())),
+        );
+        object.insert(
+            "backend".to_owned(),
+            self.backend.map_or(Value::Null, |backend| {
+                Value::String(backend.name().to_owned())
+            }),
+        );
+        object.insert(
+            "error".to_owned(),
+            self.error.clone().map_or(Value::Null, Value::String),
+        );
+        Value::Object(object)
+    }
+}
+
+/// Remembers why packages are to be installed, as they are decided.
+pub fn event(event: &Event) {
+    if let Event::Decided {
+        ref package,
+        reason: Some(ref reason),
+        ..
+    } = *event
+    {
+        REASONS
+            .lock()
+            .unwrap()
+            .push((package.clone(), reason.clone()));
+    }
+}
+
+/// Why `package` was decided to be installed, if it was since this was last asked.
+pub fn take_reason(package: &str) -> Option<InstallReason> {
+    let mut reasons = REASONS.lock().unwrap();
+    let index = reasons.iter().position(|(name, _)| name == package)?;
+    Some(reasons.remove(index).1)
+}
+
+/// The JSON document printed for `--message-format json`: an object with a `packages` array, in
+/// the order they were ensured, and whether all of them `succeeded`.
+fn to_json(packages: &[PackageSummary]) -> Map<String, Value> {
+    let succeeded = packages.iter().all(|package| package.error.is_none());
+    let mut root = Map::new();
+    root.insert(
+        "packages".to_owned(),
+        Value::Array(packages.iter().map(PackageSummary::to_json).collect()),
+    );
+    root.insert("succeeded".to_owned(), Value::Bool(succeeded));
+    root
+}
+
+/// Formats `packages` for `--message-format json`.
+pub fn format_json(packages: &[PackageSummary]) -> String {
+    serde_json::to_string_pretty(&Value::Object(to_json(packages))).unwrap()
+}
+
+/// Formats `packages` as the last line of `--message-format json-events`: the same document as
+/// `format_json`, on one line, as a `finished` event.
+pub fn format_json_event(packages: &[PackageSummary]) -> String {
+    let mut event = to_json(packages);
+    event.insert("event".to_owned(), Value::String("finished".to_owned()));
+    Value::Object(event).to_string()
+}
+
+/// Formats `packages` as a table for people, with a row for each package, and a line totalling how
+/// many packages each decision was made for, and how long it all took.
+pub fn format_human(packages: &[PackageSummary]) -> String {
+    let version = |version: &Option<Version>| match *version {
+        Some(ref version) => version.to_string(),
+        None => "-".to_owned(),
+    };
+    let rows: Vec<_> = packages
+        .iter()
+        .map(|package| {
+            vec![
+                package.package.clone(),
+                version(&package.before),
+                package.decision.name().to_owned(),
+                version(&package.after),
+                format_duration(package.duration),
+            ]
+        })
+        .collect();
+    let mut counts: Vec<(Decision, usize)> = vec![];
+    for package in packages {
+        match counts
+            .iter_mut()
+            .find(|(decision, _)| *decision == package.decision)
+        {
+            Some((_, count)) => *count += 1,
+            None => counts.push((package.decision, 1)),
+        }
+    }
+    let counts: Vec<_> = counts
+        .iter()
+        .map(|(decision, count)| format!("{} {}", count, decision.name()))
+        .collect();
+    let duration = packages.iter().map(|package| package.duration).sum();
+    format!(
+        "{}{} packages in {}: {}\n",
+        format_table(&["PACKAGE", "BEFORE", "ACTION", "AFTER", "DURATION"], &rows),
+        packages.len(),
+        format_duration(duration),
+        counts.join(", ")
+    )
+}
+
+/// `duration` in seconds, to a tenth of a second.
+fn format_duration(duration: Duration) -> String {
+    format!("{:.1}s", duration.as_secs_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        event, format_human, format_json, format_json_event, take_reason, Decision, PackageSummary,
+    };
+    use backend::Backend;
+    use event::{Event, InstallReason};
+    use semver::{Version, VersionReq};
+    use std::time::Duration;
+
+    fn v(s: &str) -> Option<Version> {
+        Some(Version::parse(s).unwrap())
+    }
+
+    #[test]
+    pub fn decisions() {
+        let between = |before: Option<Version>, after: &str| {
+            Decision::between(before.as_ref(), &v(after).unwrap())
+        };
+        assert_eq!(between(None, "1.0.0"), Decision::Installed);
+        assert_eq!(between(v("0.9.0"), "1.0.0"), Decision::Upgraded);
+        assert_eq!(between(v("2.0.0"), "1.0.0"), Decision::Downgraded);
+        assert_eq!(between(v("1.0.0"), "1.0.0"), Decision::Reinstalled);
+    }
+
+    #[test]
+    pub fn remembers_reasons() {
+        let decided = |package: &str, reason| Event::Decided {
+            package: package.to_owned(),
+            installed: None,
+            reason,
+        };
+        event(&decided("cargo-nextest", None));
+        event(&decided("cargo-audit", Some(InstallReason::NotInstalled)));
+        assert_eq!(take_reason("cargo-nextest"), None);
+        assert_eq!(
+            take_reason("cargo-audit"),
+            Some(InstallReason::NotInstalled)
+        );
+        assert_eq!(take_reason("cargo-audit"), None);
+    }
+
+    #[test]
+    pub fn formats_json() {
+        let packages = vec![
+            PackageSummary {
+                package: "ripgrep".to_owned(),
+                requirement: Some("^14".to_owned()),
+                decision: Decision::Upgraded,
+                reason: Some(InstallReason::Unsatisfied {
+                    installed: v("13.0.0").unwrap(),
+                    version_req: VersionReq::parse("^14").unwrap(),
+                }),
+                before: v("13.0.0"),
+                after: v("14.1.0"),
+                duration: Duration::from_millis(1500),
+                install_duration: Some(Duration::from_millis(1250)),
+                backend: Some(Backend::Source),
+                error: None,
+            },
+            PackageSummary {
+                package: "cargo-deny".to_owned(),
+                requirement: Some("=99".to_owned()),
+                decision: Decision::Failed,
+                reason: None,
+                before: None,
+                after: None,
+                duration: Duration::from_millis(250),
+                install_duration: None,
+                backend: None,
+                error: Some("No published version of cargo-deny matches =99".to_owned()),
+            },
+        ];
+        assert_eq!(
+            format_json(&packages),
+            r#"{
+  "packages": [
+    {
+      "after": "14.1.0",
+      "backend": "source",
+      "before": "13.0.0",
+      "decision": "upgraded",
+      "duration_secs": 1.5,
+      "error": null,
+      "install_duration_secs": 1.25,
+      "name": "ripgrep",
+      "reason": "installed 13.0.0 does not satisfy ^14",
+      "requirement": "^14"
+    },
+    {
+      "after": null,
+      "backend": null,
+      "before": null,
+      "decision": "failed",
+      "duration_secs": 0.25,
+      "error": "No published version of cargo-deny matches =99",
+      "install_duration_secs": null,
+      "name": "cargo-deny",
+      "reason": null,
+      "requirement": "=99"
+    }
+  ],
+  "succeeded": false
+}"#
+        );
+        assert!(format_json(&[]).contains("\"succeeded\": true"));
+        assert_eq!(
+            format_human(&packages),
+            "PACKAGE     BEFORE  ACTION    AFTER   DURATION\n\
+             ripgrep     13.0.0  upgraded  14.1.0  1.5s\n\
+             cargo-deny  -       failed    -       0.2s\n\
+             2 packages in 1.8s: 1 upgraded, 1 failed\n"
+        );
+        assert_eq!(
+            format_json_event(&packages[..0]),
+            r#"{"event":"finished","packages":[],"succeeded":true}"#
+        );
+    }
+}
@@ -0,0 +1,61 @@
+/// Formats `rows` as left-aligned columns separated by two spaces, with `header` as the first row.
+pub fn format_table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (index, cell) in row.iter().enumerate() {
+            if index < widths.len() {
+                widths[index] = widths[index].max(cell.chars().count());
+            }
+        }
+    }
+
+    let mut out = String::new();
+    let header: Vec<String> = header.iter().map(|h| h.to_string()).collect();
+    for row in Some(&header).into_iter().chain(rows) {
+        let mut line = String::new();
+        for (index, cell) in row.iter().enumerate() {
+            if index > 0 {
+                line.push_str("  ");
+            }
+            line.push_str(cell);
+            if index + 1 < row.len() {
+                for _ in cell.chars().count()..widths[index] {
+                    line.push(' ');
+                }
+            }
+        }
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_table;
+
+    #[test]
+    pub fn aligns_columns() {
+        let table = format_table(
+            &["PACKAGE", "INSTALLED", "STATUS"],
+            &[
+                vec![
+                    "rustfmt".to_owned(),
+                    "0.9.0".to_owned(),
+                    "satisfied".to_owned(),
+                ],
+                vec![
+                    "cargo-deny".to_owned(),
+                    "-".to_owned(),
+                    "missing".to_owned(),
+                ],
+            ],
+        );
+        assert_eq!(
+            table,
+            "PACKAGE     INSTALLED  STATUS\n\
+             rustfmt     0.9.0      satisfied\n\
+             cargo-deny  -          missing\n"
+        );
+    }
+}
@@ -0,0 +1,150 @@
+//! TeamCity service messages, for `--output teamcity`: each package is a block, in which cargo's
+//! output is folded away, and each failure is a build problem.
+
+use event::Event;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use summary::{Decision, PackageSummary};
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+/// The package whose block is open, if any.
+static OPEN: Mutex<Option<String>> = Mutex::new(None);
+
+/// Writes service messages from now on.
+pub fn init() {
+    ACTIVE.store(true, Ordering::Relaxed);
+}
+
+/// Whether we are writing service messages.
+pub fn active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Opens a block when a package starts being checked, and closes it once it's done.
+pub fn event(event: &Event) {
+    if !active() {
+        return;
+    }
+    match *event {
+        Event::CheckStarted { ref package } => {
+            close();
+            human!("{}", message("blockOpened", &[("name", package)]));
+            *OPEN.lock().unwrap() = Some(package.clone());
+        }
+        Event::Decided { reason: None, .. } | Event::InstallFinished { .. } => close(),
+        _ => {}
+    }
+}
+
+/// Closes the open block, if there is one.
+fn close() {
+    if let Some(package) = OPEN.lock().unwrap().take() {
+        human!("{}", message("blockClosed", &[("name", &package)]));
+    }
+}
+
+/// Reports each of `packages` which failed, or wasn't satisfied, as a build problem.
+pub fn report(packages: &[PackageSummary]) {
+    if !active() {
+        return;
+    }
+    close();
+    for problem in problems(packages) {
+        human!("{}", problem);
+    }
+}
+
+/// A `buildProblem` for each package which failed or wasn't satisfied, identified by its name so
+/// that TeamCity can tell when the same package fails again.
+fn problems(packages: &[PackageSummary]) -> Vec<String> {
+    packages
+        .iter()
+        .filter(|package| matches!(package.decision, Decision::Failed | Decision::Unsatisfied))
+        .map(|package| {
+            let description = format!(
+                "{}: {}",
+                package.package,
+                package.error.as_deref().unwrap_or(package.decision.name())
+            );
+            // Identities may be at most 60 characters.
+            let identity: String = format!("cargo-ensure-installed {}", package.package)
+                .chars()
+                .take(60)
+                .collect();
+            message(
+                "buildProblem",
+                &[("description", &description), ("identity", &identity)],
+            )
+        })
+        .collect()
+}
+
+/// A service message called `name`, with `attributes`.
+fn message(name: &str, attributes: &[(&str, &str)]) -> String {
+    let mut message = format!("##teamcity[{}", name);
+    for (key, value) in attributes {
+        message.push_str(&format!(" {}='{}'", key, escape(value)));
+    }
+    message.push(']');
+    message
+}
+
+/// `value` escaped for a service message's attribute.
+fn escape(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '|' => escaped.push_str("||"),
+            '\'' => escaped.push_str("|'"),
+            '\n' => escaped.push_str("|n"),
+            '\r' => escaped.push_str("|r"),
+            '[' => escaped.push_str("|["),
+            ']' => escaped.push_str("|]"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{message, problems};
+    use std::time::Duration;
+    use summary::{Decision, PackageSummary};
+
+    #[test]
+    pub fn formats_messages() {
+        assert_eq!(
+            message("blockOpened", &[("name", "ripgrep")]),
+            "##teamcity[blockOpened name='ripgrep']"
+        );
+        let failed = |package: &str, decision, error: &str| PackageSummary {
+            package: package.to_owned(),
+            requirement: Some("^14".to_owned()),
+            decision,
+            reason: None,
+            before: None,
+            after: None,
+            duration: Duration::default(),
+            install_duration: None,
+            backend: None,
+            error: Some(error.to_owned()),
+        };
+        let mut satisfied = failed("rustfmt", Decision::Satisfied, "");
+        satisfied.error = None;
+        assert_eq!(
+            problems(&[
+                satisfied,
+                failed(
+                    "ripgrep",
+                    Decision::Failed,
+                    "can't open 'Cargo.toml' [os error 2]\n"
+                ),
+            ]),
+            [
+                "##teamcity[buildProblem description='ripgrep: can|'t open |'Cargo.toml|' |[os \
+                 error 2|]|n' identity='cargo-ensure-installed ripgrep']"
+            ]
+        );
+    }
+}
@@ -0,0 +1,230 @@
+//! How long each package took to check and to install, for `--timings`, which writes a breakdown
+//! like cargo's own `--timings` does.
+
+use event::Event;
+use serde_json::{self, Map, Value};
+use std::cmp::Reverse;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use summary::PackageSummary;
+
+static INSTALLS: Mutex<Installs> = Mutex::new(Installs {
+    started: None,
+    finished: vec![],
+});
+
+struct Installs {
+    /// The package being installed, and when it started.
+    started: Option<(String, Instant)>,
+    /// How long each package which has finished installing took, until `take_install_duration`
+    /// asks.
+    finished: Vec<(String, Duration)>,
+}
+
+/// Times installs, from when they start to when they finish.
+pub fn event(event: &Event) {
+    let mut installs = INSTALLS.lock().unwrap();
+    match *event {
+        Event::InstallStarted { ref package, .. } => {
+            installs.started = Some((package.clone(), Instant::now()));
+        }
+        Event::InstallFinished { ref package, .. } => {
+            if let Some((started_package, started)) = installs.started.take() {
+                if started_package == *package {
+                    installs.finished.push((started_package, started.elapsed()));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// How long installing `package` took, if it was installed since this was last asked.
+pub fn take_install_duration(package: &str) -> Option<Duration> {
+    let mut installs = INSTALLS.lock().unwrap();
+    let index = installs
+        .finished
+        .iter()
+        .position(|(name, _)| name == package)?;
+    Some(installs.finished.remove(index).1)
+}
+
+/// How long checking `package` took: whatever of its time wasn't spent installing it.
+fn check_duration(package: &PackageSummary) -> Duration {
+    package
+        .duration
+        .saturating_sub(package.install_duration.unwrap_or_default())
+}
+
+/// The breakdown as JSON: for each package, in the order they were ensured, when it started
+/// relative to the first, and how long checking and installing it took.
+pub fn format_json(packages: &[PackageSummary]) -> String {
+    let mut started = Duration::default();
+    let packages: Vec<_> = packages
+        .iter()
+        .map(|package| {
+            let mut object = Map::new();
+            object.insert("name".to_owned(), Value::String(package.package.clone()));
+            object.insert(
+                "decision".to_owned(),
+                Value::String(package.decision.name().to_owned()),
+            );
+            object.insert("start_secs".to_owned(), Value::from(started.as_secs_f64()));
+            object.insert(
+                "check_secs".to_owned(),
+                Value::from(check_duration(package).as_secs_f64()),
+            );
+            object.insert(
+                "install_secs".to_owned(),
+                Value::from(package.install_duration.unwrap_or_default().as_secs_f64()),
+            );
+            object.insert(
+                "total_secs".to_owned(),
+                Value::from(package.duration.as_secs_f64()),
+            );
+            started += package.duration;
+            Value::Object(object)
+        })
+        .collect();
+    let mut root = Map::new();
+    root.insert("packages".to_owned(), Value::Array(packages));
+    root.insert("total_secs".to_owned(), Value::from(started.as_secs_f64()));
+    serde_json::to_string_pretty(&Value::Object(root)).unwrap()
+}
+
+/// The breakdown as a page of HTML, with a row for each package, slowest first, and a bar showing
+/// how long checking and installing it took.
+pub fn format_html(packages: &[PackageSummary]) -> String {
+    let total: Duration = packages.iter().map(|package| package.duration).sum();
+    let longest = packages
+        .iter()
+        .map(|package| package.duration)
+        .max()
+        .unwrap_or_default()
+        .as_secs_f64()
+        .max(f64::EPSILON);
+    let mut sorted: Vec<_> = packages.iter().collect();
+    sorted.sort_by_key(|package| Reverse(package.duration));
+    let mut rows = String::new();
+    for package in sorted {
+        let check = check_duration(package).as_secs_f64();
+        let install = package.install_duration.unwrap_or_default().as_secs_f64();
+        rows.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{:.1}s</td><td>{:.1}s</td><td>{:.1}s</td>\
+             <td class=\"bar\"><span class=\"check\" style=\"width: {:.1}%\"></span>\
+             <span class=\"install\" style=\"width: {:.1}%\"></span></td></tr>\n",
+            escape(&package.package),
+            package.decision.name(),
+            check,
+            install,
+            package.duration.as_secs_f64(),
+            check / longest * 100.0,
+            install / longest * 100.0
+        ));
+    }
+    format!(
+        "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>cargo ensure-installed timings</title>
+<style>
+body {{ font-family: sans-serif; }}
+table {{ border-collapse: collapse; width: 100%; }}
+td, th {{ padding: 2px 8px; text-align: left; white-space: nowrap; }}
+td.bar {{ width: 60%; }}
+td.bar span {{ display: inline-block; height: 1em; }}
+.check {{ background: #95cce8; }}
+.install {{ background: #f0b05c; }}
+</style>
+</head>
+<body>
+<h1>cargo ensure-installed timings</h1>
+<p>{} packages in {:.1}s. <span class=\"check\">&nbsp;&nbsp;</span> checking \
+<span class=\"install\">&nbsp;&nbsp;</span> installing</p>
+<table>
+<tr><th>Package</th><th>Action</th><th>Check</th><th>Install</th><th>Total</th><th></th></tr>
+{}</table>
+</body>
+</html>
+",
+        packages.len(),
+        total.as_secs_f64(),
+        rows
+    )
+}
+
+/// `text` escaped for HTML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_html, format_json};
+    use std::time::Duration;
+    use summary::{Decision, PackageSummary};
+
+    fn packages() -> Vec<PackageSummary> {
+        let package = |name: &str, decision, millis, install_millis: Option<u64>| PackageSummary {
+            package: name.to_owned(),
+            requirement: Some("*".to_owned()),
+            decision,
+            reason: None,
+            before: None,
+            after: None,
+            duration: Duration::from_millis(millis),
+            install_duration: install_millis.map(Duration::from_millis),
+            backend: None,
+            error: None,
+        };
+        vec![
+            package("rustfmt", Decision::Satisfied, 250, None),
+            package("ripgrep", Decision::Installed, 2000, Some(1500)),
+        ]
+    }
+
+    #[test]
+    pub fn formats_json() {
+        assert_eq!(
+            format_json(&packages()),
+            r#"{
+  "packages": [
+    {
+      "check_secs": 0.25,
+      "decision": "satisfied",
+      "install_secs": 0.0,
+      "name": "rustfmt",
+      "start_secs": 0.0,
+      "total_secs": 0.25
+    },
+    {
+      "check_secs": 0.5,
+      "decision": "installed",
+      "install_secs": 1.5,
+      "name": "ripgrep",
+      "start_secs": 0.25,
+      "total_secs": 2.0
+    }
+  ],
+  "total_secs": 2.25
+}"#
+        );
+    }
+
+    #[test]
+    pub fn formats_html() {
+        let html = format_html(&packages());
+        assert!(html.contains("<p>2 packages in 2.2s."), "{}", html);
+        // The slowest package comes first, with the longest bar.
+        let ripgrep = html.find("<tr><td>ripgrep</td><td>installed</td><td>0.5s</td>");
+        let rustfmt = html.find("<tr><td>rustfmt</td>");
+        assert!(ripgrep.unwrap() < rustfmt.unwrap());
+        assert!(html.contains(
+            "<span class=\"check\" style=\"width: 25.0%\"></span>\
+             <span class=\"install\" style=\"width: 75.0%\"></span>"
+        ));
+    }
+}
@@ -0,0 +1,159 @@
+use manifest::parse_tools_table;
+use requirement::{merge, Requirement};
+use std::path::{Path, PathBuf};
+use toml::Value;
+
+/// Finds the tools listed in Cargo metadata for the project containing `start`.
+///
+/// The nearest `Cargo.toml` at or above `start` may list tools in
+/// `[package.metadata.ensure-installed]`. The workspace root (which may be the same file) may list
+/// tools in `[workspace.metadata.ensure-installed]`. When both list the same tool, the package entry
+/// wins.
+///
+/// Returns the directory of the workspace root (or of the package if it isn't in a workspace)
+/// alongside the requirements, or `Ok(None)` if no `Cargo.toml` could be found.
+pub fn load_cargo_requirements(
+    start: &Path,
+) -> Result<Option<(PathBuf, Vec<Requirement>)>, String> {
+    let mut manifests = start
+        .ancestors()
+        .map(|dir| dir.join("Cargo.toml"))
+        .filter(|path| path.exists());
+
+    let nearest_path = match manifests.next() {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let nearest = read_cargo_toml(&nearest_path)?;
+
+    let mut root_path = nearest_path.clone();
+    let mut requirements = match nearest.get("workspace") {
+        Some(workspace) => workspace_requirements(&nearest_path, workspace)?,
+        None => {
+            let mut requirements = vec![];
+            for path in manifests {
+                let value = read_cargo_toml(&path)?;
+                if let Some(workspace) = value.get("workspace") {
+                    requirements = workspace_requirements(&path, workspace)?;
+                    root_path = path;
+                    break;
+                }
+            }
+            requirements
+        }
+    };
+
+    if let Some(package) = nearest.get("package") {
+        let package_requirements = metadata_requirements(&nearest_path, "package", package)?;
+        merge(&mut requirements, package_requirements);
+    }
+    let root_dir = root_path.parent().unwrap_or(start).to_owned();
+    Ok(Some((root_dir, requirements)))
+}
+
+pub fn read_cargo_toml(path: &Path) -> Result<Value, String> {
+    let contents = match ::read_file_to_string(path) {
+        Ok(s) => s,
+        Err(err) => return Err(format!("Error reading {:?}: {:?}", path, err)),
+    };
+    match contents.parse::<Value>() {
+        Ok(v) => Ok(v),
+        Err(err) => Err(format!("Error parsing {:?}: {:?}", path, err)),
+    }
+}
+
+fn workspace_requirements(path: &Path, workspace: &Value) -> Result<Vec<Requirement>, String> {
+    metadata_requirements(path, "workspace", workspace)
+}
+
+fn metadata_requirements(
+    path: &Path,
+    section: &str,
+    value: &Value,
+) -> Result<Vec<Requirement>, String> {
+    match value
+        .get("metadata")
+        .and_then(|metadata| metadata.get("ensure-installed"))
+    {
+        Some(tools) => parse_tools_table(
+            path,
+            &format!("{}.metadata.ensure-installed", section),
+            tools,
+        ),
+        None => Ok(vec![]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{metadata_requirements, workspace_requirements};
+    use requirement::merge;
+    use std::path::PathBuf;
+    use toml::Value;
+
+    fn parse(contents: &str) -> Value {
+        contents.parse::<Value>().unwrap()
+    }
+
+    #[test]
+    pub fn metadata_table() {
+        let value = parse(
+            r###"[workspace]
+members = ["a"]
+
+[workspace.metadata.ensure-installed]
+rustfmt = "0.9"
+cargo-deny = { version = "0.14" }"###,
+        );
+        let requirements = workspace_requirements(
+            &PathBuf::from("/path/to/Cargo.toml"),
+            value.get("workspace").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(requirements.len(), 2);
+        assert_eq!(requirements[0].package, "cargo-deny");
+        assert_eq!(requirements[1].package, "rustfmt");
+    }
+
+    #[test]
+    pub fn no_metadata() {
+        let value = parse("[workspace]\nmembers = [\"a\"]");
+        let requirements = workspace_requirements(
+            &PathBuf::from("/path/to/Cargo.toml"),
+            value.get("workspace").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(requirements.len(), 0);
+    }
+
+    #[test]
+    pub fn package_overrides_workspace() {
+        let value = parse(
+            r###"[package]
+name = "a"
+
+[package.metadata.ensure-installed]
+rustfmt = "1.4"
+cargo-audit = "0.18"
+
+[workspace.metadata.ensure-installed]
+rustfmt = "0.9"
+cargo-deny = "0.14""###,
+        );
+        let path = PathBuf::from("/path/to/Cargo.toml");
+        let mut requirements =
+            workspace_requirements(&path, value.get("workspace").unwrap()).unwrap();
+        merge(
+            &mut requirements,
+            metadata_requirements(&path, "package", value.get("package").unwrap()).unwrap(),
+        );
+        let summary: Vec<_> = requirements
+            .iter()
+            .map(|r| format!("{}@{}", r.package, r.raw_version))
+            .collect();
+        assert_eq!(
+            summary,
+            vec!["cargo-deny@0.14", "rustfmt@1.4", "cargo-audit@0.18"]
+        );
+    }
+}